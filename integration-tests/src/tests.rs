@@ -582,3 +582,18 @@ fn deploy_with_output_overwrites_files_but_does_not_delete_the_directory() {
     assert_file_contains( cwd.join( "custom-output" ).join( "test1.txt" ), "C" );
     assert_file_exists( cwd.join( "custom-output" ).join( "test2.txt" ) );
 }
+
+#[test]
+fn deploy_pwa() {
+    let cwd = crate_path( "static-files" );
+    run( &cwd, &*CARGO_WEB, &["deploy", "--target", "wasm32-unknown-unknown", "--pwa"] ).assert_success();
+
+    assert_file_contains( cwd.join( "target/deploy/manifest.webmanifest" ), "\"name\": \"static-files\"" );
+    assert_file_contains( cwd.join( "target/deploy/index.html" ), "manifest.webmanifest" );
+    assert_file_contains( cwd.join( "target/deploy/index.html" ), "service-worker.js" );
+
+    let service_worker = cwd.join( "target/deploy/service-worker.js" );
+    assert_file_contains( &service_worker, "\"subdirectory/dummy file.json\"" );
+    assert_file_contains( &service_worker, "\"static-files.js\"" );
+    assert_file_contains( &service_worker, "\"manifest.webmanifest\"" );
+}
@@ -0,0 +1,121 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use libflate::gzip;
+
+use cargo_shim::MessageFormat;
+use config::BudgetConfig;
+use error::Error;
+use messages;
+use utils::read_bytes;
+
+fn gzipped_size( data: &[u8] ) -> io::Result< u64 > {
+    let mut encoder = gzip::Encoder::new( Vec::new() )?;
+    encoder.write_all( data )?;
+    let output = encoder.finish().into_result()?;
+    Ok( output.len() as u64 )
+}
+
+struct Measurement {
+    name: &'static str,
+    size: u64,
+    limit: Option< u64 >
+}
+
+impl Measurement {
+    fn is_over_budget( &self ) -> bool {
+        self.limit.map( |limit| self.size > limit ).unwrap_or( false )
+    }
+
+    fn describe( &self ) -> Option< String > {
+        let limit = self.limit?;
+        let difference = self.size as i64 - limit as i64;
+        let percent = if limit == 0 { 0.0 } else { difference as f64 * 100.0 / limit as f64 };
+        let status = if difference > 0 { "OVER BUDGET" } else { "ok" };
+        Some( format!(
+            "    {:<14} {:>10} bytes / {:>10} bytes  {:>+10} bytes ({:+.1}%)  {}",
+            self.name,
+            self.size,
+            limit,
+            difference,
+            percent,
+            status
+        ))
+    }
+}
+
+/// Checks the sizes of the `.wasm` and `.js` artifacts against the `[budget]` from `Web.toml`.
+///
+/// Returns an error if any of the limits is exceeded, unless the budget is in the warn-only mode.
+pub fn check_budget( message_format: MessageFormat, budget: &BudgetConfig, target_name: &str, artifacts: &[PathBuf] ) -> Result< (), Error > {
+    if budget.is_empty() {
+        return Ok(());
+    }
+
+    let mut wasm_size = 0;
+    let mut js_size = 0;
+    let mut gzipped_total_size = 0;
+    let mut found_any = false;
+    for path in artifacts {
+        let is_wasm = match path.extension() {
+            Some( ext ) if ext == "wasm" => true,
+            Some( ext ) if ext == "js" => false,
+            _ => continue
+        };
+
+        let contents = read_bytes( path ).map_err( |error| Error::CannotLoadFile( path.clone(), error ) )?;
+        if is_wasm {
+            wasm_size += contents.len() as u64;
+        } else {
+            js_size += contents.len() as u64;
+        }
+
+        if budget.max_gzipped_size.is_some() {
+            gzipped_total_size += gzipped_size( &contents ).map_err( |error| Error::CannotLoadFile( path.clone(), error ) )?;
+        }
+
+        found_any = true;
+    }
+
+    if !found_any {
+        return Ok(());
+    }
+
+    let measurements = [
+        Measurement { name: ".wasm", size: wasm_size, limit: budget.max_wasm_size },
+        Measurement { name: ".js", size: js_size, limit: budget.max_js_size },
+        Measurement { name: "gzipped total", size: gzipped_total_size, limit: budget.max_gzipped_size }
+    ];
+
+    if !measurements.iter().any( |measurement| measurement.is_over_budget() ) {
+        debug!( "The `{}` is within its size budget", target_name );
+        return Ok(());
+    }
+
+    let mut message = format!( "the `{}` exceeds the size budget from Web.toml:\n", target_name );
+    for line in measurements.iter().filter_map( |measurement| measurement.describe() ) {
+        message.push_str( &line );
+        message.push( '\n' );
+    }
+
+    if budget.warn_only {
+        messages::warning( message_format, None, message.trim_end() );
+        Ok(())
+    } else {
+        Err( message.trim_end().to_owned().into() )
+    }
+}
+
+#[test]
+fn test_measurement() {
+    let within = Measurement { name: ".js", size: 900, limit: Some( 1000 ) };
+    assert!( !within.is_over_budget() );
+
+    let over = Measurement { name: ".wasm", size: 1100, limit: Some( 1000 ) };
+    assert!( over.is_over_budget() );
+    assert!( over.describe().unwrap().contains( "+100 bytes (+10.0%)  OVER BUDGET" ) );
+
+    let unlimited = Measurement { name: "gzipped total", size: 1100, limit: None };
+    assert!( !unlimited.is_over_budget() );
+    assert!( unlimited.describe().is_none() );
+}
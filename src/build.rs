@@ -21,9 +21,11 @@ use serde_json;
 use walkdir::WalkDir;
 use atty::{self, Stream};
 
-use config::Config;
+use config::{BudgetConfig, Config, EnvVariable, LoaderConfig, PwaConfig, ThreadsConfig, WasmOptConfig};
 use emscripten::initialize_emscripten;
 use error::Error;
+use messages;
+use package::DownloadSettings;
 use utils::{read, find_cmd};
 use wasm;
 
@@ -139,9 +141,10 @@ pub struct BuildArgs {
 
     is_verbose: bool,
     message_format: MessageFormat,
+    skip_validation: bool,
 
     backend: Option< Backend >,
-    runtime: RuntimeKind,
+    runtime: Option< RuntimeKind >,
 
     package_name: Option< String >,
     target_name: Option< TargetName >
@@ -150,8 +153,17 @@ pub struct BuildArgs {
 pub struct AggregatedConfig {
     uses_old_stdweb: bool,
     profile: Profile,
+    // The build type after taking the old `stdweb` into account, which might not be the one that was asked for.
+    build_type: BuildType,
     pub link_args: Vec< String >,
-    pub prepend_js: Vec< (PathBuf, String) >
+    pub prepend_js: Vec< (PathBuf, String) >,
+    pub runtime: RuntimeKind,
+    pub loader: LoaderConfig,
+    pub pwa: PwaConfig,
+    pub budget: BudgetConfig,
+    pub wasm_opt: WasmOptConfig,
+    pub threads: ThreadsConfig,
+    pub env: Vec< EnvVariable >
 }
 
 impl From<super::Build> for BuildArgs {
@@ -164,8 +176,9 @@ impl From<super::Build> for BuildArgs {
             use_system_emscripten: b.use_system_emscripten,
             is_verbose: b.verbose,
             message_format: MessageFormat::Human,
+            skip_validation: b.skip_validation,
             backend: b.target,
-            runtime: RuntimeKind::Standalone,
+            runtime: None,
             package_name: b.package,
             target_name: None,
         }
@@ -180,7 +193,7 @@ impl BuildArgs {
         if let Some(rt) = ext.runtime {
             match out.backend {
                 None | Some(Backend::WebAssembly) => {
-                    out.runtime = rt;
+                    out.runtime = Some(rt);
                 }
                 Some(be) => {
                     return Err(Error::ConfigurationError(format!(
@@ -203,6 +216,16 @@ impl BuildArgs {
     pub fn load_project( &self ) -> Result< Project, Error > {
         Project::new( self.clone() )
     }
+
+    pub fn message_format( &self ) -> MessageFormat {
+        self.message_format
+    }
+}
+
+/// The result of a build along with what we've learned while processing its `.wasm` files.
+pub struct WebBuild {
+    pub result: CargoResult,
+    pub uses_shared_memory: bool
 }
 
 #[derive(Clone)]
@@ -214,6 +237,39 @@ pub struct Project {
     main_config: Option< Config >
 }
 
+pub fn find_rustup() -> Option< &'static str > {
+    find_cmd( &[ "rustup", "rustup.exe" ] )
+}
+
+/// Returns every target known to rustup along with whether it's installed.
+pub fn rustup_targets( rustup: &str ) -> Result< HashMap< String, bool >, Error > {
+    let output = Command::new( rustup )
+        .args( &[ "target", "list" ] )
+        .output()
+        .map_err( |err| Error::RuntimeError( "cannot get the target list through rustup".into(), err.into() ) )?;
+
+    if !output.status.success() {
+        return Err( "cannot get the target list through rustup: rustup invocation failed".into() );
+    }
+
+    let mut targets = HashMap::new();
+    let stdout = String::from_utf8_lossy( &output.stdout );
+    for line in stdout.trim().split( "\n" ) {
+        let target = &line[ 0..line.find( " " ).unwrap_or( line.len() ) ];
+        let is_installed = line.ends_with( "(installed)" );
+
+        trace!( "Target `{}`: {}", target, is_installed );
+        targets.insert( target.to_owned(), is_installed );
+    }
+
+    Ok( targets )
+}
+
+/// Versions of `stdweb` older than this don't support the new way of processing the `.wasm` file.
+pub fn is_old_stdweb( package: &CargoPackage ) -> bool {
+    package.name == "stdweb" && package.id.version() < Version::parse( "0.4.11" ).unwrap()
+}
+
 fn get_package< 'a >( name: Option< &str >, project: &'a CargoProject ) -> Result< usize, Error > {
     if let Some( name ) = name {
         match project.packages.iter().position( |package| package.name == name ) {
@@ -267,7 +323,7 @@ impl Project {
         let default_package = get_package( args.package_name.as_ref().map( |name| name.as_str() ), &project )?;
         let default_target = get_target( &args.target_name, &project.packages[ default_package ] )?;
 
-        let main_config = Config::load_for_package_printing_warnings( &project.packages[ default_package ], true )?;
+        let main_config = Config::load_for_package_printing_warnings( &project.packages[ default_package ], true, args.message_format )?;
 
         let project = Project {
             build_args: args,
@@ -277,7 +333,7 @@ impl Project {
             main_config
         };
 
-        if project.build_args.runtime != RuntimeKind::Standalone && !project.backend().is_native_wasm() {
+        if project.build_args.runtime.map( |runtime| runtime != RuntimeKind::Standalone ).unwrap_or( false ) && !project.backend().is_native_wasm() {
             return Err( format!( "`--runtime` can be only used with `--target=wasm32-unknown-unknown`" ).into() );
         }
 
@@ -311,7 +367,7 @@ impl Project {
         }))
     }
 
-    fn used_packages( &self, profile: Profile ) -> Vec< &CargoPackage > {
+    pub fn used_packages( &self, profile: Profile ) -> Vec< &CargoPackage > {
         let main_package = self.package();
         let mut packages = self.project.used_packages_with_rustflags(
             self.backend().triplet(),
@@ -342,29 +398,48 @@ impl Project {
 
     pub fn aggregate_configuration( &self, profile: Profile ) -> Result< AggregatedConfig, Error > {
         let main_package = self.package();
+        let packages = self.used_packages( profile );
+        let uses_old_stdweb = packages.iter().any( |package| is_old_stdweb( package ) );
+        let build_type = if self.backend().is_native_wasm() && self.build_args.build_type == BuildType::Debug && uses_old_stdweb {
+            // TODO: Remove this in the future.
+            BuildType::Release
+        } else {
+            self.build_args.build_type
+        };
+
+        let main_config_runtime = self.main_config.as_ref().and_then( |config| config.get_runtime( self.backend(), build_type ) );
         let mut aggregated_config = AggregatedConfig {
-            uses_old_stdweb: false,
+            uses_old_stdweb,
             profile,
+            build_type,
             link_args: Vec::new(),
-            prepend_js: Vec::new()
+            prepend_js: Vec::new(),
+            runtime: self.build_args.runtime.or( main_config_runtime ).unwrap_or( RuntimeKind::Standalone ),
+            loader: self.main_config.as_ref().map( |config| config.loader.clone() ).unwrap_or_default(),
+            pwa: self.main_config.as_ref().map( |config| config.pwa.clone() ).unwrap_or_default(),
+            budget: self.main_config.as_ref().map( |config| config.budget.clone() ).unwrap_or_default(),
+            wasm_opt: self.main_config.as_ref().map( |config| config.get_wasm_opt( self.backend(), build_type ) ).unwrap_or_default(),
+            threads: self.main_config.as_ref().map( |config| config.threads.clone() ).unwrap_or_default(),
+            env: match self.main_config {
+                Some( ref config ) => config.get_env( self.backend(), build_type )?,
+                None => Vec::new()
+            }
         };
 
-        let packages = self.used_packages( profile );
+        if uses_old_stdweb {
+            debug!( "Using old `stdweb`!" );
+        } else {
+            debug!( "Using new `stdweb`!" );
+        }
+
         let mut maximum_minimum_version = None;
         let mut configs = Vec::new();
 
         for package in &packages {
-            if package.name == "stdweb" && package.id.version() < Version::parse( "0.4.11" ).unwrap() {
-                debug!( "Using old `stdweb`!" );
-                aggregated_config.uses_old_stdweb = true;
-            } else {
-                debug!( "Using new `stdweb`!" );
-            }
-
             let config = if package.id == main_package.id {
                 self.main_config.clone()
             } else {
-                Config::load_for_package_printing_warnings( package, false )?
+                Config::load_for_package_printing_warnings( package, false, self.build_args.message_format )?
             };
 
             if let Some( ref config ) = config {
@@ -393,12 +468,12 @@ impl Project {
 
         for config in configs.iter().rev() {
             if let Some( ref config ) = *config {
-                if let Some( ref link_args ) = config.get_link_args( self.backend() ) {
+                if let Some( link_args ) = config.get_link_args( self.backend(), build_type ) {
                     debug!( "{} defines the following link-args: {:?}", config.source(), link_args );
-                    aggregated_config.link_args.extend( link_args.iter().cloned() );
+                    aggregated_config.link_args.extend( link_args );
                 }
 
-                if let Some( ref prepend_js ) = config.get_prepend_js( self.backend() ) {
+                if let Some( prepend_js ) = config.get_prepend_js( self.backend(), build_type ) {
                     debug!( "{} wants to prepend the following JS files: {:?}", config.source(), prepend_js );
                     let config_dir = config.config_path.as_ref().unwrap().parent().unwrap();
                     for path in prepend_js.iter() {
@@ -419,7 +494,7 @@ impl Project {
         Ok( aggregated_config )
     }
 
-    fn prepare_build_config( &self, config: &AggregatedConfig, target: &CargoTarget ) -> BuildConfig {
+    fn prepare_build_config( &self, config: &AggregatedConfig, target: &CargoTarget ) -> Result< BuildConfig, Error > {
         let package = self.package();
         let mut extra_paths = Vec::new();
         let mut extra_rustflags = Vec::new();
@@ -438,7 +513,7 @@ impl Project {
         }
 
         if self.backend().is_emscripten() {
-            if let Some( emscripten ) = initialize_emscripten( self.build_args.use_system_emscripten, self.backend().is_emscripten_wasm() ) {
+            if let Some( emscripten ) = initialize_emscripten( self.build_args.message_format, &DownloadSettings::from_env(), self.build_args.use_system_emscripten, self.backend().is_emscripten_wasm() )? {
                 extra_paths.push( emscripten.emscripten_path.clone() );
 
                 let emscripten_path = emscripten.emscripten_path.to_string_lossy().into_owned();
@@ -501,7 +576,9 @@ impl Project {
             }
         }
 
-        if self.backend().is_native_wasm() && self.build_args.build_type == BuildType::Debug && config.uses_old_stdweb {
+        let build_type = config.build_type;
+        if build_type != self.build_args.build_type {
+            messages::warning( self.build_args.message_format, None, "debug builds on the wasm32-unknown-unknown are currently totally broken; forcing a release build" );
             extra_rustflags.push( "-C".to_owned() );
             extra_rustflags.push( "debuginfo=2".to_owned() );
         }
@@ -514,16 +591,6 @@ impl Project {
             }
         }
 
-        let build_type = self.build_args.build_type;
-        let build_type = if self.backend().is_native_wasm() && build_type == BuildType::Debug && config.uses_old_stdweb {
-            // TODO: Remove this in the future.
-            eprintln!( "warning: debug builds on the wasm32-unknown-unknown are currently totally broken" );
-            eprintln!( "         forcing a release build" );
-            BuildType::Release
-        } else {
-            build_type
-        };
-
         if !extra_emmaken_cflags.is_empty() && !vanilla_emscripten_build {
             // We need to do this through EMMAKEN_CFLAGS since Rust can't handle linker args with spaces.
             // https://github.com/rust-lang/rust/issues/30947
@@ -543,7 +610,11 @@ impl Project {
         extra_environment.push( ("CARGO_WEB_TARGET_DIR".to_owned(), self.target_directory().to_str().unwrap().to_owned()) );
         extra_environment.push( ("CARGO_WEB_TARGET".to_owned(), self.backend().triplet().to_owned()) );
 
-        BuildConfig {
+        for variable in &config.env {
+            extra_environment.push( (variable.name.clone(), variable.value.clone()) );
+        }
+
+        Ok( BuildConfig {
             build_target: target_to_build_target( target, config.profile ),
             build_type,
             triplet: Some( self.backend().triplet().into() ),
@@ -557,7 +628,7 @@ impl Project {
             message_format: self.build_args.message_format,
             is_verbose: self.build_args.is_verbose,
             use_color: atty::is(Stream::Stdout) && atty::is(Stream::Stderr)
-        }
+        })
     }
 
     pub fn static_paths( package: &CargoPackage, target: &CargoTarget ) -> Vec< PathBuf > {
@@ -600,31 +671,13 @@ impl Project {
     }
 
     fn install_target_if_necessary( &self ) -> Result< (), Error > {
-        let rustup = match find_cmd( &[ "rustup", "rustup.exe" ] ) {
+        let rustup = match find_rustup() {
             Some( path ) => path,
             // If the user installed Rust not through rustup then they're on their own.
             None => return Ok(())
         };
 
-        let output = Command::new( rustup )
-            .args( &[ "target", "list" ] )
-            .output()
-            .map_err( |err| Error::RuntimeError( "cannot get the target list through rustup".into(), err.into() ) )?;
-
-        if !output.status.success() {
-            return Err( "cannot get the target list through rustup: rustup invocation failed".into() );
-        }
-
-        let mut targets = HashMap::new();
-        let stdout = String::from_utf8_lossy( &output.stdout );
-        for line in stdout.trim().split( "\n" ) {
-            let target = &line[ 0..line.find( " " ).unwrap_or( line.len() ) ];
-            let is_installed = line.ends_with( "(installed)" );
-
-            trace!( "Target `{}`: {}", target, is_installed );
-            targets.insert( target.to_owned(), is_installed );
-        }
-
+        let targets = rustup_targets( rustup )?;
         match targets.get( self.backend().triplet() ).cloned() {
             Some( false ) => {
                 debug!( "Trying to install target `{}`...", self.backend().triplet() );
@@ -658,10 +711,10 @@ impl Project {
         }
     }
 
-    fn build_or_check( &self, config: &AggregatedConfig, target: &CargoTarget, should_build: bool ) -> Result< CargoResult, Error > {
+    fn build_or_check( &self, config: &AggregatedConfig, target: &CargoTarget, should_build: bool ) -> Result< WebBuild, Error > {
         self.install_target_if_necessary()?;
 
-        let build_config = self.prepare_build_config( config, target );
+        let build_config = self.prepare_build_config( config, target )?;
         let mut prepend_js = String::new();
         if self.backend().is_native_wasm() {
             for &(_, ref contents) in &config.prepend_js {
@@ -704,6 +757,8 @@ impl Project {
         }
 
         let target_dir = self.target_directory();
+        let mut processing_error = None;
+        let mut uses_shared_memory = false;
         let result = if !should_build {
             build_config.check()
         } else {
@@ -712,6 +767,7 @@ impl Project {
                     .map( |triplet| triplet == "wasm32-unknown-unknown" )
                     .unwrap_or( false );
 
+            let message_format = build_config.message_format;
             build_config.build( Some( |artifacts: Vec< PathBuf >| {
                 let mut out = Vec::new();
                 for path in artifacts {
@@ -724,30 +780,55 @@ impl Project {
                         continue;
                     }
 
-                    if let Some( artifact ) = wasm::process_wasm_file( config.uses_old_stdweb, self.build_args.runtime, &build_config, &prepend_js, target_dir, &path ) {
-                        debug!( "Generated artifact: {:?}", artifact );
-                        out.push( artifact );
+                    match wasm::process_wasm_file( config.uses_old_stdweb, config.runtime, &config.loader, &config.wasm_opt, &config.threads, &config.env, self.build_args.skip_validation, &build_config, &prepend_js, target_dir, &path ) {
+                        Ok( processed ) => {
+                            uses_shared_memory |= processed.uses_shared_memory;
+                            for artifact in processed.artifacts {
+                                debug!( "Generated artifact: {:?}", artifact );
+                                out.push( artifact );
+                            }
+                        },
+                        Err( error ) => {
+                            if processing_error.is_none() {
+                                processing_error = Some( error );
+                            }
+                        }
                     }
 
                     out.push( path );
                 }
 
+                for path in &out {
+                    if let Some( kind ) = messages::artifact_kind( path ) {
+                        messages::artifact( message_format, kind, path );
+                    }
+                }
+
                 out
             }))
         };
 
+        if let Some( error ) = processing_error {
+            return Err( error );
+        }
+
         if result.is_ok() == false {
             return Err( Error::BuildError );
         }
 
-        Ok( result )
+        Ok( WebBuild { result, uses_shared_memory } )
     }
 
     pub fn build( &self, config: &AggregatedConfig, target: &CargoTarget ) -> Result< CargoResult, Error > {
+        self.build_or_check( config, target, true ).map( |build| build.result )
+    }
+
+    /// Same as `build`, but also returns what we've learned while processing the `.wasm` files.
+    pub fn build_for_web( &self, config: &AggregatedConfig, target: &CargoTarget ) -> Result< WebBuild, Error > {
         self.build_or_check( config, target, true )
     }
 
     pub fn check( &self, config: &AggregatedConfig, target: &CargoTarget ) -> Result< CargoResult, Error > {
-        self.build_or_check( config, target, false )
+        self.build_or_check( config, target, false ).map( |build| build.result )
     }
 }
@@ -70,7 +70,6 @@ fn level_color( level: &str ) -> Style {
 
 fn print_diagnostic< W: Write >( use_color: bool, diag: &Diagnostic, fp: &mut W ) -> fmt::Result {
     let color = level_color( diag.level.as_str() );
-    let arrow_color = Color::Blue.bold();
 
     write!( fp, "{}{}",
         MaybePrint( use_color, color.prefix() ),
@@ -83,6 +82,12 @@ fn print_diagnostic< W: Write >( use_color: bool, diag: &Diagnostic, fp: &mut W
     };
 
     writeln!( fp, "{}: {}", MaybePrint( use_color, color.suffix() ), diag.message )?;
+    print_code_frame( use_color, diag, fp )
+}
+
+fn print_code_frame< W: Write >( use_color: bool, diag: &Diagnostic, fp: &mut W ) -> fmt::Result {
+    let color = level_color( diag.level.as_str() );
+    let arrow_color = Color::Blue.bold();
     let line_number_digits = diag.spans.last().map( |span| format!( "{}", span.line_start + span.text.iter().count() ).len() ).unwrap_or( 0 );
     for span in &diag.spans {
         writeln!( fp, "{}{}-->{} {}:{}:{}",
@@ -313,6 +318,21 @@ fn simple_coloring( message: &str ) -> Result< String, fmt::Error > {
     Ok( output )
 }
 
+/// Renders a diagnostic which didn't come from rustc the same way rustc would.
+pub fn render( use_color: bool, diag: &Diagnostic ) -> String {
+    let mut output = String::new();
+    print_diagnostic( cfg!( unix ) && use_color, diag, &mut output ).unwrap();
+    output
+}
+
+/// Renders only the source locations and the children of a diagnostic,
+/// e.g. for use inside of an error message.
+pub fn render_code_frame( diag: &Diagnostic ) -> String {
+    let mut output = String::new();
+    print_code_frame( false, diag, &mut output ).unwrap();
+    output
+}
+
 pub fn print( use_color: bool, message: &Message ) {
     let diag = &message.message;
 
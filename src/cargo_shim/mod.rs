@@ -24,7 +24,10 @@ mod diagnostic_formatter;
 use self::cargo::cfg::{Cfg, CfgExpr};
 use self::cargo_output::{CargoOutput, PackageId};
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub use self::rustc_diagnostic::{Diagnostic, DiagnosticSpan, DiagnosticSpanLine};
+pub use self::diagnostic_formatter::{render as render_diagnostic, render_code_frame};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum BuildType {
     Debug,
     Release
@@ -101,7 +104,9 @@ pub struct CargoPackage {
     pub targets: Vec< CargoTarget >,
     pub dependencies: Vec< CargoDependency >,
     pub is_workspace_member: bool,
-    pub is_default: bool
+    pub is_default: bool,
+    /// The contents of the `[package.metadata]` section.
+    pub metadata: serde_json::Value
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -344,6 +349,7 @@ impl CargoProject {
                     manifest_path: manifest_path,
                     is_workspace_member,
                     is_default: false,
+                    metadata: package.metadata,
                     targets: package.targets.into_iter().filter_map( |target| {
                         Some( CargoTarget {
                             name: target.name,
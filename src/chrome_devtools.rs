@@ -373,5 +373,26 @@ pub struct ExceptionDetails {
     pub column_number: u32,
     #[serde(rename = "scriptId")]
     pub script_id: Option< String >,
-    pub url: Option< String >
+    pub url: Option< String >,
+    #[serde(rename = "stackTrace")]
+    pub stack_trace: Option< StackTrace >
+}
+
+// https://chromedevtools.github.io/devtools-protocol/tot/Runtime/#type-StackTrace
+#[derive(Clone, Deserialize, Debug)]
+pub struct StackTrace {
+    #[serde(rename = "callFrames")]
+    pub call_frames: Vec< CallFrame >
+}
+
+// https://chromedevtools.github.io/devtools-protocol/tot/Runtime/#type-CallFrame
+#[derive(Clone, Deserialize, Debug)]
+pub struct CallFrame {
+    #[serde(rename = "functionName")]
+    pub function_name: String,
+    pub url: String,
+    #[serde(rename = "lineNumber")]
+    pub line_number: u32,
+    #[serde(rename = "columnNumber")]
+    pub column_number: u32
 }
@@ -3,6 +3,7 @@ use cargo_shim::{
     TargetKind
 };
 
+use budget::check_budget;
 use build::BuildArgs;
 use error::Error;
 
@@ -16,7 +17,8 @@ pub fn command_build_or_check(build_args: BuildArgs, should_build: bool) -> Resu
     let config = project.aggregate_configuration( Profile::Main )?;
     for target in targets {
         if should_build {
-            project.build( &config, target )?;
+            let result = project.build( &config, target )?;
+            check_budget( build_args.message_format(), &config.budget, &target.name, result.artifacts() )?;
         } else {
             project.check( &config, target )?;
         }
@@ -0,0 +1,279 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::bytes::RegexBuilder;
+use regex;
+use serde_json;
+use walkdir::WalkDir;
+
+use cargo_shim::CargoProject;
+use error::Error;
+use project_dirs::PROJECT_DIRS;
+use utils::read_bytes;
+use wasm_js_snippet::Snippet;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct CleanArgs {
+    pub gc_snippets: bool,
+    pub snippets: bool,
+    pub metadata: bool,
+    pub test_runner: bool,
+    pub packages: bool,
+    pub dry_run: bool
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+struct Freed {
+    files: u64,
+    bytes: u64
+}
+
+impl Freed {
+    fn add( &mut self, other: Freed ) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+    }
+}
+
+fn measure( path: &Path ) -> Freed {
+    let mut freed = Freed::default();
+    for entry in WalkDir::new( path ).into_iter().filter_map( |entry| entry.ok() ) {
+        if entry.file_type().is_file() {
+            freed.files += 1;
+            freed.bytes += entry.metadata().map( |metadata| metadata.len() ).unwrap_or( 0 );
+        }
+    }
+
+    freed
+}
+
+fn remove( path: &Path, dry_run: bool ) -> Result< Freed, Error > {
+    let metadata = match fs::symlink_metadata( path ) {
+        Ok( metadata ) => metadata,
+        Err( _ ) => return Ok( Freed::default() )
+    };
+
+    let freed = measure( path );
+    if dry_run {
+        return Ok( freed );
+    }
+
+    debug!( "Removing {:?}...", path );
+    if metadata.is_dir() {
+        fs::remove_dir_all( path ).map_err( |error| Error::CannotRemoveDirectory( path.to_owned(), error ) )?;
+    } else {
+        fs::remove_file( path ).map_err( |error| Error::CannotRemoveFile( path.to_owned(), error ) )?;
+    }
+
+    Ok( freed )
+}
+
+fn report( description: &str, freed: Freed, dry_run: bool ) {
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    eprintln!( "    {} {}: {} file(s), {} bytes", verb, description, freed.files, freed.bytes );
+}
+
+fn snippets_directory( target_dir: &Path ) -> PathBuf {
+    target_dir.join( ".cargo-web" ).join( "snippets" )
+}
+
+/// Returns the snippet files whose functions aren't referenced by any of the build artifacts.
+///
+/// The snippets are only written when a crate which uses them is compiled, so besides
+/// the final `.wasm` files we also have to look into the `.rlib`s of every dependency;
+/// rather than parsing those we simply search for the names of the snippets.
+fn unreferenced_snippets( target_dir: &Path ) -> Result< Vec< PathBuf >, Error > {
+    let snippets_dir = snippets_directory( target_dir );
+    let mut snippets = Vec::new();
+    let mut output = Vec::new();
+    for entry in WalkDir::new( &snippets_dir ).into_iter().filter_map( |entry| entry.ok() ) {
+        if !entry.file_type().is_file() || !entry.path().extension().map( |ext| ext == "json" ).unwrap_or( false ) {
+            continue;
+        }
+
+        let blob = read_bytes( entry.path() ).map_err( |error| Error::CannotLoadFile( entry.path().to_owned(), error ) )?;
+        match serde_json::from_slice::< Snippet >( &blob ) {
+            Ok( snippet ) => snippets.push( (entry.path().to_owned(), snippet.name) ),
+            // A corrupted snippet is of no use to anyone.
+            Err( _ ) => output.push( entry.path().to_owned() )
+        }
+    }
+
+    if snippets.is_empty() {
+        return Ok( output );
+    }
+
+    // Longer names go first so that a name which is a prefix of another one can't shadow it.
+    let mut names: Vec< &str > = snippets.iter().map( |&(_, ref name)| name.as_str() ).collect();
+    names.sort_by( |a, b| b.len().cmp( &a.len() ) );
+    let names: Vec< _ > = names.into_iter().map( regex::escape ).collect();
+    let pattern = format!( r"(?-u:\b)(?:{})(?-u:\b)", names.join( "|" ) );
+    let regex = RegexBuilder::new( &pattern )
+        .size_limit( 256 * 1024 * 1024 )
+        .build()
+        .map_err( |error| format!( "cannot search for the JS snippets: {}", error ) )?;
+
+    let mut referenced = HashSet::new();
+    let build_dir = target_dir.join( "wasm32-unknown-unknown" );
+    for entry in WalkDir::new( &build_dir ).into_iter().filter_map( |entry| entry.ok() ) {
+        let is_artifact = entry.path().extension()
+            .map( |ext| ext == "wasm" || ext == "rlib" || ext == "o" || ext == "a" )
+            .unwrap_or( false );
+
+        if !entry.file_type().is_file() || !is_artifact {
+            continue;
+        }
+
+        let bytes = read_bytes( entry.path() ).map_err( |error| Error::CannotLoadFile( entry.path().to_owned(), error ) )?;
+        for name in regex.find_iter( &bytes ) {
+            referenced.insert( name.as_bytes().to_owned() );
+        }
+    }
+
+    output.extend(
+        snippets.into_iter()
+            .filter( |&(_, ref name)| !referenced.contains( name.as_bytes() ) )
+            .map( |(path, _)| path )
+    );
+
+    Ok( output )
+}
+
+/// Returns the `.cargoweb-metadata` files along with the `.js` and `.wasm` files they describe.
+///
+/// With the old stdweb the metadata is what tells us that the `.wasm` file was
+/// already processed, so removing it alone would get that file processed twice.
+fn metadata_files( target_dir: &Path ) -> Vec< PathBuf > {
+    let mut output = Vec::new();
+    let metadata_paths = WalkDir::new( target_dir ).into_iter()
+        .filter_map( |entry| entry.ok() )
+        .filter( |entry| entry.file_type().is_file() && entry.path().extension().map( |ext| ext == "cargoweb-metadata" ).unwrap_or( false ) )
+        .map( |entry| entry.path().to_owned() );
+
+    for path in metadata_paths {
+        output.push( path.with_extension( "js" ) );
+        output.push( path.with_extension( "wasm" ) );
+        output.push( path );
+    }
+
+    output
+}
+
+fn clean_project( target_dir: &Path, args: CleanArgs ) -> Result< Freed, Error > {
+    let mut total = Freed::default();
+    if args.snippets {
+        let freed = remove( &snippets_directory( target_dir ), args.dry_run )?;
+        report( "the JS snippets", freed, args.dry_run );
+        total.add( freed );
+    } else if args.gc_snippets {
+        let mut freed = Freed::default();
+        for path in unreferenced_snippets( target_dir )? {
+            freed.add( remove( &path, args.dry_run )? );
+        }
+
+        report( "unused JS snippets", freed, args.dry_run );
+        total.add( freed );
+    }
+
+    if args.metadata {
+        let mut freed = Freed::default();
+        for path in metadata_files( target_dir ) {
+            freed.add( remove( &path, args.dry_run )? );
+        }
+
+        report( "`.cargoweb-metadata` files and the artifacts they describe", freed, args.dry_run );
+        total.add( freed );
+    }
+
+    Ok( total )
+}
+
+pub fn command_clean( mut args: CleanArgs ) -> Result< (), Error > {
+    // By default only clean up what can be safely removed without forcing a rebuild.
+    if !(args.gc_snippets || args.snippets || args.metadata || args.test_runner || args.packages) {
+        args.gc_snippets = true;
+    }
+
+    let mut total = Freed::default();
+    if args.gc_snippets || args.snippets || args.metadata {
+        let project = CargoProject::new( None, false, false, &[] )?;
+        total.add( clean_project( Path::new( &project.target_directory ), args )? );
+    }
+
+    if args.test_runner {
+        let freed = remove( &PROJECT_DIRS.cache_dir().join( "bin" ), args.dry_run )?;
+        report( "the cached test runner", freed, args.dry_run );
+        total.add( freed );
+    }
+
+    if args.packages {
+        let mut freed = Freed::default();
+        for name in &[ "emscripten", "binaryen" ] {
+            freed.add( remove( &PROJECT_DIRS.data_local_dir().join( name ), args.dry_run )? );
+        }
+
+        report( "the downloaded Emscripten and binaryen packages", freed, args.dry_run );
+        total.add( freed );
+    }
+
+    let verb = if args.dry_run { "Would free" } else { "Freed" };
+    eprintln!( "    {} {} bytes in total", verb, total.bytes );
+    Ok(())
+}
+
+#[test]
+fn test_clean_project() {
+    use tempfile;
+    use utils::write;
+
+    fn add_snippet( target_dir: &Path, name: &str ) -> PathBuf {
+        let directory = snippets_directory( target_dir ).join( &name[ ..2 ] );
+        fs::create_dir_all( &directory ).unwrap();
+
+        let path = directory.join( format!( "{}.json", name ) );
+        let snippet = Snippet { name: name.to_owned(), code: "return 1;".to_owned(), arg_count: 0 };
+        write( &path, &serde_json::to_string( &snippet ).unwrap() ).unwrap();
+        path
+    }
+
+    let tmpdir = tempfile::Builder::new().prefix( "cargo-web-clean-test" ).tempdir().unwrap();
+    let target_dir = tmpdir.path();
+    let used = add_snippet( target_dir, "__snippet_used" );
+    let used_by_rlib = add_snippet( target_dir, "__snippet_in_rlib" );
+    let unused = add_snippet( target_dir, "__snippet_in" );
+
+    let deps = target_dir.join( "wasm32-unknown-unknown" ).join( "debug" ).join( "deps" );
+    fs::create_dir_all( &deps ).unwrap();
+    write( deps.join( "foo.wasm" ), "\x0e__snippet_used\x00" ).unwrap();
+    write( deps.join( "libbar.rlib" ), "\x11__snippet_in_rlib\x00" ).unwrap();
+    let release = target_dir.join( "wasm32-unknown-unknown" ).join( "release" );
+    fs::create_dir_all( &release ).unwrap();
+    write( release.join( "foo.wasm" ), "\x0e__snippet_used\x00" ).unwrap();
+    write( release.join( "foo.js" ), "" ).unwrap();
+    write( release.join( "foo.cargoweb-metadata" ), "{}" ).unwrap();
+
+    let args = CleanArgs { gc_snippets: true, dry_run: true, ..CleanArgs::default() };
+    let freed = clean_project( target_dir, args ).unwrap();
+    assert_eq!( freed.files, 1 );
+    assert!( unused.exists() );
+
+    let args = CleanArgs { dry_run: false, ..args };
+    clean_project( target_dir, args ).unwrap();
+    assert!( used.exists() );
+    assert!( used_by_rlib.exists() );
+    assert!( !unused.exists() );
+    assert!( release.join( "foo.cargoweb-metadata" ).exists() );
+
+    let args = CleanArgs { metadata: true, ..CleanArgs::default() };
+    let freed = clean_project( target_dir, args ).unwrap();
+    assert_eq!( freed.files, 3 );
+    assert!( !release.join( "foo.cargoweb-metadata" ).exists() );
+    assert!( !release.join( "foo.wasm" ).exists() );
+    assert!( !release.join( "foo.js" ).exists() );
+
+    let args = CleanArgs { snippets: true, ..CleanArgs::default() };
+    clean_project( target_dir, args ).unwrap();
+    assert!( !snippets_directory( target_dir ).exists() );
+    assert!( deps.join( "libbar.rlib" ).exists() );
+}
@@ -0,0 +1,9 @@
+use serde_json;
+
+use config_schema;
+use error::Error;
+
+pub fn command_config_schema() -> Result< (), Error > {
+    println!( "{}", serde_json::to_string_pretty( &config_schema::schema() ).unwrap() );
+    Ok(())
+}
@@ -6,11 +6,12 @@ use cargo_shim::{
     TargetKind
 };
 
+use budget::check_budget;
 use build::BuildArgs;
 use deployment::Deployment;
 use error::Error;
 
-pub fn command_deploy(build_args: BuildArgs, directory: Option<PathBuf>) -> Result<(), Error> {
+pub fn command_deploy(build_args: BuildArgs, directory: Option<PathBuf>, pwa: bool, source_maps: bool) -> Result<(), Error> {
     let project = build_args.load_project()?;
 
     let package = project.package();
@@ -29,9 +30,21 @@ pub fn command_deploy(build_args: BuildArgs, directory: Option<PathBuf>) -> Resu
 
     let config = project.aggregate_configuration( Profile::Main )?;
     let target = targets[ 0 ];
-    let result = project.build( &config, target )?;
+    let build = project.build_for_web( &config, target )?;
+    check_budget( project.build_args().message_format(), &config.budget, &target.name, build.result.artifacts() )?;
 
-    let deployment = Deployment::new( package, target, &result )?;
+    let mut deployment = Deployment::new( package, target, &build.result, build.uses_shared_memory )?;
+    if source_maps {
+        deployment.deploy_source_maps();
+    }
+
+    if config.loader.integrity {
+        deployment.enable_subresource_integrity()?;
+    }
+
+    if pwa {
+        deployment.enable_pwa( project.build_args().message_format(), &config.pwa, &target.name )?;
+    }
 
     let is_using_default_directory;
     let directory = match directory {
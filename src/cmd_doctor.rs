@@ -0,0 +1,223 @@
+use std::env;
+use std::process::Command;
+
+use serde_json;
+use cargo_shim::Profile;
+
+use build::{self, Backend, BuildArgs};
+use cmd_test::find_nodejs;
+use config::Config;
+use emscripten::{binaryen_package, emscripten_package, find_emcc};
+use error::Error;
+use package::{DownloadSettings, PrebuiltPackage, installed_version, package_path};
+use test_chromium::find_chromium;
+use utils::find_cmd;
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Warning,
+    Error
+}
+
+impl Status {
+    fn as_str( &self ) -> &'static str {
+        match *self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Error => "ERROR"
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    name: String,
+    status: Status,
+    details: String
+}
+
+struct Checks( Vec< Check > );
+
+impl Checks {
+    fn add< N, D >( &mut self, name: N, status: Status, details: D ) where N: Into< String >, D: Into< String > {
+        self.0.push( Check { name: name.into(), status, details: details.into() } );
+    }
+}
+
+// Returns the first line of the `--version` output of the given command.
+fn version_of( command: &str ) -> Option< String > {
+    let output = Command::new( command ).arg( "--version" ).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy( &output.stdout ).lines().next().map( |line| line.trim().to_owned() )
+}
+
+fn check_toolchain( checks: &mut Checks ) {
+    for &command in &[ "rustc", "cargo" ] {
+        match version_of( command ) {
+            Some( version ) => checks.add( command, Status::Ok, version ),
+            None => checks.add( command, Status::Error, format!( "`{} --version` failed; is Rust installed?", command ) )
+        }
+    }
+
+    let rustup = match build::find_rustup() {
+        Some( rustup ) => rustup,
+        None => {
+            checks.add( "rustup", Status::Warning, "not found; the targets have to be installed manually" );
+            return;
+        }
+    };
+
+    let targets = match build::rustup_targets( rustup ) {
+        Ok( targets ) => targets,
+        Err( error ) => {
+            checks.add( "rustup", Status::Error, error.to_string() );
+            return;
+        }
+    };
+
+    for backend in &[ Backend::WebAssembly, Backend::EmscriptenWebAssembly, Backend::EmscriptenAsmJs ] {
+        let name = format!( "target {}", backend.triplet() );
+        match targets.get( backend.triplet() ).cloned() {
+            Some( true ) => checks.add( name, Status::Ok, "installed" ),
+            Some( false ) => checks.add( name, Status::Warning, "not installed; it will be installed through rustup when needed" ),
+            None => checks.add( name, Status::Warning, "not available for this toolchain" )
+        }
+    }
+}
+
+fn check_test_runners( checks: &mut Checks ) {
+    match find_nodejs() {
+        Some( nodejs ) => checks.add( "node.js", Status::Ok, version_of( nodejs ).unwrap_or_else( || nodejs.to_owned() ) ),
+        None => checks.add( "node.js", Status::Warning, "not found; it's needed for `cargo web test --nodejs`" )
+    }
+
+    match find_chromium() {
+        Some( chromium ) => checks.add( "chromium", Status::Ok, chromium ),
+        None => checks.add( "chromium", Status::Warning, "neither Chromium nor Chrome found; one of them is needed for `cargo web test`" )
+    }
+}
+
+fn check_package( checks: &mut Checks, name: &str, package: Option< PrebuiltPackage >, system_command: Option< &str >, purpose: &str ) {
+    let package = match package {
+        Some( package ) => package,
+        None => {
+            match system_command {
+                Some( command ) => checks.add( name, Status::Ok, format!( "no prebuilt package for this platform; using `{}` from the PATH", command ) ),
+                None => checks.add( name, Status::Warning, format!( "no prebuilt package for this platform and not found in the PATH; it's needed {}", purpose ) )
+            }
+
+            return;
+        }
+    };
+
+    match installed_version( &package ) {
+        Some( ref version ) if version == package.version => {
+            checks.add( name, Status::Ok, format!( "{} in {:?}", version, package_path( &package ) ) );
+        },
+        Some( version ) => {
+            checks.add( name, Status::Warning, format!( "{} is outdated; {} will be downloaded when needed {}", version, package.version, purpose ) );
+        },
+        None if DownloadSettings::from_env().offline => {
+            checks.add( name, Status::Warning, format!( "not installed and the offline mode is enabled; {} is needed {}", package.version, purpose ) );
+        },
+        None => {
+            checks.add( name, Status::Ok, format!( "not downloaded yet; {} will be downloaded when needed {}", package.version, purpose ) );
+        }
+    }
+}
+
+fn check_packages( checks: &mut Checks ) {
+    check_package( checks, "emscripten", emscripten_package(), find_emcc(), "for the Emscripten targets" );
+    check_package( checks, "binaryen", binaryen_package(), find_cmd( &[ "wasm-opt", "wasm-opt.exe" ] ), "for the `[wasm-opt]` section" );
+}
+
+/// Whether there's a `Cargo.toml` in the current directory or in any of its parents,
+/// which is where `cargo` itself would look for one.
+fn is_in_cargo_project() -> bool {
+    let cwd = match env::current_dir() {
+        Ok( cwd ) => cwd,
+        Err( _ ) => return false
+    };
+
+    cwd.ancestors().any( |directory| directory.join( "Cargo.toml" ).is_file() )
+}
+
+fn check_project( checks: &mut Checks, build_args: BuildArgs ) {
+    if !is_in_cargo_project() {
+        checks.add( "project", Status::Warning, "not in a cargo project; skipping the project checks" );
+        return;
+    }
+
+    let project = match build_args.load_project() {
+        Ok( project ) => project,
+        Err( error ) => {
+            checks.add( "project", Status::Error, format!( "cannot load the project: {}", error ) );
+            return;
+        }
+    };
+
+    checks.add( "project", Status::Ok, format!( "`{}` targeting `{}`", project.package().name, project.backend().triplet() ) );
+
+    let main_package = project.package();
+    let mut uses_stdweb = false;
+    for package in project.used_packages( Profile::Main ) {
+        if package.name == "stdweb" {
+            uses_stdweb = true;
+            let version = package.id.version();
+            if build::is_old_stdweb( package ) {
+                checks.add( "stdweb", Status::Warning, format!( "{} is too old to use the new `.wasm` processing; please update to at least 0.4.11", version ) );
+            } else {
+                checks.add( "stdweb", Status::Ok, version.to_string() );
+            }
+        }
+
+        let is_main_crate = package.id == main_package.id;
+        let name = format!( "config of `{}`", package.name );
+        match Config::load_for_package( package, is_main_crate ) {
+            Ok( None ) => {},
+            Ok( Some( (config, warnings) ) ) => {
+                if warnings.is_empty() {
+                    checks.add( name, Status::Ok, config.source() );
+                } else {
+                    checks.add( name, Status::Warning, format!( "{} has {} warning(s); run `cargo web build` to see them", config.source(), warnings.len() ) );
+                }
+            },
+            Err( error ) => checks.add( name, Status::Error, error.to_string() )
+        }
+    }
+
+    if !uses_stdweb {
+        checks.add( "stdweb", Status::Ok, "not used" );
+    }
+}
+
+pub fn command_doctor( build_args: BuildArgs, json: bool ) -> Result< (), Error > {
+    let mut checks = Checks( Vec::new() );
+    checks.add( "cargo-web", Status::Ok, env!( "CARGO_PKG_VERSION" ) );
+    check_toolchain( &mut checks );
+    check_test_runners( &mut checks );
+    check_packages( &mut checks );
+    check_project( &mut checks, build_args );
+
+    let checks = checks.0;
+    if json {
+        println!( "{}", serde_json::to_string_pretty( &json!({ "checks": checks }) ).unwrap() );
+    } else {
+        let width = checks.iter().map( |check| check.name.len() ).max().unwrap_or( 0 );
+        for check in &checks {
+            eprintln!( "  {:<7}  {:<width$}  {}", check.status.as_str(), check.name, check.details, width = width );
+        }
+    }
+
+    let failures = checks.iter().filter( |check| check.status == Status::Error ).count();
+    if failures > 0 {
+        return Err( format!( "{} check(s) failed", failures ).into() );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use handlebars::Handlebars;
+use serde_json;
+use walkdir::WalkDir;
+
+use error::Error;
+use utils::{read, write};
+
+// The files which end with `.hbs` are rendered with Handlebars and saved without the extension.
+const COMMON_FILES: &'static [(&'static str, &'static str)] = &[
+    (".gitignore", include_str!( "templates/gitignore" )),
+    ("Web.toml.hbs", include_str!( "templates/Web.toml.hbs" ))
+];
+
+const APP_FILES: &'static [(&'static str, &'static str)] = &[
+    ("Cargo.toml.hbs", include_str!( "templates/app/Cargo.toml.hbs" )),
+    ("src/main.rs", include_str!( "templates/app/main.rs" )),
+    ("static/index.html.hbs", include_str!( "templates/app/index.html.hbs" ))
+];
+
+const LIB_FILES: &'static [(&'static str, &'static str)] = &[
+    ("Cargo.toml.hbs", include_str!( "templates/lib/Cargo.toml.hbs" )),
+    ("src/lib.rs", include_str!( "templates/lib/lib.rs" ))
+];
+
+const CDYLIB_FILES: &'static [(&'static str, &'static str)] = &[
+    ("Cargo.toml.hbs", include_str!( "templates/cdylib/Cargo.toml.hbs" )),
+    ("src/lib.rs", include_str!( "templates/cdylib/lib.rs" )),
+    ("static/index.html.hbs", include_str!( "templates/cdylib/index.html.hbs" ))
+];
+
+const WORKER_FILES: &'static [(&'static str, &'static str)] = &[
+    ("Cargo.toml.hbs", include_str!( "templates/worker/Cargo.toml.hbs" )),
+    ("src/main.rs", include_str!( "templates/worker/main.rs" )),
+    ("static/index.html.hbs", include_str!( "templates/worker/index.html.hbs" ))
+];
+
+const DEFAULT_TARGET: &'static str = "wasm32-unknown-unknown";
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Template {
+    App,
+    Lib,
+    CDyLib,
+    Worker
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str( s: &str ) -> Result< Self, Self::Err > {
+        match s {
+            "app" => Ok( Template::App ),
+            "lib" => Ok( Template::Lib ),
+            "cdylib" => Ok( Template::CDyLib ),
+            "worker" => Ok( Template::Worker ),
+            _ => Err( Error::ConfigurationError( format!( "{} is not a valid template.", s ) ) )
+        }
+    }
+}
+
+impl Template {
+    fn name( &self ) -> &'static str {
+        match *self {
+            Template::App => "app",
+            Template::Lib => "lib",
+            Template::CDyLib => "cdylib",
+            Template::Worker => "worker"
+        }
+    }
+
+    fn files( &self ) -> &'static [(&'static str, &'static str)] {
+        match *self {
+            Template::App => APP_FILES,
+            Template::Lib => LIB_FILES,
+            Template::CDyLib => CDYLIB_FILES,
+            Template::Worker => WORKER_FILES
+        }
+    }
+}
+
+fn check_name( name: &str ) -> Result< (), Error > {
+    let is_valid =
+        name.chars().next().map( |ch| ch.is_ascii_alphabetic() || ch == '_' ).unwrap_or( false ) &&
+        name.chars().all( |ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-' );
+
+    if !is_valid {
+        return Err( Error::ConfigurationError( format!(
+            "`{}` is not a valid crate name; use `--name` to specify a different one",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+fn render( handlebars: &Handlebars, data: &serde_json::Value, path: &str, contents: &str ) -> Result< (String, String), Error > {
+    if !path.ends_with( ".hbs" ) {
+        return Ok( (path.to_owned(), contents.to_owned()) );
+    }
+
+    let contents = handlebars.render_template( contents, data ).map_err( |error| format!( "cannot render `{}`: {}", path, error ) )?;
+    Ok( (path[ ..path.len() - ".hbs".len() ].to_owned(), contents) )
+}
+
+/// Renders the files of the template; the keys are the paths relative to the crate's root.
+///
+/// The embedded files can be overridden, or new ones added, by putting them
+/// in a directory named after the template inside of the `template_dir`.
+fn render_template( template: Template, template_dir: Option< &Path >, name: &str ) -> Result< BTreeMap< String, String >, Error > {
+    let handlebars = Handlebars::new();
+    let data = json!({
+        "name": name,
+        "crate_name": name.replace( "-", "_" ),
+        "cargo_web_version": env!( "CARGO_PKG_VERSION" ),
+        "default_target": DEFAULT_TARGET
+    });
+
+    let mut output = BTreeMap::new();
+    for &(path, contents) in COMMON_FILES.iter().chain( template.files() ) {
+        let (path, contents) = render( &handlebars, &data, path, contents )?;
+        output.insert( path, contents );
+    }
+
+    if let Some( template_dir ) = template_dir {
+        let root = template_dir.join( template.name() );
+        if !root.is_dir() {
+            return Err( format!( "template directory not found: {:?}", root ).into() );
+        }
+
+        for entry in WalkDir::new( &root ) {
+            let entry = entry.map_err( |error| format!( "cannot read {:?}: {}", root, error ) )?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let relative_path = entry.path().strip_prefix( &root ).unwrap();
+            let relative_path: Vec< _ > = relative_path.components().map( |component| component.as_os_str().to_string_lossy().into_owned() ).collect();
+            let contents = read( entry.path() ).map_err( |error| Error::CannotLoadFile( entry.path().to_owned(), error ) )?;
+            let (path, contents) = render( &handlebars, &data, &relative_path.join( "/" ), &contents )?;
+            output.insert( path, contents );
+        }
+    }
+
+    Ok( output )
+}
+
+/// Returns the existing `.gitignore` with the entries of the template which it doesn't have
+/// appended to it, or `None` if it already has all of them.
+fn merge_gitignore( existing: &str, template: &str ) -> Option< String > {
+    let missing: Vec< _ > = template.lines()
+        .filter( |line| !line.trim().is_empty() && !existing.lines().any( |existing_line| existing_line.trim() == line.trim() ) )
+        .collect();
+
+    if missing.is_empty() {
+        return None;
+    }
+
+    let mut output = existing.to_owned();
+    if !output.is_empty() && !output.ends_with( "\n" ) {
+        output.push( '\n' );
+    }
+
+    for line in missing {
+        output.push_str( line );
+        output.push( '\n' );
+    }
+
+    Some( output )
+}
+
+fn generate( root: &Path, name: Option< String >, template: Template, template_dir: Option< PathBuf > ) -> Result< (), Error > {
+    let name = match name {
+        Some( name ) => name,
+        None => root.file_name().map( |name| name.to_string_lossy().into_owned() ).ok_or_else( || {
+            Error::ConfigurationError( format!( "cannot infer the crate name from {:?}; use `--name` to specify it", root ) )
+        })?
+    };
+
+    check_name( &name )?;
+    let mut files = render_template( template, template_dir.as_ref().map( |path| path.as_path() ), &name )?;
+
+    // Just as `cargo init` does we add our entries to an already existing `.gitignore`.
+    let gitignore_path = root.join( ".gitignore" );
+    if gitignore_path.exists() {
+        if let Some( template_gitignore ) = files.remove( ".gitignore" ) {
+            let existing = read( &gitignore_path ).map_err( |error| Error::CannotLoadFile( gitignore_path.clone(), error ) )?;
+            if let Some( gitignore ) = merge_gitignore( &existing, &template_gitignore ) {
+                files.insert( ".gitignore".to_owned(), gitignore );
+            }
+        }
+    }
+
+    let existing: Vec< _ > = files.keys().filter( |path| *path != ".gitignore" && root.join( path ).exists() ).map( |path| format!( "`{}`", path ) ).collect();
+    if !existing.is_empty() {
+        return Err( Error::ConfigurationError( format!( "refusing to overwrite existing files: {}", existing.join( ", " ) ) ) );
+    }
+
+    for (path, contents) in &files {
+        let full_path = root.join( path );
+        if let Some( parent ) = full_path.parent() {
+            fs::create_dir_all( parent ).map_err( |error| Error::CannotCreateFile( parent.to_owned(), error ) )?;
+        }
+
+        write( &full_path, contents ).map_err( |error| Error::CannotWriteToFile( full_path.clone(), error ) )?;
+    }
+
+    eprintln!( "     Created `{}` from the `{}` template", name, template.name() );
+    Ok(())
+}
+
+pub fn command_new( path: PathBuf, name: Option< String >, template: Template, template_dir: Option< PathBuf > ) -> Result< (), Error > {
+    if path.exists() {
+        return Err( Error::ConfigurationError( format!( "destination {:?} already exists; use `cargo web init` to use an existing directory", path ) ) );
+    }
+
+    generate( &path, name, template, template_dir )
+}
+
+pub fn command_init( path: PathBuf, name: Option< String >, template: Template, template_dir: Option< PathBuf > ) -> Result< (), Error > {
+    let path = env::current_dir().map_err( |error| Error::EnvironmentError( format!( "cannot get the current directory: {}", error ) ) )?.join( path );
+    let path = path.canonicalize().map_err( |error| Error::ConfigurationError( format!( "cannot use {:?}: {}", path, error ) ) )?;
+    generate( &path, name, template, template_dir )
+}
+
+#[test]
+fn test_render_templates() {
+    use toml;
+    use config_schema;
+
+    let schema = config_schema::schema();
+    for &template in &[ Template::App, Template::Lib, Template::CDyLib, Template::Worker ] {
+        let files = render_template( template, None, "hello-world" ).unwrap();
+
+        let manifest: toml::Value = toml::from_str( &files[ "Cargo.toml" ] ).unwrap();
+        assert_eq!( manifest.get( "package" ).and_then( |package| package.get( "name" ) ), Some( &toml::Value::String( "hello-world".to_owned() ) ) );
+
+        let web_toml: toml::Value = toml::from_str( &files[ "Web.toml" ] ).unwrap();
+        assert!( config_schema::unknown_keys( &schema, &web_toml ).is_empty() );
+        assert!( files[ "Web.toml" ].contains( env!( "CARGO_PKG_VERSION" ) ) );
+
+        assert!( files.contains_key( "src/main.rs" ) || files.contains_key( "src/lib.rs" ) );
+        assert_eq!( files.contains_key( "static/index.html" ), template != Template::Lib );
+    }
+
+    assert!( render_template( Template::CDyLib, None, "hello-world" ).unwrap()[ "static/index.html" ].contains( "Rust.hello_world" ) );
+}
+
+#[test]
+fn test_generate() {
+    use tempfile;
+
+    let tmpdir = tempfile::Builder::new().prefix( "cargo-web-new-test" ).tempdir().unwrap();
+    let template_dir = tmpdir.path().join( "templates" );
+    fs::create_dir_all( template_dir.join( "app" ).join( "static" ) ).unwrap();
+    write( template_dir.join( "app" ).join( "static" ).join( "index.html.hbs" ), "<title>{{name}}</title>" ).unwrap();
+
+    let root = tmpdir.path().join( "hello" );
+    generate( &root, None, Template::App, Some( template_dir.clone() ) ).unwrap();
+    assert_eq!( read( root.join( "static" ).join( "index.html" ) ).unwrap(), "<title>hello</title>" );
+    assert!( root.join( "src" ).join( "main.rs" ).exists() );
+
+    assert!( generate( &root, None, Template::App, None ).is_err() );
+    assert!( generate( &tmpdir.path().join( "1st" ), None, Template::App, None ).is_err() );
+
+    let root = tmpdir.path().join( "existing" );
+    fs::create_dir_all( &root ).unwrap();
+    write( root.join( ".gitignore" ), "/target\n*.log" ).unwrap();
+    generate( &root, None, Template::Lib, None ).unwrap();
+    assert_eq!( read( root.join( ".gitignore" ) ).unwrap(), "/target\n*.log\n**/*.rs.bk\n" );
+}
+
+#[test]
+fn test_merge_gitignore() {
+    assert_eq!( merge_gitignore( "", "/target\n" ), Some( "/target\n".to_owned() ) );
+    assert_eq!( merge_gitignore( "/target\n**/*.rs.bk\n", "/target\n**/*.rs.bk\n" ), None );
+    assert_eq!( merge_gitignore( "node_modules", "/target\n" ), Some( "node_modules\n/target\n".to_owned() ) );
+}
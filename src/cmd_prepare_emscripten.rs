@@ -1,8 +1,24 @@
-use emscripten::initialize_emscripten;
+use std::path::PathBuf;
+
+use cargo_shim::MessageFormat;
+use emscripten::{binaryen_package, emscripten_package, initialize_emscripten};
 use error::Error;
+use package::{DownloadSettings, install_archives};
+
+pub fn command_prepare_emscripten( offline: bool, archives: Vec< PathBuf > ) -> Result< (), Error > {
+    let mut settings = DownloadSettings::from_env();
+    settings.offline = settings.offline || offline;
+
+    if !archives.is_empty() {
+        let packages: Vec< _ > = emscripten_package().into_iter().chain( binaryen_package() ).collect();
+        if packages.is_empty() {
+            return Err( Error::EmscriptenNotAvailable );
+        }
+
+        install_archives( MessageFormat::Human, &packages, &archives )?;
+    }
 
-pub fn command_prepare_emscripten< 'a >() -> Result< (), Error > {
-    match initialize_emscripten( false, true ) {
+    match initialize_emscripten( MessageFormat::Human, &settings, false, true )? {
         None => return Err( Error::EmscriptenNotAvailable ),
         Some( _emscripten ) => return Ok( () ),
     }
@@ -0,0 +1,48 @@
+use serde_json;
+
+use cargo_shim::{
+    Profile,
+    TargetKind
+};
+
+use build::BuildArgs;
+use error::Error;
+use utils::read_bytes;
+use wasm_size;
+use wasm_threads;
+
+pub fn command_size( build_args: BuildArgs, top: usize, json: bool ) -> Result< (), Error > {
+    let project = build_args.load_project()?;
+    if !project.backend().is_native_wasm() {
+        return Err( "`cargo web size` is only supported when targeting `wasm32-unknown-unknown`".into() );
+    }
+
+    let targets = project.target_or_select( |target| {
+        target.kind == TargetKind::Bin || target.kind == TargetKind::CDyLib
+    })?;
+
+    if targets.is_empty() {
+        return Err( "No valid target found; expected a `bin` crate or a `cdylib`".into() );
+    }
+
+    let config = project.aggregate_configuration( Profile::Main )?;
+    let target = targets[ 0 ];
+    let result = project.build( &config, target )?;
+
+    let wasm_path = result.artifacts().iter()
+        .find( |artifact| artifact.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) && !wasm_threads::is_worker_module( artifact ) )
+        .cloned()
+        .ok_or_else( || Error::from( "internal error: no `.wasm` file found" ) )?;
+
+    let bytes = read_bytes( &wasm_path ).map_err( |error| Error::CannotLoadFile( wasm_path.clone(), error ) )?;
+    let report = wasm_size::analyze( &bytes, top ).map_err( |error| format!( "cannot analyze {:?}: {}", wasm_path, error ) )?;
+
+    if json {
+        println!( "{}", serde_json::to_string_pretty( &report ).unwrap() );
+    } else {
+        eprintln!( "Size breakdown of {:?}:", wasm_path );
+        report.print();
+    }
+
+    Ok(())
+}
@@ -31,7 +31,8 @@ use http_utils::{
     SimpleServer,
     response_from_data,
     response_from_status,
-    response_from_file
+    response_from_file,
+    with_cross_origin_isolation
 };
 
 use deployment::{Deployment, ArtifactKind};
@@ -141,8 +142,8 @@ fn select_target( project: &Project ) -> Result< CargoTarget, Error > {
 impl LastBuild {
     fn new( project: Project, target: CargoTarget, counter: Counter ) -> Result< Self, Error > {
         let config = project.aggregate_configuration( Profile::Main )?;
-        let result = project.build( &config, &target )?;
-        let deployment = Deployment::new( project.package(), &target, &result )?;
+        let build = project.build_for_web( &config, &target )?;
+        let deployment = Deployment::new( project.package(), &target, &build.result, build.uses_shared_memory )?;
 
         Ok( LastBuild {
             counter,
@@ -272,8 +273,14 @@ fn monitor_for_changes_and_rebuild(
                         last_paths_to_watch = new_paths_to_watch;
                     }
 
-                    if let Ok( new_build ) = LastBuild::new( project, target, counter ) {
-                        *last_build.lock().unwrap() = new_build;
+                    match LastBuild::new( project, target, counter ) {
+                        Ok( new_build ) => {
+                            *last_build.lock().unwrap() = new_build;
+                        },
+                        Err( error ) => {
+                            eprintln!( "error: {}", error );
+                            eprintln!( "==== The previous build will keep being served ====" );
+                        }
                     }
                 }
             }
@@ -341,14 +348,20 @@ pub fn command_start(
                 }
             }
 
-            match artifact.kind {
+            let response = match artifact.kind {
                 ArtifactKind::Data( data ) => {
-                    return response_from_data(&artifact.mime_type, data);
+                    response_from_data(&artifact.mime_type, data)
                 },
 
                 ArtifactKind::File( fp ) => {
-                    return response_from_file(&artifact.mime_type, fp);
+                    response_from_file(&artifact.mime_type, fp)
                 }
+            };
+
+            if last_build.deployment.uses_shared_memory() {
+                with_cross_origin_isolation( response )
+            } else {
+                response
             }
         } else {
             response_from_status(StatusCode::NOT_FOUND)
@@ -1,6 +1,6 @@
-use std::process::{Command, exit};
+use std::process::{Command, Stdio, exit};
+use std::io::{BufRead, BufReader};
 use std::iter;
-use std::env;
 use std::fs;
 use std::ffi::OsStr;
 
@@ -20,15 +20,11 @@ use utils::{
 };
 use test_chromium::test_in_chromium;
 use project_dirs::PROJECT_DIRS;
+use wasm_stack_trace::Symbolicator;
 
 pub const TEST_RUNNER: &'static str = include_str!( "test_runner.js" );
 
-fn test_in_nodejs(
-    backend: Backend,
-    build: CargoResult,
-    arg_passthrough: &Vec< &OsStr >,
-    any_failure: &mut bool
-) -> Result<(), Error> {
+pub fn find_nodejs() -> Option< &'static str > {
     let possible_commands =
         if cfg!( windows ) {
             &[ "node.exe" ][..]
@@ -36,7 +32,16 @@ fn test_in_nodejs(
             &[ "nodejs", "node" ][..]
         };
 
-    let nodejs_name = find_cmd( possible_commands ).ok_or_else( || {
+    find_cmd( possible_commands )
+}
+
+fn test_in_nodejs(
+    backend: Backend,
+    build: CargoResult,
+    arg_passthrough: &Vec< &OsStr >,
+    any_failure: &mut bool
+) -> Result<(), Error> {
+    let nodejs_name = find_nodejs().ok_or_else( || {
         Error::EnvironmentError( "node.js not found; please install it!".into() )
     })?;
 
@@ -69,28 +74,57 @@ fn test_in_nodejs(
         .chain( iter::once( artifact.as_os_str() ) )
         .chain( arg_passthrough.iter().cloned() );
 
-    let previous_cwd = env::current_dir().unwrap();
-    if backend.is_emscripten_wasm() {
+    let symbolicator = if backend.is_native_wasm() {
+        build.artifacts().iter()
+            .find( |artifact| artifact.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) )
+            .and_then( |wasm_artifact| Symbolicator::from_file( wasm_artifact ) )
+    } else {
+        None
+    };
+
+    let working_directory = if backend.is_emscripten_wasm() {
         // On the Emscripten target the `.wasm` file is in a different directory.
         let wasm_artifact = build.artifacts().iter()
             .find( |artifact| artifact.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) )
             .expect( "internal error: no .wasm file found" );
 
-        env::set_current_dir( wasm_artifact.parent().unwrap() ).unwrap();
+        wasm_artifact.parent().unwrap()
     } else {
-        env::set_current_dir( artifact.parent().unwrap() ).unwrap();
-    }
+        artifact.parent().unwrap()
+    };
 
+    // This is set only for the child so that an early return can't leave us in the wrong directory.
     let mut command = Command::new( nodejs_name );
     command.args( test_args );
+    command.current_dir( working_directory );
 
     debug!( "Launching: {:?}", command );
 
-    let status = command.run();
-    *any_failure = *any_failure || !status.is_ok();
-    debug!( "Status: {:?}", status );
+    if let Some( symbolicator ) = symbolicator {
+        command.stderr( Stdio::piped() );
+        let mut child = command.spawn()
+            .map_err( |err| Error::RuntimeError( "cannot launch node.js".into(), err.into() ) )?;
+
+        // Panics end up on stderr, so that's where the stack traces are.
+        let stderr = BufReader::new( child.stderr.take().unwrap() );
+        for line in stderr.lines() {
+            let line = match line {
+                Ok( line ) => line,
+                Err( _ ) => break
+            };
+
+            eprintln!( "{}", symbolicator.symbolicate( &line ) );
+        }
 
-    env::set_current_dir( previous_cwd ).unwrap();
+        let status = child.wait()
+            .map_err( |err| Error::RuntimeError( "cannot wait for node.js to finish".into(), err.into() ) )?;
+        *any_failure = *any_failure || !status.success();
+        debug!( "Status: {:?}", status );
+    } else {
+        let status = command.run();
+        *any_failure = *any_failure || !status.is_ok();
+        debug!( "Status: {:?}", status );
+    }
 
     Ok(())
 }
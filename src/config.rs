@@ -1,32 +1,26 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::de::{self, Deserialize, Deserializer};
+use serde_json;
 use toml;
 use semver::Version;
-use cargo_shim::CargoPackage;
+use cargo_shim::{BuildType, CargoPackage, MessageFormat, render_code_frame};
 
 use build::Backend;
+use config_diagnostics::{self, Location};
+use config_schema;
+use dotenv;
+use messages;
 use utils::read;
+use wasm_runtime::RuntimeKind;
 use error::Error;
 
-fn is_all_strings( array: &[toml::Value] ) -> bool {
-    array.iter().all( |element| element.is_str() )
-}
-
-fn from_string_or_array_of_strings( path_in_toml: &str, config: &Config, prepend_js: toml::Value ) -> Result< Vec< String >, Error > {
-    match prepend_js {
-        toml::Value::String( path ) => Ok( vec![ path ] ),
-        toml::Value::Array( ref array ) if is_all_strings( &array ) => {
-            Ok( array.iter().map( |value| value.clone().try_into().unwrap() ).collect() )
-        },
-        _ => {
-            Err( format!(
-                "{}: '{}' must be either a string or an array of strings",
-                config.source(),
-                path_in_toml
-            ).into() )
-        }
-    }
+lazy_static! {
+    static ref SCHEMA: serde_json::Value = config_schema::schema();
 }
 
 #[derive(Clone, Debug, Default)]
@@ -35,42 +29,266 @@ pub struct PerTargetConfig {
     pub prepend_js: Option< Vec< String > >
 }
 
+/// Settings from the `[profile.dev]` and `[profile.release]` sections.
+#[derive(Clone, Debug, Default)]
+pub struct PerProfileConfig {
+    pub link_args: Option< Vec< String > >,
+    pub prepend_js: Option< Vec< String > >,
+    pub runtime: Option< RuntimeKind >,
+    pub wasm_opt: Option< WasmOptConfig >,
+    pub env: BTreeMap< String, EnvVarConfig >,
+    pub env_file: Option< String >
+}
+
+/// A variable from the `[env]` section.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct EnvVarConfig {
+    /// When missing the value has to come from a `.env` file or from the environment.
+    pub value: Option< String >,
+    /// Whether the variable is also exposed to the JavaScript through `Module.env`.
+    pub expose: bool
+}
+
+/// An environment variable which is set when compiling the crate.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EnvVariable {
+    pub name: String,
+    pub value: String,
+    pub expose: bool
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LoaderConfig {
+    pub wasm_base_url: Option< String >,
+    pub credentials: Option< String >,
+    pub cache_in_indexeddb: bool,
+    pub integrity: bool
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PwaIcon {
+    pub src: String,
+    pub sizes: Option< String >,
+    #[serde(rename = "type")]
+    pub mime_type: Option< String >
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PwaConfig {
+    pub name: Option< String >,
+    pub short_name: Option< String >,
+    pub description: Option< String >,
+    pub start_url: Option< String >,
+    pub display: Option< String >,
+    pub theme_color: Option< String >,
+    pub background_color: Option< String >,
+    pub icons: Vec< PwaIcon >
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BudgetConfig {
+    pub max_wasm_size: Option< u64 >,
+    pub max_js_size: Option< u64 >,
+    pub max_gzipped_size: Option< u64 >,
+    pub warn_only: bool
+}
+
+impl BudgetConfig {
+    pub fn is_empty( &self ) -> bool {
+        self.max_wasm_size.is_none() && self.max_js_size.is_none() && self.max_gzipped_size.is_none()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WasmOptConfig {
+    pub level: Option< String >
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ThreadsConfig {
+    pub pool_size: Option< u32 >,
+    pub stack_size: Option< u64 >
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Config {
     crate_name: Option< String >,
     pub config_path: Option< PathBuf >,
+    origin: &'static str,
 
     pub minimum_cargo_web_version: Option< Version >,
     pub per_target: HashMap< Backend, PerTargetConfig >,
-    pub default_target: Option< Backend >
+    pub per_profile: HashMap< BuildType, PerProfileConfig >,
+    pub per_target_profile: HashMap< (Backend, BuildType), PerProfileConfig >,
+    pub default_target: Option< Backend >,
+    pub loader: LoaderConfig,
+    pub pwa: PwaConfig,
+    pub budget: BudgetConfig,
+    pub wasm_opt: WasmOptConfig,
+    pub threads: ThreadsConfig,
+    pub env: BTreeMap< String, EnvVarConfig >,
+    pub env_file: Option< String >
 }
 
 impl Config {
     pub fn source( &self ) -> String {
+        let origin = if self.origin.is_empty() { WEB_TOML } else { self.origin };
         if let Some( ref name ) = self.crate_name {
-            format!( "`{}`'s Web.toml", name )
+            format!( "`{}`'s {}", name, origin )
         } else if let Some( ref path ) = self.config_path {
             format!( "{:?}", path )
         } else {
-            "Web.toml".into()
+            origin.into()
         }
     }
 
-    pub fn get_link_args( &self, backend: Backend ) -> Option< &Vec< String > > {
-        self.per_target.get( &backend ).and_then( |per_target| per_target.link_args.as_ref() )
+    // Returns the profile sections which apply, from the least to the most specific one.
+    fn get_profiles( &self, backend: Backend, build_type: BuildType ) -> Vec< &PerProfileConfig > {
+        self.per_profile.get( &build_type ).into_iter()
+            .chain( self.per_target_profile.get( &(backend, build_type) ) )
+            .collect()
     }
 
-    pub fn get_prepend_js( &self, backend: Backend ) -> Option< &Vec< String > > {
-        self.per_target.get( &backend ).and_then( |per_target| per_target.prepend_js.as_ref() )
+    /// The link args from the profile sections are appended to the ones of the target.
+    pub fn get_link_args( &self, backend: Backend, build_type: BuildType ) -> Option< Vec< String > > {
+        let target_link_args = self.per_target.get( &backend ).and_then( |per_target| per_target.link_args.as_ref() );
+        let link_args: Vec< &Vec< String > > = target_link_args.into_iter()
+            .chain( self.get_profiles( backend, build_type ).into_iter().filter_map( |profile| profile.link_args.as_ref() ) )
+            .collect();
+
+        if link_args.is_empty() {
+            return None;
+        }
+
+        Some( link_args.into_iter().flat_map( |link_args| link_args.iter().cloned() ).collect() )
+    }
+
+    /// The `prepend-js` from the most specific section replaces the other ones.
+    pub fn get_prepend_js( &self, backend: Backend, build_type: BuildType ) -> Option< &Vec< String > > {
+        self.get_profiles( backend, build_type ).into_iter().rev()
+            .filter_map( |profile| profile.prepend_js.as_ref() )
+            .next()
+            .or_else( || self.per_target.get( &backend ).and_then( |per_target| per_target.prepend_js.as_ref() ) )
+    }
+
+    pub fn get_runtime( &self, backend: Backend, build_type: BuildType ) -> Option< RuntimeKind > {
+        self.get_profiles( backend, build_type ).into_iter().rev()
+            .filter_map( |profile| profile.runtime )
+            .next()
+    }
+
+    /// The top-level `[wasm-opt]` section only applies to release builds;
+    /// it can be overridden, or enabled for debug builds, by the profile sections.
+    pub fn get_wasm_opt( &self, backend: Backend, build_type: BuildType ) -> WasmOptConfig {
+        let default = if build_type == BuildType::Release { Some( &self.wasm_opt ) } else { None };
+        self.get_profiles( backend, build_type ).into_iter().rev()
+            .filter_map( |profile| profile.wasm_opt.as_ref() )
+            .next()
+            .or( default )
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Resolves the environment variables for the given target and profile.
+    ///
+    /// The `.env` files are loaded first and then overridden by the `[env]` sections,
+    /// with the more specific sections taking precedence; the variables which are
+    /// already set in the environment are never overridden.
+    pub fn get_env( &self, backend: Backend, build_type: BuildType ) -> Result< Vec< EnvVariable >, Error > {
+        let profiles = self.get_profiles( backend, build_type );
+        let mut variables: BTreeMap< String, EnvVarConfig > = BTreeMap::new();
+
+        let env_files = self.env_file.iter().chain( profiles.iter().filter_map( |profile| profile.env_file.as_ref() ) );
+        for env_file in env_files {
+            let path = match self.config_path.as_ref().and_then( |path| path.parent() ) {
+                Some( config_dir ) => config_dir.join( env_file ),
+                None => PathBuf::from( env_file )
+            };
+
+            let contents = read( &path ).map_err( |error| format!( "{}: cannot read {:?}: {}", self.source(), env_file, error ) )?;
+            let entries = dotenv::parse( &contents ).map_err( |error| format!( "{}: cannot parse {:?}: {}", self.source(), env_file, error ) )?;
+            for (name, value) in entries {
+                variables.entry( name ).or_insert_with( Default::default ).value = Some( value );
+            }
+        }
+
+        let sections = Some( &self.env ).into_iter().chain( profiles.iter().map( |profile| &profile.env ) );
+        for section in sections {
+            for (name, variable) in section {
+                let entry = variables.entry( name.clone() ).or_insert_with( Default::default );
+                if variable.value.is_some() {
+                    entry.value = variable.value.clone();
+                }
+
+                entry.expose = entry.expose || variable.expose;
+            }
+        }
+
+        let mut output = Vec::new();
+        for (name, variable) in variables {
+            let value = match env::var( &name ).ok().or( variable.value ) {
+                Some( value ) => value,
+                None => return Err( format!( "{}: environment variable `{}` has no value", self.source(), name ).into() )
+            };
+
+            output.push( EnvVariable { name, value, expose: variable.expose } );
+        }
+
+        Ok( output )
     }
 }
 
-pub enum Warning {
+pub enum WarningKind {
     UnknownKey( String ),
     InvalidValue( String ),
     Deprecation( String, Option< String > )
 }
 
+impl WarningKind {
+    fn key( &self ) -> &str {
+        match *self {
+            WarningKind::UnknownKey( ref key ) |
+            WarningKind::InvalidValue( ref key ) |
+            WarningKind::Deprecation( ref key, _ ) => key
+        }
+    }
+}
+
+pub struct Warning {
+    pub kind: WarningKind,
+    /// The file in which the offending key is defined.
+    pub path: PathBuf,
+    pub location: Option< Location >,
+    /// The key which was most likely meant in case of a typo.
+    pub suggestion: Option< &'static str >
+}
+
+fn suggest_key( key_path: &str ) -> Option< &'static str > {
+    let (section, key) = match key_path.rfind( '.' ) {
+        Some( index ) => (&key_path[ ..index ], &key_path[ index + 1.. ]),
+        None => ("", key_path)
+    };
+
+    config_diagnostics::closest_match( key, &config_schema::known_keys( &SCHEMA, section ) )
+}
+
+fn toml_error( config: &Config, source: &str, description: &str, error: toml::de::Error ) -> Error {
+    // The position is already a part of the code frame.
+    let mut message = error.to_string();
+    if let Some( index ) = message.rfind( " at line " ) {
+        message.truncate( index );
+    }
+
+    let location = error.line_col().and_then( |(line, column)| config_diagnostics::locate_position( source, line, column ) );
+    let (path, location) = match (config.config_path.as_ref(), location) {
+        (Some( path ), Some( location )) => (path, location),
+        _ => return Error::ConfigurationError( format!( "{}: {}: {}", config.source(), description, message ) )
+    };
+
+    let diagnostic = config_diagnostics::to_diagnostic( "error", message.clone(), path, Some( &location ), None, None );
+    Error::ConfigurationError( format!( "{}: {}: {}\n{}", config.source(), description, message, render_code_frame( &diagnostic ).trim_end() ) )
+}
+
 fn add_link_args( config: &mut Config, backend: Backend, link_args: Vec< String > ) -> Result< (), Error > {
     {
         let per_target = config.per_target.entry( backend ).or_insert( Default::default() );
@@ -95,12 +313,378 @@ fn add_prepend_js( config: &mut Config, backend: Backend, prepend_js: Vec< Strin
     return Err( format!( "{}: you can't have multiple 'prepend-js' defined for a single target", config.source() ).into() );
 }
 
+fn add_profile( config: &mut Config, backend: Option< Backend >, build_type: BuildType, profile: PerProfileConfig ) -> Result< (), Error > {
+    let source = config.source();
+    let existing = match backend {
+        Some( backend ) => config.per_target_profile.entry( (backend, build_type) ).or_insert( Default::default() ),
+        None => config.per_profile.entry( build_type ).or_insert( Default::default() )
+    };
+
+    macro_rules! set {
+        ($field:ident, $key:expr) => {
+            if let Some( value ) = profile.$field {
+                if existing.$field.is_some() {
+                    return Err( format!( "{}: you can't have multiple '{}' defined for a single target and profile", source, $key ).into() );
+                }
+
+                existing.$field = Some( value );
+            }
+        }
+    }
+
+    set!( link_args, "link-args" );
+    set!( prepend_js, "prepend-js" );
+    set!( runtime, "runtime" );
+    set!( wasm_opt, "wasm-opt" );
+    set!( env_file, "env-file" );
+
+    for (name, variable) in profile.env {
+        if existing.env.contains_key( &name ) {
+            return Err( format!( "{}: you can't have multiple definitions of the environment variable `{}` for a single target and profile", source, name ).into() );
+        }
+
+        existing.env.insert( name, variable );
+    }
+
+    Ok(())
+}
+
+fn add_profiles( config: &mut Config, backends: Option< &[Backend] >, profiles: RawProfiles ) -> Result< (), Error > {
+    let profiles = profiles.dev.map( |profile| (BuildType::Debug, profile) ).into_iter()
+        .chain( profiles.release.map( |profile| (BuildType::Release, profile) ) );
+
+    for (build_type, profile) in profiles {
+        let runtime = match profile.runtime {
+            Some( runtime ) => {
+                let kind = RuntimeKind::from_str( &runtime ).map_err( |_| format!( "{}: `runtime` has an invalid value: `{}`", config.source(), runtime ) )?;
+                Some( kind )
+            },
+            None => None
+        };
+
+        let profile = PerProfileConfig {
+            link_args: profile.link_args,
+            prepend_js: profile.prepend_js.map( |StringOrArray( prepend_js )| prepend_js ),
+            runtime,
+            wasm_opt: profile.wasm_opt.map( |wasm_opt| WasmOptConfig { level: wasm_opt.level.map( |level| level.as_str().to_owned() ) } ),
+            env: profile.env.into_iter().map( |(name, RawEnvValue( variable ))| (name, variable) ).collect(),
+            env_file: profile.env_file
+        };
+
+        match backends {
+            Some( backends ) => {
+                for backend in backends.iter().cloned() {
+                    add_profile( config, Some( backend ), build_type, profile.clone() )?;
+                }
+            },
+            None => add_profile( config, None, build_type, profile )?
+        }
+    }
+
+    Ok(())
+}
+
+// Parses either a plain number of bytes or a string like `"250 KB"` or `"1.5MiB"`.
+fn parse_size( value: &toml::Value ) -> Option< u64 > {
+    let text = match *value {
+        toml::Value::Integer( size ) if size >= 0 => return Some( size as u64 ),
+        toml::Value::String( ref text ) => text.trim(),
+        _ => return None
+    };
+
+    let split_at = text.find( |ch: char| !ch.is_digit( 10 ) && ch != '.' ).unwrap_or( text.len() );
+    let (number, unit) = text.split_at( split_at );
+    let number: f64 = number.parse().ok()?;
+    let multiplier = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "kib" => 1024,
+        "mb" => 1000 * 1000,
+        "mib" => 1024 * 1024,
+        _ => return None
+    };
+
+    Some( (number * multiplier as f64).round() as u64 )
+}
+
+const WEB_TOML: &'static str = "Web.toml";
+const MANIFEST_SECTION: &'static str = "`[package.metadata.web]`";
+const MANIFEST_PREFIX: &'static str = "package.metadata.web";
+
+// A file from which the configuration is loaded.
+struct ConfigFile {
+    path: PathBuf,
+    contents: String,
+    // The path of the table which holds the configuration inside of the file.
+    prefix: &'static str,
+    value: toml::Value
+}
+
+impl ConfigFile {
+    fn key_path( &self, key: &str ) -> String {
+        if self.prefix.is_empty() {
+            key.to_owned()
+        } else {
+            format!( "{}.{}", self.prefix, key )
+        }
+    }
+
+    fn defines( &self, key: &str ) -> bool {
+        key.split( '.' ).fold( Some( &self.value ), |value, segment| value.and_then( |value| value.get( segment ) ) ).is_some()
+    }
+}
+
+// Merges the `source` into the `target`, collecting the keys which are defined in both.
+fn merge( target: &mut toml::value::Table, source: toml::value::Table, prefix: &str, conflicts: &mut Vec< String > ) {
+    for (key, value) in source {
+        let path = if prefix.is_empty() { key.clone() } else { format!( "{}.{}", prefix, key ) };
+        if !target.contains_key( &key ) {
+            target.insert( key, value );
+            continue;
+        }
+
+        match (target.get_mut( &key ).unwrap(), value) {
+            (&mut toml::Value::Table( ref mut existing ), toml::Value::Table( value )) => merge( existing, value, &path, conflicts ),
+            _ => conflicts.push( path )
+        }
+    }
+}
+
 const ALL_BACKENDS: &'static [Backend] = &[
     Backend::EmscriptenAsmJs,
     Backend::EmscriptenWebAssembly,
     Backend::WebAssembly
 ];
 
+struct StringOrArray( Vec< String > );
+
+impl< 'de > Deserialize< 'de > for StringOrArray {
+    fn deserialize< D >( deserializer: D ) -> Result< Self, D::Error > where D: Deserializer< 'de > {
+        struct Visitor;
+        impl< 'de > de::Visitor< 'de > for Visitor {
+            type Value = StringOrArray;
+
+            fn expecting( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+                write!( formatter, "either a string or an array of strings" )
+            }
+
+            fn visit_str< E >( self, value: &str ) -> Result< Self::Value, E > where E: de::Error {
+                Ok( StringOrArray( vec![ value.to_owned() ] ) )
+            }
+
+            fn visit_seq< A >( self, mut seq: A ) -> Result< Self::Value, A::Error > where A: de::SeqAccess< 'de > {
+                let mut output = Vec::new();
+                while let Some( element ) = seq.next_element()? {
+                    output.push( element );
+                }
+
+                Ok( StringOrArray( output ) )
+            }
+        }
+
+        deserializer.deserialize_any( Visitor )
+    }
+}
+
+struct Size( u64 );
+
+impl< 'de > Deserialize< 'de > for Size {
+    fn deserialize< D >( deserializer: D ) -> Result< Self, D::Error > where D: Deserializer< 'de > {
+        let value = toml::Value::deserialize( deserializer )?;
+        parse_size( &value ).map( Size ).ok_or_else( || {
+            de::Error::custom( "expected either a number of bytes or a string like \"250 KB\"" )
+        })
+    }
+}
+
+// Either a plain value or a table like `{ value = "...", expose = true }`.
+struct RawEnvValue( EnvVarConfig );
+
+impl< 'de > Deserialize< 'de > for RawEnvValue {
+    fn deserialize< D >( deserializer: D ) -> Result< Self, D::Error > where D: Deserializer< 'de > {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Table {
+            value: Option< toml::Value >,
+            #[serde(default)]
+            expose: bool
+        }
+
+        fn stringify< E >( value: toml::Value ) -> Result< String, E > where E: de::Error {
+            match value {
+                toml::Value::String( value ) => Ok( value ),
+                toml::Value::Integer( value ) => Ok( value.to_string() ),
+                toml::Value::Float( value ) => Ok( value.to_string() ),
+                toml::Value::Boolean( value ) => Ok( value.to_string() ),
+                _ => Err( de::Error::custom( "expected either a string, a number, a boolean or a table with a `value` and `expose` keys" ) )
+            }
+        }
+
+        let value = toml::Value::deserialize( deserializer )?;
+        if value.is_table() {
+            let table: Table = value.try_into().map_err( de::Error::custom )?;
+            let value = match table.value {
+                Some( value ) => Some( stringify( value )? ),
+                None => None
+            };
+
+            Ok( RawEnvValue( EnvVarConfig { value, expose: table.expose } ) )
+        } else {
+            Ok( RawEnvValue( EnvVarConfig { value: Some( stringify( value )? ), expose: false } ) )
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Credentials {
+    Omit,
+    SameOrigin,
+    Include
+}
+
+impl Credentials {
+    fn as_str( &self ) -> &'static str {
+        match *self {
+            Credentials::Omit => "omit",
+            Credentials::SameOrigin => "same-origin",
+            Credentials::Include => "include"
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq)]
+enum Cache {
+    #[serde(rename = "none")] Disabled,
+    #[serde(rename = "indexeddb")] IndexedDb
+}
+
+#[derive(Deserialize)]
+enum OptimizationLevel {
+    #[serde(rename = "0")] O0,
+    #[serde(rename = "1")] O1,
+    #[serde(rename = "2")] O2,
+    #[serde(rename = "3")] O3,
+    #[serde(rename = "4")] O4,
+    #[serde(rename = "s")] Os,
+    #[serde(rename = "z")] Oz
+}
+
+impl OptimizationLevel {
+    fn as_str( &self ) -> &'static str {
+        match *self {
+            OptimizationLevel::O0 => "0",
+            OptimizationLevel::O1 => "1",
+            OptimizationLevel::O2 => "2",
+            OptimizationLevel::O3 => "3",
+            OptimizationLevel::O4 => "4",
+            OptimizationLevel::Os => "s",
+            OptimizationLevel::Oz => "z"
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawCargoWebSection {
+    minimum_version: Option< String >
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawLoaderSection {
+    wasm_base_url: Option< String >,
+    credentials: Option< Credentials >,
+    cache: Option< Cache >,
+    integrity: Option< bool >
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawPwaSection {
+    name: Option< String >,
+    short_name: Option< String >,
+    description: Option< String >,
+    start_url: Option< String >,
+    display: Option< String >,
+    theme_color: Option< String >,
+    background_color: Option< String >,
+    icons: Option< Vec< PwaIcon > >
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawBudgetSection {
+    max_wasm_size: Option< Size >,
+    max_js_size: Option< Size >,
+    max_gzipped_size: Option< Size >,
+    warn_only: Option< bool >
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawWasmOptSection {
+    level: Option< OptimizationLevel >
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawThreadsSection {
+    pool_size: Option< u32 >,
+    stack_size: Option< Size >
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawProfileSection {
+    link_args: Option< Vec< String > >,
+    prepend_js: Option< StringOrArray >,
+    runtime: Option< String >,
+    wasm_opt: Option< RawWasmOptSection >,
+    #[serde(default)]
+    env: BTreeMap< String, RawEnvValue >,
+    env_file: Option< String >
+}
+
+#[derive(Deserialize)]
+struct RawProfiles {
+    dev: Option< RawProfileSection >,
+    release: Option< RawProfileSection >
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawTargetSection {
+    link_args: Option< Vec< String > >,
+    prepend_js: Option< StringOrArray >,
+    profile: Option< RawProfiles >
+}
+
+// The contents of a `Web.toml` as they're deserialized; the unknown keys
+// are ignored here since they're detected separately through the schema.
+//
+// The unknown targets are kept as they are so that their contents, whatever
+// those may be, don't turn what's only a warning into a hard error.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawConfig {
+    link_args: Option< Vec< String > >,
+    prepend_js: Option< StringOrArray >,
+    default_target: Option< String >,
+    cargo_web: Option< RawCargoWebSection >,
+    loader: Option< RawLoaderSection >,
+    pwa: Option< RawPwaSection >,
+    budget: Option< RawBudgetSection >,
+    wasm_opt: Option< RawWasmOptSection >,
+    threads: Option< RawThreadsSection >,
+    #[serde(default)]
+    env: BTreeMap< String, RawEnvValue >,
+    env_file: Option< String >,
+    profile: Option< RawProfiles >,
+    #[serde(default)]
+    target: BTreeMap< String, toml::Value >
+}
+
 impl Config {
     pub fn load_from_file< P >(
             path: P,
@@ -109,13 +693,21 @@ impl Config {
         ) -> Result< Option< (Self, Vec< Warning >) >, Error > where P: AsRef< Path >
     {
         let path = path.as_ref();
-
         let mut config = Config::default();
         config.config_path = Some( path.into() );
         config.crate_name = crate_name.clone();
 
-        let config_toml = match read( path ) {
-            Ok( config ) => config,
+        let web_toml = match Config::read_web_toml( &config, path )? {
+            Some( web_toml ) => web_toml,
+            None => return Ok( None )
+        };
+
+        Config::load( config, is_main_crate, vec![ web_toml ] ).map( Some )
+    }
+
+    fn read_web_toml( config: &Config, path: &Path ) -> Result< Option< ConfigFile >, Error > {
+        let contents = match read( path ) {
+            Ok( contents ) => contents,
             Err( error ) => {
                 if error.kind() == io::ErrorKind::NotFound {
                     return Ok( None );
@@ -127,174 +719,597 @@ impl Config {
 
         debug!( "Loading {:?}...", path );
 
-        let raw: toml::Value = toml::from_str( config_toml.as_str() ).unwrap();
-        let mut warnings = Vec::new();
-
-        // TODO: This is getting way too long. Split it into multiple functions.
-        trace!( "Loaded config: {:#?}", raw );
-        match raw {
-            toml::Value::Table( table ) => {
-                for (toplevel_key, toplevel_value) in table {
-                    match toplevel_key.as_str() {
-                        // TODO: Remove this in the future.
-                        "link-args" => {
-                            let link_args: Vec< String > =
-                                toplevel_value.try_into().map_err( |_|
-                                    format!( "{}: 'link-args' is not an array of strings", config.source()
-                                ))?;
-
-                            warnings.push( Warning::Deprecation(
-                                "link-args".to_owned(),
-                                Some( "it should be moved to the '[target.emscripten]' section".to_owned() )
-                            ));
-
-                            for backend in ALL_BACKENDS.iter().cloned() {
-                                add_link_args( &mut config, backend, link_args.clone() )?;
-                            }
-                        },
-                        "prepend-js" => {
-                            let toplevel_value = from_string_or_array_of_strings( &toplevel_key, &config, toplevel_value )?;
-                            for backend in ALL_BACKENDS.iter().cloned() {
-                                add_prepend_js( &mut config, backend, toplevel_value.clone() )?;
-                            }
-                        },
-                        "default-target" => {
-                            let default_target: String =
-                                toplevel_value.try_into().map_err( |_|
-                                    format!( "{}: 'default-target' is not a string", config.source()
-                                ))?;
-
-                            let default_target = match default_target.as_str() {
-                                "wasm32-unknown-unknown" => Backend::WebAssembly,
-                                "wasm32-unknown-emscripten" => Backend::EmscriptenWebAssembly,
-                                "asmjs-unknown-emscripten" => Backend::EmscriptenAsmJs,
-                                _ => {
-                                    if is_main_crate {
-                                        return Err( format!( "{}: `default-target` has an invalid value: `{}`", config.source(), default_target ).into() );
-                                    } else {
-                                        warnings.push( Warning::InvalidValue( toplevel_key.clone() ) );
-                                    }
-
-                                    continue;
-                                }
-                            };
-
-                            config.default_target = Some( default_target );
-                        },
-                        "cargo-web" => {
-                            let cargo_web_table: toml::value::Table =
-                                toplevel_value.try_into()
-                                .map_err( |_| format!( "{}: 'cargo-web' should be a section", config.source() ) )?;
-
-                            for (cargo_web_key, cargo_web_value) in cargo_web_table {
-                                match cargo_web_key.as_str() {
-                                    "minimum-version" => {
-                                        let version: String = cargo_web_value.try_into().map_err( |_| format!( "{}: 'cargo-web.minimum-version' is not a string", config.source() ) )?;
-                                        let version = Version::parse( &version ).map_err( |_| format!( "{}: 'cargo-web.minimum-version' is not a valid version", config.source() ) )?;
-                                        config.minimum_cargo_web_version = Some( version );
-                                    },
-                                    cargo_web_key => {
-                                        warnings.push( Warning::UnknownKey( format!( "cargo-web.{}", cargo_web_key ) ) );
-                                    }
-                                }
-                            }
-                        },
-                        "target" => {
-                            let target_table: toml::value::Table =
-                                toplevel_value.try_into()
-                                .map_err( |_| format!( "{}: 'target' should be a section", config.source() ) )?;
-
-                            for (target_key, target_value) in target_table {
-                                let backends = match target_key.as_str() {
-                                    "wasm32-unknown-unknown" => &[Backend::WebAssembly][..],
-                                    "wasm32-unknown-emscripten" => &[Backend::EmscriptenWebAssembly][..],
-                                    "asmjs-unknown-emscripten" => &[Backend::EmscriptenAsmJs][..],
-                                    "emscripten" => &[Backend::EmscriptenWebAssembly, Backend::EmscriptenAsmJs][..],
-                                    target_key => {
-                                        warnings.push( Warning::UnknownKey( format!( "target.{}", target_key ) ) );
-                                        continue;
-                                    }
-                                };
-
-                                let target_subtable: toml::value::Table =
-                                    target_value.try_into()
-                                    .map_err( |_| format!( "{}: 'target.{}' should be a section", config.source(), target_key ) )?;
-
-                                for (per_target_key, per_target_value) in target_subtable {
-                                    let path_in_toml = format!( "target.{}.{}", target_key, per_target_key );
-                                    match per_target_key.as_str() {
-                                        "link-args" => {
-                                            let link_args: Vec< String > =
-                                                per_target_value.try_into().map_err( |_|
-                                                    format!(
-                                                        "{}: '{}' is not an array of strings",
-                                                        config.source(),
-                                                        path_in_toml
-                                                    )
-                                                )?;
-
-                                            for backend in backends.iter().cloned() {
-                                                add_link_args( &mut config, backend, link_args.clone() )?;
-                                            }
-                                        },
-                                        "prepend-js" => {
-                                            let per_target_value = from_string_or_array_of_strings( &path_in_toml, &config, per_target_value )?;
-                                            for backend in backends.iter().cloned() {
-                                                add_prepend_js( &mut config, backend, per_target_value.clone() )?;
-                                            }
-                                        },
-                                        _ => {
-                                            warnings.push( Warning::UnknownKey( path_in_toml ) );
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        toplevel_key => {
-                            warnings.push( Warning::UnknownKey( toplevel_key.into() ) );
-                        }
+        let value: toml::Value = toml::from_str( &contents ).map_err( |error| toml_error( config, &contents, "invalid TOML", error ) )?;
+        trace!( "Loaded config: {:#?}", value );
+
+        // This is only done to report type errors with their location.
+        let _: RawConfig = toml::from_str( &contents ).map_err( |error| toml_error( config, &contents, "invalid configuration", error ) )?;
+
+        Ok( Some( ConfigFile {
+            path: path.to_owned(),
+            contents,
+            prefix: "",
+            value
+        }))
+    }
+
+    fn read_manifest_section( config: &Config, package: &CargoPackage ) -> Result< Option< ConfigFile >, Error > {
+        let metadata = match package.metadata.get( "web" ) {
+            Some( metadata ) => metadata,
+            None => return Ok( None )
+        };
+
+        debug!( "Loading `[package.metadata.web]` from {:?}...", package.manifest_path );
+
+        let value = toml::Value::try_from( metadata ).map_err( |error| {
+            format!( "{}: cannot convert `[package.metadata.web]`: {}", config.source(), error )
+        })?;
+
+        if !value.is_table() {
+            return Err( format!( "{}: `package.metadata.web` should be a section", config.source() ).into() );
+        }
+
+        let _: RawConfig = value.clone().try_into().map_err( |error| {
+            format!( "{}: invalid configuration in `[package.metadata.web]`: {}", config.source(), error )
+        })?;
+
+        // The manifest was already parsed by cargo; we only need it to find the keys.
+        let contents = read( &package.manifest_path ).unwrap_or_default();
+        Ok( Some( ConfigFile {
+            path: package.manifest_path.clone(),
+            contents,
+            prefix: MANIFEST_PREFIX,
+            value
+        }))
+    }
+
+    fn load( mut config: Config, is_main_crate: bool, files: Vec< ConfigFile > ) -> Result< (Self, Vec< Warning >), Error > {
+        let mut value = toml::value::Table::new();
+        let mut conflicts = Vec::new();
+        for file in &files {
+            if let toml::Value::Table( ref table ) = file.value {
+                merge( &mut value, table.clone(), "", &mut conflicts );
+            }
+        }
+
+        if !conflicts.is_empty() {
+            let conflicts: Vec< _ > = conflicts.iter().map( |key| format!( "`{}`", key ) ).collect();
+            return Err( format!(
+                "{}: the following keys are defined both in the Web.toml and in the `[package.metadata.web]` of the Cargo.toml: {}",
+                config.source(),
+                conflicts.join( ", " )
+            ).into() );
+        }
+
+        let value = toml::Value::Table( value );
+        let raw: RawConfig = value.clone().try_into().map_err( |error| format!( "{}: invalid configuration: {}", config.source(), error ) )?;
+        let mut warnings: Vec< _ > = config_schema::unknown_keys( &SCHEMA, &value ).into_iter().map( WarningKind::UnknownKey ).collect();
+
+        // TODO: Remove this in the future.
+        if let Some( link_args ) = raw.link_args {
+            warnings.push( WarningKind::Deprecation(
+                "link-args".to_owned(),
+                Some( "it should be moved to the '[target.emscripten]' section".to_owned() )
+            ));
+
+            for backend in ALL_BACKENDS.iter().cloned() {
+                add_link_args( &mut config, backend, link_args.clone() )?;
+            }
+        }
+
+        if let Some( StringOrArray( prepend_js ) ) = raw.prepend_js {
+            for backend in ALL_BACKENDS.iter().cloned() {
+                add_prepend_js( &mut config, backend, prepend_js.clone() )?;
+            }
+        }
+
+        if let Some( default_target ) = raw.default_target {
+            match default_target.as_str() {
+                "wasm32-unknown-unknown" => config.default_target = Some( Backend::WebAssembly ),
+                "wasm32-unknown-emscripten" => config.default_target = Some( Backend::EmscriptenWebAssembly ),
+                "asmjs-unknown-emscripten" => config.default_target = Some( Backend::EmscriptenAsmJs ),
+                _ => {
+                    if is_main_crate {
+                        return Err( format!( "{}: `default-target` has an invalid value: `{}`", config.source(), default_target ).into() );
+                    } else {
+                        warnings.push( WarningKind::InvalidValue( "default-target".to_owned() ) );
                     }
                 }
-            },
-            _ => panic!()
+            }
         }
 
-        Ok( Some( (config, warnings) ) )
+        if let Some( version ) = raw.cargo_web.and_then( |cargo_web| cargo_web.minimum_version ) {
+            let version = Version::parse( &version ).map_err( |_| format!( "{}: 'cargo-web.minimum-version' is not a valid version", config.source() ) )?;
+            config.minimum_cargo_web_version = Some( version );
+        }
+
+        if let Some( loader ) = raw.loader {
+            config.loader.wasm_base_url = loader.wasm_base_url;
+            config.loader.credentials = loader.credentials.map( |credentials| credentials.as_str().to_owned() );
+            config.loader.cache_in_indexeddb = loader.cache == Some( Cache::IndexedDb );
+            config.loader.integrity = loader.integrity.unwrap_or( false );
+        }
+
+        if let Some( pwa ) = raw.pwa {
+            config.pwa = PwaConfig {
+                name: pwa.name,
+                short_name: pwa.short_name,
+                description: pwa.description,
+                start_url: pwa.start_url,
+                display: pwa.display,
+                theme_color: pwa.theme_color,
+                background_color: pwa.background_color,
+                icons: pwa.icons.unwrap_or_default()
+            };
+        }
+
+        if let Some( budget ) = raw.budget {
+            config.budget = BudgetConfig {
+                max_wasm_size: budget.max_wasm_size.map( |Size( size )| size ),
+                max_js_size: budget.max_js_size.map( |Size( size )| size ),
+                max_gzipped_size: budget.max_gzipped_size.map( |Size( size )| size ),
+                warn_only: budget.warn_only.unwrap_or( false )
+            };
+        }
+
+        if let Some( level ) = raw.wasm_opt.and_then( |wasm_opt| wasm_opt.level ) {
+            config.wasm_opt.level = Some( level.as_str().to_owned() );
+        }
+
+        if let Some( threads ) = raw.threads {
+            if threads.pool_size == Some( 0 ) {
+                return Err( format!( "{}: 'threads.pool-size' cannot be zero", config.source() ).into() );
+            }
+
+            config.threads = ThreadsConfig {
+                pool_size: threads.pool_size,
+                stack_size: threads.stack_size.map( |Size( size )| size )
+            };
+        }
+
+        config.env = raw.env.into_iter().map( |(name, RawEnvValue( variable ))| (name, variable) ).collect();
+        config.env_file = raw.env_file;
+
+        if let Some( profiles ) = raw.profile {
+            add_profiles( &mut config, None, profiles )?;
+        }
+
+        for (target_key, target) in raw.target {
+            let backends = match target_key.as_str() {
+                "wasm32-unknown-unknown" => &[Backend::WebAssembly][..],
+                "wasm32-unknown-emscripten" => &[Backend::EmscriptenWebAssembly][..],
+                "asmjs-unknown-emscripten" => &[Backend::EmscriptenAsmJs][..],
+                "emscripten" => &[Backend::EmscriptenWebAssembly, Backend::EmscriptenAsmJs][..],
+                // This was already reported as an unknown key.
+                _ => continue
+            };
+
+            let target: RawTargetSection = target.try_into().map_err( |error| {
+                format!( "{}: invalid configuration in `[target.{}]`: {}", config.source(), target_key, error )
+            })?;
+
+            if let Some( link_args ) = target.link_args {
+                for backend in backends.iter().cloned() {
+                    add_link_args( &mut config, backend, link_args.clone() )?;
+                }
+            }
+
+            if let Some( StringOrArray( prepend_js ) ) = target.prepend_js {
+                for backend in backends.iter().cloned() {
+                    add_prepend_js( &mut config, backend, prepend_js.clone() )?;
+                }
+            }
+
+            if let Some( profiles ) = target.profile {
+                add_profiles( &mut config, Some( backends ), profiles )?;
+            }
+        }
+
+        let warnings = warnings.into_iter().map( |kind| {
+            let file = files.iter().find( |file| file.defines( kind.key() ) ).unwrap_or( &files[ 0 ] );
+            let location = config_diagnostics::locate_key( &file.contents, &file.key_path( kind.key() ) );
+            let suggestion = match kind {
+                WarningKind::UnknownKey( ref key ) => suggest_key( key ),
+                _ => None
+            };
+
+            Warning { kind, path: file.path.clone(), location, suggestion }
+        }).collect();
+
+        Ok( (config, warnings) )
     }
 
+    /// Loads the configuration from both the `Web.toml` and the `[package.metadata.web]`
+    /// section of the `Cargo.toml`; they're merged and a key can be defined only in one of them.
     pub fn load_for_package( package: &CargoPackage, is_main_crate: bool ) -> Result< Option< (Self, Vec< Warning >) >, Error > {
-        let path = package.manifest_path.with_file_name( "Web.toml" );
-        let config = match Config::load_from_file( path, Some( package.name.clone() ), is_main_crate )? {
-            None => return Ok( None ),
-            Some( config ) => config
+        let path = package.manifest_path.with_file_name( WEB_TOML );
+        let mut config = Config::default();
+        config.config_path = Some( path.clone() );
+        config.crate_name = Some( package.name.clone() );
+
+        let web_toml = Config::read_web_toml( &config, &path )?;
+        let manifest_section = Config::read_manifest_section( &config, package )?;
+
+        config.origin = match (web_toml.is_some(), manifest_section.is_some()) {
+            (true, false) => WEB_TOML,
+            (false, true) => MANIFEST_SECTION,
+            (true, true) => "Web.toml and `[package.metadata.web]`",
+            (false, false) => return Ok( None )
         };
 
-        Ok( Some( config ) )
+        if web_toml.is_none() {
+            config.config_path = Some( package.manifest_path.clone() );
+        }
+
+        let files = web_toml.into_iter().chain( manifest_section.into_iter() ).collect();
+        Config::load( config, is_main_crate, files ).map( Some )
     }
 
-    pub fn load_for_package_printing_warnings( package: &CargoPackage, is_main_crate: bool ) -> Result< Option< Self >, Error > {
+    pub fn load_for_package_printing_warnings( package: &CargoPackage, is_main_crate: bool, message_format: MessageFormat ) -> Result< Option< Self >, Error > {
         let (config, warnings) = match Config::load_for_package( package, is_main_crate )? {
             Some( (config, warnings) ) => (config, warnings),
             None => return Ok( None )
         };
 
         for warning in warnings {
-            match warning {
-                Warning::UnknownKey( key ) => {
-                    eprintln!( "warning: unknown key in {}: {}", config.source(), key );
+            let (message, label) = match warning.kind {
+                WarningKind::UnknownKey( key ) => {
+                    (format!( "unknown key in {}: {}", config.source(), key ), "unknown key")
                 },
-                Warning::Deprecation( key, None ) => {
-                    eprintln!( "warning: key in {} is deprecated: {}", config.source(), key );
+                WarningKind::Deprecation( key, None ) => {
+                    (format!( "key in {} is deprecated: {}", config.source(), key ), "deprecated")
                 },
-                Warning::Deprecation( key, Some( description ) ) => {
-                    eprintln!( "warning: key in {} is deprecated: {} ({})", config.source(), key, description );
+                WarningKind::Deprecation( key, Some( description ) ) => {
+                    (format!( "key in {} is deprecated: {} ({})", config.source(), key, description ), "deprecated")
                 },
-                Warning::InvalidValue( key ) => {
-                    eprintln!( "warning: key `{}` in {} has an invalid value", key, config.source() );
+                WarningKind::InvalidValue( key ) => {
+                    (format!( "key `{}` in {} has an invalid value", key, config.source() ), "invalid value")
                 }
-            }
+            };
+
+            let help = warning.suggestion.map( |suggestion| format!( "did you mean `{}`?", suggestion ) );
+            let diagnostic = config_diagnostics::to_diagnostic( "warning", message, &warning.path, warning.location.as_ref(), Some( label ), help );
+            messages::diagnostic( message_format, &diagnostic );
         }
 
         Ok( Some( config ) )
     }
 }
+
+#[test]
+fn test_parse_size() {
+    assert_eq!( parse_size( &toml::Value::Integer( 1234 ) ), Some( 1234 ) );
+    assert_eq!( parse_size( &toml::Value::String( "250 KB".into() ) ), Some( 250000 ) );
+    assert_eq!( parse_size( &toml::Value::String( "1.5MiB".into() ) ), Some( 1572864 ) );
+    assert_eq!( parse_size( &toml::Value::String( "100".into() ) ), Some( 100 ) );
+    assert_eq!( parse_size( &toml::Value::String( "lots".into() ) ), None );
+    assert_eq!( parse_size( &toml::Value::Integer( -1 ) ), None );
+}
+
+#[test]
+fn test_warnings_and_syntax_errors() {
+    use tempfile;
+    use utils::write;
+
+    let tmpdir = tempfile::Builder::new().prefix( "cargo-web-config-test" ).tempdir().unwrap();
+    let path = tmpdir.path().join( "Web.toml" );
+
+    write( &path, "[loader]\ncach = \"indexeddb\"\n" ).unwrap();
+    let (_, warnings) = Config::load_from_file( &path, None, true ).unwrap().unwrap();
+    assert_eq!( warnings.len(), 1 );
+    assert_eq!( warnings[ 0 ].kind.key(), "loader.cach" );
+    assert_eq!( warnings[ 0 ].suggestion, Some( "cache" ) );
+    assert_eq!( warnings[ 0 ].location.as_ref().map( |location| (location.line, location.column_start) ), Some( (2, 1) ) );
+
+    write( &path, "[loader]\ncache = \n" ).unwrap();
+    match Config::load_from_file( &path, None, true ) {
+        Err( Error::ConfigurationError( message ) ) => assert!( message.contains( "--> " ), "{}", message ),
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_load_typed_config() {
+    use tempfile;
+    use utils::write;
+
+    let tmpdir = tempfile::Builder::new().prefix( "cargo-web-config-test" ).tempdir().unwrap();
+    let path = tmpdir.path().join( "Web.toml" );
+
+    write( &path, r#"
+        link-args = ["-s", "USE_SDL=2"]
+
+        [loader]
+        credentials = "include"
+        cache = "indexeddb"
+
+        [budget]
+        max-wasm-size = "1 KiB"
+
+        [wasm-opt]
+        level = "s"
+
+        [target.wasm32-unknown-unknown]
+        prepend-js = ["src/a.js", "src/b.js"]
+    "# ).unwrap();
+
+    let (config, warnings) = Config::load_from_file( &path, None, true ).unwrap().unwrap();
+    assert_eq!( config.get_link_args( Backend::EmscriptenAsmJs, BuildType::Debug ), Some( vec![ "-s".to_owned(), "USE_SDL=2".to_owned() ] ) );
+    assert_eq!( config.loader.credentials, Some( "include".to_owned() ) );
+    assert!( config.loader.cache_in_indexeddb );
+    assert_eq!( config.budget.max_wasm_size, Some( 1024 ) );
+    assert_eq!( config.wasm_opt.level, Some( "s".to_owned() ) );
+    assert_eq!( config.get_prepend_js( Backend::WebAssembly, BuildType::Debug ), Some( &vec![ "src/a.js".to_owned(), "src/b.js".to_owned() ] ) );
+
+    assert_eq!( warnings.len(), 1 );
+    match warnings[ 0 ].kind {
+        WarningKind::Deprecation( ref key, _ ) => assert_eq!( key, "link-args" ),
+        _ => panic!()
+    }
+
+    write( &path, "[loader]\ncredentials = \"sometimes\"\n" ).unwrap();
+    assert!( Config::load_from_file( &path, None, true ).is_err() );
+}
+
+#[test]
+fn test_load_loader_section() {
+    use tempfile;
+    use utils::write;
+
+    let tmpdir = tempfile::Builder::new().prefix( "cargo-web-config-test" ).tempdir().unwrap();
+    let path = tmpdir.path().join( "Web.toml" );
+
+    write( &path, "[loader]\n" ).unwrap();
+    let (config, warnings) = Config::load_from_file( &path, None, true ).unwrap().unwrap();
+    assert_eq!( config.loader.wasm_base_url, None );
+    assert_eq!( config.loader.credentials, None );
+    assert!( !config.loader.cache_in_indexeddb );
+    assert!( !config.loader.integrity );
+    assert!( warnings.is_empty() );
+
+    write( &path, r#"
+        [loader]
+        wasm-base-url = "https://cdn.example.com/"
+        credentials = "same-origin"
+        cache = "none"
+        integrity = true
+    "# ).unwrap();
+
+    let (config, warnings) = Config::load_from_file( &path, None, true ).unwrap().unwrap();
+    assert_eq!( config.loader.wasm_base_url, Some( "https://cdn.example.com/".to_owned() ) );
+    assert_eq!( config.loader.credentials, Some( "same-origin".to_owned() ) );
+    assert!( !config.loader.cache_in_indexeddb );
+    assert!( config.loader.integrity );
+    assert!( warnings.is_empty() );
+
+    write( &path, "[loader]\ncredentials = \"omit\"\n" ).unwrap();
+    let (config, _) = Config::load_from_file( &path, None, true ).unwrap().unwrap();
+    assert_eq!( config.loader.credentials, Some( "omit".to_owned() ) );
+
+    write( &path, "[loader]\ncache = \"localstorage\"\n" ).unwrap();
+    assert!( Config::load_from_file( &path, None, true ).is_err() );
+
+    write( &path, "[loader]\nintegrity = \"yes\"\n" ).unwrap();
+    assert!( Config::load_from_file( &path, None, true ).is_err() );
+
+    write( &path, "[loader]\nwasm-base-url = 1\n" ).unwrap();
+    assert!( Config::load_from_file( &path, None, true ).is_err() );
+}
+
+#[test]
+fn test_load_with_manifest_section() {
+    fn manifest( contents: &str ) -> ConfigFile {
+        let value: toml::Value = toml::from_str( contents ).unwrap();
+        ConfigFile {
+            path: PathBuf::from( "Cargo.toml" ),
+            contents: contents.to_owned(),
+            prefix: MANIFEST_PREFIX,
+            value: value.get( "package" ).and_then( |package| package.get( "metadata" ) ).and_then( |metadata| metadata.get( "web" ) ).unwrap().clone()
+        }
+    }
+
+    let web_toml_contents = "[loader]\ncache = \"indexeddb\"\n";
+    let web_toml = ConfigFile {
+        path: PathBuf::from( "Web.toml" ),
+        contents: web_toml_contents.to_owned(),
+        prefix: "",
+        value: toml::from_str( web_toml_contents ).unwrap()
+    };
+
+    let manifest_section = manifest( "[package]\nname = \"foo\"\n\n[package.metadata.web.loader]\ncredentials = \"include\"\ncach = \"none\"\n" );
+    let (config, warnings) = Config::load( Config::default(), true, vec![ web_toml, manifest_section ] ).unwrap();
+    assert!( config.loader.cache_in_indexeddb );
+    assert_eq!( config.loader.credentials, Some( "include".to_owned() ) );
+
+    assert_eq!( warnings.len(), 1 );
+    assert_eq!( warnings[ 0 ].path, PathBuf::from( "Cargo.toml" ) );
+    assert_eq!( warnings[ 0 ].location.as_ref().map( |location| location.line ), Some( 6 ) );
+
+    let web_toml = ConfigFile {
+        path: PathBuf::from( "Web.toml" ),
+        contents: web_toml_contents.to_owned(),
+        prefix: "",
+        value: toml::from_str( web_toml_contents ).unwrap()
+    };
+
+    let manifest_section = manifest( "[package.metadata.web.loader]\ncache = \"none\"\n" );
+    match Config::load( Config::default(), true, vec![ web_toml, manifest_section ] ) {
+        Err( error ) => assert!( error.to_string().contains( "`loader.cache`" ) ),
+        Ok( _ ) => panic!()
+    }
+}
+
+#[test]
+fn test_load_profiles() {
+    use tempfile;
+    use utils::write;
+
+    let tmpdir = tempfile::Builder::new().prefix( "cargo-web-config-test" ).tempdir().unwrap();
+    let path = tmpdir.path().join( "Web.toml" );
+
+    write( &path, r#"
+        prepend-js = "src/prelude.js"
+
+        [wasm-opt]
+        level = "s"
+
+        [profile.dev.wasm-opt]
+        level = "1"
+
+        [profile.release]
+        prepend-js = "src/prelude.min.js"
+        runtime = "library-es6"
+
+        [target.emscripten]
+        link-args = ["-s", "USE_SDL=2"]
+
+        [target.emscripten.profile.dev]
+        link-args = ["-s", "ASSERTIONS=1"]
+
+        [target.wasm32-unknown-unknown.profile.release]
+        runtime = "web-extension"
+    "# ).unwrap();
+
+    let (config, warnings) = Config::load_from_file( &path, None, true ).unwrap().unwrap();
+    assert!( warnings.is_empty() );
+
+    assert_eq!( config.get_link_args( Backend::EmscriptenAsmJs, BuildType::Debug ), Some( vec![
+        "-s".to_owned(), "USE_SDL=2".to_owned(), "-s".to_owned(), "ASSERTIONS=1".to_owned()
+    ]));
+    assert_eq!( config.get_link_args( Backend::EmscriptenAsmJs, BuildType::Release ), Some( vec![ "-s".to_owned(), "USE_SDL=2".to_owned() ] ) );
+    assert_eq!( config.get_link_args( Backend::WebAssembly, BuildType::Debug ), None );
+
+    assert_eq!( config.get_prepend_js( Backend::WebAssembly, BuildType::Debug ), Some( &vec![ "src/prelude.js".to_owned() ] ) );
+    assert_eq!( config.get_prepend_js( Backend::WebAssembly, BuildType::Release ), Some( &vec![ "src/prelude.min.js".to_owned() ] ) );
+
+    assert_eq!( config.get_runtime( Backend::WebAssembly, BuildType::Debug ), None );
+    assert_eq!( config.get_runtime( Backend::WebAssembly, BuildType::Release ), Some( RuntimeKind::WebExtension ) );
+    assert_eq!( config.get_runtime( Backend::EmscriptenWebAssembly, BuildType::Release ), Some( RuntimeKind::LibraryEs6 ) );
+
+    assert_eq!( config.get_wasm_opt( Backend::WebAssembly, BuildType::Debug ).level, Some( "1".to_owned() ) );
+    assert_eq!( config.get_wasm_opt( Backend::WebAssembly, BuildType::Release ).level, Some( "s".to_owned() ) );
+
+    write( &path, "[profile.release]\nruntime = \"node\"\n" ).unwrap();
+    assert!( Config::load_from_file( &path, None, true ).is_err() );
+
+    write( &path, "[target.emscripten.profile.dev]\nlink-args = []\n\n[target.asmjs-unknown-emscripten.profile.dev]\nlink-args = []\n" ).unwrap();
+    assert!( Config::load_from_file( &path, None, true ).is_err() );
+}
+
+#[test]
+fn test_load_env() {
+    use tempfile;
+    use utils::write;
+
+    let tmpdir = tempfile::Builder::new().prefix( "cargo-web-config-test" ).tempdir().unwrap();
+    let path = tmpdir.path().join( "Web.toml" );
+
+    write( tmpdir.path().join( ".env" ), "CARGO_WEB_TEST_API_URL=http://localhost:8080\nCARGO_WEB_TEST_TOKEN=secret\n" ).unwrap();
+    write( &path, r#"
+        env-file = ".env"
+
+        [env]
+        CARGO_WEB_TEST_API_URL = { expose = true }
+        CARGO_WEB_TEST_RETRIES = 3
+
+        [profile.release.env]
+        CARGO_WEB_TEST_API_URL = "https://api.example.com"
+    "# ).unwrap();
+
+    let (config, warnings) = Config::load_from_file( &path, None, true ).unwrap().unwrap();
+    assert!( warnings.is_empty() );
+
+    let variable = |name: &str, value: &str, expose: bool| EnvVariable { name: name.to_owned(), value: value.to_owned(), expose };
+    assert_eq!( config.get_env( Backend::WebAssembly, BuildType::Debug ).unwrap(), vec![
+        variable( "CARGO_WEB_TEST_API_URL", "http://localhost:8080", true ),
+        variable( "CARGO_WEB_TEST_RETRIES", "3", false ),
+        variable( "CARGO_WEB_TEST_TOKEN", "secret", false )
+    ]);
+
+    assert_eq!( config.get_env( Backend::WebAssembly, BuildType::Release ).unwrap()[ 0 ], variable( "CARGO_WEB_TEST_API_URL", "https://api.example.com", true ) );
+
+    write( &path, "[env]\nCARGO_WEB_TEST_MISSING = { expose = true }\n" ).unwrap();
+    let (config, _) = Config::load_from_file( &path, None, true ).unwrap().unwrap();
+    assert!( config.get_env( Backend::WebAssembly, BuildType::Debug ).is_err() );
+}
+
+#[test]
+fn test_raw_config_matches_the_schema() {
+    use serde::de::{Visitor, value};
+    use serde::forward_to_deserialize_any;
+
+    // A deserializer which only records the names of the fields of the struct it's asked for.
+    struct FieldNames< 'a >( &'a mut &'static [&'static str] );
+
+    impl< 'a, 'de > Deserializer< 'de > for FieldNames< 'a > {
+        type Error = value::Error;
+
+        fn deserialize_any< V: Visitor< 'de > >( self, _: V ) -> Result< V::Value, Self::Error > {
+            Err( de::Error::custom( "expected a struct" ) )
+        }
+
+        fn deserialize_struct< V: Visitor< 'de > >( self, _: &'static str, fields: &'static [&'static str], _: V ) -> Result< V::Value, Self::Error > {
+            *self.0 = fields;
+            Err( de::Error::custom( "only the field names are needed" ) )
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    fn field_names< T >() -> Vec< &'static str > where T: for< 'de > Deserialize< 'de > {
+        let mut fields: &'static [&'static str] = &[];
+        assert!( T::deserialize( FieldNames( &mut fields ) ).is_err() );
+
+        let mut fields = fields.to_vec();
+        fields.sort();
+        fields
+    }
+
+    let mut sections: Vec< (String, _) > = vec![
+        ("".to_owned(), field_names::< RawConfig >()),
+        ("cargo-web".to_owned(), field_names::< RawCargoWebSection >()),
+        ("loader".to_owned(), field_names::< RawLoaderSection >()),
+        ("pwa".to_owned(), field_names::< RawPwaSection >()),
+        ("budget".to_owned(), field_names::< RawBudgetSection >()),
+        ("wasm-opt".to_owned(), field_names::< RawWasmOptSection >()),
+        ("threads".to_owned(), field_names::< RawThreadsSection >()),
+        ("profile".to_owned(), field_names::< RawProfiles >()),
+        ("profile.dev".to_owned(), field_names::< RawProfileSection >()),
+        ("profile.release".to_owned(), field_names::< RawProfileSection >()),
+        ("profile.release.wasm-opt".to_owned(), field_names::< RawWasmOptSection >())
+    ];
+
+    for target in &[ "wasm32-unknown-unknown", "wasm32-unknown-emscripten", "asmjs-unknown-emscripten", "emscripten" ] {
+        sections.push( (format!( "target.{}", target ), field_names::< RawTargetSection >()) );
+        sections.push( (format!( "target.{}.profile.dev", target ), field_names::< RawProfileSection >()) );
+    }
+
+    for (section, fields) in sections {
+        let mut known_keys = config_schema::known_keys( &SCHEMA, &section );
+        known_keys.sort();
+        assert_eq!( known_keys, fields, "the schema of `{}` doesn't match its `Raw*` struct", section );
+    }
+
+    assert_eq!( config_schema::known_keys( &SCHEMA, "target" ).len(), 4 );
+}
+
+#[test]
+fn test_unknown_targets_are_only_warnings() {
+    use tempfile;
+    use utils::write;
+
+    let tmpdir = tempfile::Builder::new().prefix( "cargo-web-config-test" ).tempdir().unwrap();
+    let path = tmpdir.path().join( "Web.toml" );
+
+    write( &path, "[target.wasm32]\nlink-args = 1\nprofile = \"release\"\n" ).unwrap();
+    let (_, warnings) = Config::load_from_file( &path, None, true ).unwrap().unwrap();
+    assert_eq!( warnings.len(), 1 );
+    assert_eq!( warnings[ 0 ].kind.key(), "target.wasm32" );
+
+    write( &path, "[target.emscripten]\nlink-args = 1\n" ).unwrap();
+    assert!( Config::load_from_file( &path, None, true ).is_err() );
+}
@@ -0,0 +1,229 @@
+use std::cmp::{max, min};
+use std::path::Path;
+
+use cargo_shim::{Diagnostic, DiagnosticSpan, DiagnosticSpanLine};
+
+/// A location of something inside of a `Web.toml`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Location {
+    /// 1-based.
+    pub line: usize,
+    /// 1-based, character offset.
+    pub column_start: usize,
+    pub column_end: usize,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub line_text: String
+}
+
+impl Location {
+    fn new( line_number: usize, line_offset: usize, line: &str, start: usize, end: usize ) -> Self {
+        let column_start = line[ ..start ].chars().count() + 1;
+        let column_end = column_start + max( line[ start..end ].chars().count(), 1 );
+        Location {
+            line: line_number + 1,
+            column_start,
+            column_end,
+            byte_start: line_offset + start,
+            byte_end: line_offset + end,
+            line_text: line.to_owned()
+        }
+    }
+}
+
+fn strip_comment( line: &str ) -> &str {
+    let mut quote = None;
+    for (index, ch) in line.char_indices() {
+        match (quote, ch) {
+            (None, '#') => return &line[ ..index ],
+            (None, '"') | (None, '\'') => quote = Some( ch ),
+            (Some( current ), _) if current == ch => quote = None,
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn normalize_key( key: &str ) -> String {
+    let segments: Vec< _ > = key.split( '.' )
+        .map( |segment| segment.trim().trim_matches( '"' ).trim_matches( '\'' ) )
+        .collect();
+
+    segments.join( "." )
+}
+
+/// Finds where the key with the given dotted path (e.g. `target.emscripten.link-args`)
+/// is defined; for sections this points at the section's header.
+///
+/// This is only a line based scan since the `toml` crate doesn't keep
+/// any spans, but it's good enough for the keys which we're interested in.
+pub fn locate_key( source: &str, key_path: &str ) -> Option< Location > {
+    let mut table = String::new();
+    let mut line_offset = 0;
+    for (line_number, line) in source.split( '\n' ).enumerate() {
+        let offset = line_offset;
+        line_offset += line.len() + 1;
+
+        let line = line.trim_end_matches( '\r' );
+        let content = strip_comment( line );
+        let trimmed = content.trim_start();
+        let indentation = content.len() - trimmed.len();
+
+        if trimmed.starts_with( '[' ) {
+            let inner = trimmed.trim_start_matches( '[' );
+            let end = match inner.find( ']' ) {
+                Some( end ) => end,
+                None => continue
+            };
+
+            let name = &inner[ ..end ];
+            table = normalize_key( name );
+            if table == key_path {
+                let start = indentation + (trimmed.len() - inner.len()) + (name.len() - name.trim_start().len());
+                return Some( Location::new( line_number, offset, line, start, start + name.trim().len() ) );
+            }
+
+            continue;
+        }
+
+        let key = match trimmed.find( '=' ) {
+            Some( end ) => trimmed[ ..end ].trim_end(),
+            None => continue
+        };
+
+        if key.is_empty() {
+            continue;
+        }
+
+        let path = if table.is_empty() {
+            normalize_key( key )
+        } else {
+            format!( "{}.{}", table, normalize_key( key ) )
+        };
+
+        if path == key_path {
+            return Some( Location::new( line_number, offset, line, indentation, indentation + key.len() ) );
+        }
+    }
+
+    None
+}
+
+/// Converts a 0-based line and byte column, as reported by the `toml` crate, into a `Location`.
+pub fn locate_position( source: &str, line_number: usize, column: usize ) -> Option< Location > {
+    let line_offset: usize = source.split( '\n' ).take( line_number ).map( |line| line.len() + 1 ).sum();
+    let line = source.split( '\n' ).nth( line_number )?.trim_end_matches( '\r' );
+
+    let mut start = min( column, line.len() );
+    while !line.is_char_boundary( start ) {
+        start -= 1;
+    }
+
+    let end = line[ start.. ].chars().next().map( |ch| start + ch.len_utf8() ).unwrap_or( start );
+    Some( Location::new( line_number, line_offset, line, start, end ) )
+}
+
+fn edit_distance( a: &str, b: &str ) -> usize {
+    let b: Vec< char > = b.chars().collect();
+    let mut previous: Vec< usize > = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![ i + 1 ];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[ j ] + if ca == cb { 0 } else { 1 };
+            current.push( min( substitution, min( previous[ j + 1 ], current[ j ] ) + 1 ) );
+        }
+
+        previous = current;
+    }
+
+    previous[ b.len() ]
+}
+
+/// Returns the candidate which the `name` is most likely a typo of, if any.
+pub fn closest_match< 'a >( name: &str, candidates: &[&'a str] ) -> Option< &'a str > {
+    let max_distance = max( name.chars().count(), 3 ) / 3;
+    candidates.iter()
+        .map( |&candidate| (edit_distance( name, candidate ), candidate) )
+        .filter( |&(distance, _)| distance <= max_distance )
+        .min_by_key( |&(distance, _)| distance )
+        .map( |(_, candidate)| candidate )
+}
+
+pub fn to_diagnostic(
+    level: &str,
+    message: String,
+    path: &Path,
+    location: Option< &Location >,
+    label: Option< &str >,
+    help: Option< String >
+) -> Diagnostic {
+    let spans = location.into_iter().map( |location| {
+        DiagnosticSpan {
+            file_name: path.to_string_lossy().into_owned(),
+            byte_start: location.byte_start as u32,
+            byte_end: location.byte_end as u32,
+            line_start: location.line,
+            line_end: location.line,
+            column_start: location.column_start,
+            column_end: location.column_end,
+            is_primary: true,
+            text: vec![ DiagnosticSpanLine {
+                text: location.line_text.clone(),
+                highlight_start: location.column_start,
+                highlight_end: location.column_end
+            }],
+            label: label.map( |label| label.to_owned() ),
+            suggested_replacement: None,
+            expansion: None
+        }
+    }).collect();
+
+    let children = help.into_iter().map( |help| {
+        Diagnostic {
+            message: help,
+            code: None,
+            level: "help".to_owned(),
+            spans: Vec::new(),
+            children: Vec::new(),
+            rendered: None
+        }
+    }).collect();
+
+    Diagnostic {
+        message,
+        code: None,
+        level: level.to_owned(),
+        spans,
+        children,
+        rendered: None
+    }
+}
+
+#[test]
+fn test_locate_key() {
+    let source = "prepend-js = \"foo.js\"\n\n[loader]\n# cache = \"none\"\n  cach = \"indexeddb\"\n\n[target.\"emscripten\"]\nlink-arg = [\"-s\"] # link-args\n";
+
+    let location = locate_key( source, "prepend-js" ).unwrap();
+    assert_eq!( (location.line, location.column_start, location.column_end), (1, 1, 11) );
+
+    let location = locate_key( source, "loader.cach" ).unwrap();
+    assert_eq!( (location.line, location.column_start, location.column_end), (5, 3, 7) );
+    assert_eq!( &source[ location.byte_start..location.byte_end ], "cach" );
+
+    let location = locate_key( source, "target.emscripten" ).unwrap();
+    assert_eq!( (location.line, location.column_start), (7, 2) );
+
+    let location = locate_key( source, "target.emscripten.link-arg" ).unwrap();
+    assert_eq!( location.line, 8 );
+
+    assert_eq!( locate_key( source, "loader.cache" ), None );
+}
+
+#[test]
+fn test_closest_match() {
+    let candidates = &[ "prepend-js", "default-target", "loader" ];
+    assert_eq!( closest_match( "prepend_js", candidates ), Some( "prepend-js" ) );
+    assert_eq!( closest_match( "loadr", candidates ), Some( "loader" ) );
+    assert_eq!( closest_match( "something-else", candidates ), None );
+}
@@ -0,0 +1,266 @@
+use serde_json::{Map, Value};
+use toml;
+
+fn string( description: &str ) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+fn boolean( description: &str ) -> Value {
+    json!({ "type": "boolean", "description": description })
+}
+
+fn one_of( description: &str, values: &[&str] ) -> Value {
+    json!({ "type": "string", "enum": values, "description": description })
+}
+
+fn string_or_array( description: &str ) -> Value {
+    json!({
+        "description": description,
+        "anyOf": [
+            { "type": "string" },
+            { "type": "array", "items": { "type": "string" } }
+        ]
+    })
+}
+
+fn size( description: &str ) -> Value {
+    json!({
+        "description": description,
+        "anyOf": [
+            { "type": "integer", "minimum": 0 },
+            { "type": "string", "pattern": "^\\s*[0-9.]+\\s*([kKmM]i?[bB]|[bB])?\\s*$" }
+        ]
+    })
+}
+
+fn section( description: &str, properties: Value ) -> Value {
+    json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "additionalProperties": false
+    })
+}
+
+fn link_args( description: &str ) -> Value {
+    json!({
+        "type": "array",
+        "items": { "type": "string" },
+        "description": description
+    })
+}
+
+fn wasm_opt_section( description: &str ) -> Value {
+    section( description, json!({
+        "level": one_of( "The optimization level passed as `-O<level>`.", &[ "0", "1", "2", "3", "4", "s", "z" ] )
+    }))
+}
+
+fn env_section( description: &str ) -> Value {
+    let value = json!({ "type": [ "string", "number", "boolean" ] });
+    json!({
+        "type": "object",
+        "description": description,
+        "propertyNames": { "pattern": "^[A-Za-z_][A-Za-z0-9_]*$" },
+        "additionalProperties": {
+            "anyOf": [
+                value.clone(),
+                {
+                    "type": "object",
+                    "properties": {
+                        "value": value,
+                        "expose": boolean( "Whether the variable is also available to the JavaScript through `Module.env`." )
+                    },
+                    "additionalProperties": false
+                }
+            ]
+        }
+    })
+}
+
+fn per_profile_section( description: &str ) -> Value {
+    section( description, json!({
+        "link-args": link_args( "Extra arguments passed to the linker; appended to the ones from the less specific sections." ),
+        "prepend-js": string_or_array( "JavaScript file(s) prepended to the `.js` artifact; replaces the ones from the less specific sections." ),
+        "runtime": one_of(
+            "The type of JavaScript runtime generated for `wasm32-unknown-unknown` unless `--runtime` is specified.",
+            &[ "standalone", "library-es6", "web-extension", "experimental-only-loader" ]
+        ),
+        "wasm-opt": wasm_opt_section( "Settings of binaryen's `wasm-opt`; replaces the top-level `[wasm-opt]` section." ),
+        "env": env_section( "Environment variables set when compiling the crate; override the ones from the less specific sections." ),
+        "env-file": string( "A `.env` file from which the environment variables are loaded." )
+    }))
+}
+
+fn profiles_section( description: &str ) -> Value {
+    section( description, json!({
+        "dev": per_profile_section( "Settings used only for debug builds." ),
+        "release": per_profile_section( "Settings used only for `--release` builds." )
+    }))
+}
+
+fn per_target_section( description: &str ) -> Value {
+    section( description, json!({
+        "link-args": link_args( "Extra arguments passed to the linker; only supported on the Emscripten targets." ),
+        "prepend-js": string_or_array( "JavaScript file(s) prepended to the `.js` artifact of this target." ),
+        "profile": profiles_section( "Settings used only for this target and the given profile." )
+    }))
+}
+
+/// Returns the JSON Schema of `Web.toml`.
+///
+/// Besides being exported through `cargo web config schema` this
+/// is also what's used to detect unknown keys when loading the config.
+pub fn schema() -> Value {
+    let targets = json!({
+        "wasm32-unknown-unknown": per_target_section( "Settings used only when targeting `wasm32-unknown-unknown`." ),
+        "wasm32-unknown-emscripten": per_target_section( "Settings used only when targeting `wasm32-unknown-emscripten`." ),
+        "asmjs-unknown-emscripten": per_target_section( "Settings used only when targeting `asmjs-unknown-emscripten`." ),
+        "emscripten": per_target_section( "Settings used for both of the Emscripten targets." )
+    });
+
+    let mut deprecated_link_args = link_args( "Deprecated; use `link-args` in the `[target.emscripten]` section instead." );
+    deprecated_link_args[ "deprecated" ] = Value::Bool( true );
+
+    let mut schema = section( "The configuration of `cargo-web`.", json!({
+        "link-args": deprecated_link_args,
+        "prepend-js": string_or_array( "JavaScript file(s) prepended to the `.js` artifact." ),
+        "default-target": one_of(
+            "The target used when `--target` is not specified on the command line.",
+            &[ "wasm32-unknown-unknown", "wasm32-unknown-emscripten", "asmjs-unknown-emscripten" ]
+        ),
+        "cargo-web": section( "Requirements on `cargo-web` itself.", json!({
+            "minimum-version": string( "The minimum version of `cargo-web` necessary to compile this crate." )
+        })),
+        "loader": section( "Settings of the runtime generated for `wasm32-unknown-unknown`.", json!({
+            "wasm-base-url": string( "The base URL from which the `.wasm` file will be fetched." ),
+            "credentials": one_of( "The credentials mode used when fetching the `.wasm` file.", &[ "omit", "same-origin", "include" ] ),
+            "cache": one_of( "Where the compiled module is cached in the browser.", &[ "none", "indexeddb" ] ),
+            "integrity": boolean( "Whether the `.wasm` file is verified against its SHA-384 digest." )
+        })),
+        "pwa": section( "Used by `cargo web deploy --pwa` to generate the web app manifest.", json!({
+            "name": string( "The name of the application." ),
+            "short-name": string( "The short name of the application." ),
+            "description": string( "The description of the application." ),
+            "start-url": string( "The URL which is loaded when the application is launched." ),
+            "display": string( "The preferred display mode, e.g. `standalone`." ),
+            "theme-color": string( "The default theme color." ),
+            "background-color": string( "The background color of the splash screen." ),
+            "icons": {
+                "type": "array",
+                "description": "The icons of the application.",
+                "items": {
+                    "type": "object",
+                    "required": [ "src" ],
+                    "properties": {
+                        "src": string( "The path of the icon." ),
+                        "sizes": string( "The sizes of the icon, e.g. `192x192`." ),
+                        "type": string( "The MIME type of the icon." )
+                    }
+                }
+            }
+        })),
+        "budget": section( "Size limits checked after the artifacts are generated.", json!({
+            "max-wasm-size": size( "The maximum size of the `.wasm` file." ),
+            "max-js-size": size( "The maximum size of the `.js` file." ),
+            "max-gzipped-size": size( "The maximum total size of the `.wasm` and `.js` files after gzip compression." ),
+            "warn-only": boolean( "Whether exceeding the budget only prints a warning instead of failing the build." )
+        })),
+        "wasm-opt": wasm_opt_section( "Settings of binaryen's `wasm-opt` which is run on `--release` builds." ),
+        "threads": section( "Settings used when the crate is compiled with shared memory.", json!({
+            "pool-size": { "type": "integer", "minimum": 1, "description": "The maximum number of worker threads." },
+            "stack-size": size( "The size of the stack of every worker thread." )
+        })),
+        "env": env_section( "Environment variables set when compiling the crate, so they're available through `env!()`." ),
+        "env-file": string( "A `.env` file from which the environment variables are loaded." ),
+        "profile": profiles_section( "Settings which only apply to debug or `--release` builds." ),
+        "target": section( "Settings which only apply to a specific target.", targets )
+    }));
+
+    schema[ "$schema" ] = Value::String( "http://json-schema.org/draft-07/schema#".to_owned() );
+    schema[ "title" ] = Value::String( "Web.toml".to_owned() );
+    schema
+}
+
+fn properties( schema: &Value ) -> Option< &Map< String, Value > > {
+    schema.get( "properties" ).and_then( |properties| properties.as_object() )
+}
+
+/// Returns the keys which are valid inside of the section with the given dotted path.
+pub fn known_keys< 'a >( schema: &'a Value, section: &str ) -> Vec< &'a str > {
+    let mut current = Some( schema );
+    for segment in section.split( '.' ).filter( |segment| !segment.is_empty() ) {
+        current = current.and_then( properties ).and_then( |properties| properties.get( segment ) );
+    }
+
+    current.and_then( properties )
+        .map( |properties| properties.keys().map( |key| key.as_str() ).collect() )
+        .unwrap_or_default()
+}
+
+fn collect_unknown_keys( schema: &Value, table: &toml::value::Table, prefix: &str, output: &mut Vec< String > ) {
+    for (key, value) in table {
+        let path = if prefix.is_empty() { key.clone() } else { format!( "{}.{}", prefix, key ) };
+        match properties( schema ).and_then( |properties| properties.get( key ) ) {
+            Some( subschema ) => {
+                if let toml::Value::Table( ref subtable ) = *value {
+                    if properties( subschema ).is_some() {
+                        collect_unknown_keys( subschema, subtable, &path, output );
+                    }
+                }
+            },
+            None => output.push( path )
+        }
+    }
+}
+
+/// Returns the dotted paths of every key which isn't in the schema.
+pub fn unknown_keys( schema: &Value, value: &toml::Value ) -> Vec< String > {
+    let mut output = Vec::new();
+    if let toml::Value::Table( ref table ) = *value {
+        collect_unknown_keys( schema, table, "", &mut output );
+    }
+
+    output
+}
+
+#[test]
+fn test_unknown_keys() {
+    let value: toml::Value = toml::from_str( r#"
+        prepend-js = "foo.js"
+        prepend_js = "foo.js"
+
+        [loader]
+        cache = "none"
+        cach = "indexeddb"
+
+        [target.emscripten]
+        link-args = []
+        link-arg = []
+
+        [target.wasm32]
+        prepend-js = "foo.js"
+
+        [[pwa.icons]]
+        src = "icon.png"
+    "# ).unwrap();
+
+    let schema = schema();
+    assert_eq!( unknown_keys( &schema, &value ), vec![
+        "loader.cach".to_owned(),
+        "prepend_js".to_owned(),
+        "target.emscripten.link-arg".to_owned(),
+        "target.wasm32".to_owned()
+    ]);
+}
+
+#[test]
+fn test_known_keys() {
+    let schema = schema();
+    assert_eq!( known_keys( &schema, "wasm-opt" ), vec![ "level" ] );
+    assert!( known_keys( &schema, "target.emscripten" ).contains( &"link-args" ) );
+    assert!( known_keys( &schema, "" ).contains( &"default-target" ) );
+    assert_eq!( known_keys( &schema, "profile" ), vec![ "dev", "release" ] );
+    assert!( known_keys( &schema, "target.emscripten.profile.release" ).contains( &"runtime" ) );
+    assert!( known_keys( &schema, "nonexistent" ).is_empty() );
+}
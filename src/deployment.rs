@@ -6,16 +6,21 @@ use std::fs::{self, File};
 use handlebars::Handlebars;
 use walkdir::WalkDir;
 use mime_guess::{Mime, guess_mime_type};
+use serde_json;
+use sha1::Sha1;
 
 use cargo_shim::{
+    MessageFormat,
     TargetKind,
     CargoPackage,
     CargoTarget,
     CargoResult
 };
 
+use config::PwaConfig;
 use error::Error;
-use utils::{get_sha1sum, read_bytes};
+use messages;
+use utils::{get_sha1sum, get_subresource_integrity, read_bytes};
 
 // Note: newlines before the DOCTYPE break GitHub pages
 const DEFAULT_INDEX_HTML_TEMPLATE: &'static str = r#"<!DOCTYPE html>
@@ -41,19 +46,103 @@ const DEFAULT_INDEX_HTML_TEMPLATE: &'static str = r#"<!DOCTYPE html>
             }
         });
     </script>
-</head>
+{{{head}}}</head>
 <body>
-    <script src="{{{js_url}}}"></script>
+    <script>
+        window.addEventListener( "cargo-web:progress", function( event ) {
+            var progress = event.detail;
+            var indicator = document.getElementById( "cargo-web-progress" );
+            if( progress.phase === "finished" || progress.phase === "failed" ) {
+                if( indicator ) {
+                    indicator.parentNode.removeChild( indicator );
+                }
+                return;
+            }
+
+            if( !indicator ) {
+                indicator = document.createElement( "progress" );
+                indicator.id = "cargo-web-progress";
+                document.querySelector( "body" ).appendChild( indicator );
+            }
+
+            if( progress.phase === "downloading" && progress.total > 0 ) {
+                indicator.max = progress.total;
+                indicator.value = progress.loaded;
+            } else {
+                indicator.removeAttribute( "value" );
+            }
+        });
+    </script>
+    <script src="{{{js_url}}}"{{#if integrity}} integrity="{{integrity}}" crossorigin="anonymous"{{/if}}></script>
 </body>
 </html>"#;
 
-fn generate_index_html( filename: &str ) -> String {
+const PWA_HEAD_TEMPLATE: &'static str = r#"    <link rel="manifest" href="manifest.webmanifest" />
+{{#if theme_color}}    <meta name="theme-color" content="{{theme_color}}" />
+{{/if}}    <script>
+        if( "serviceWorker" in navigator ) {
+            window.addEventListener( "load", function() {
+                navigator.serviceWorker.register( "service-worker.js" );
+            });
+        }
+    </script>
+"#;
+
+const SERVICE_WORKER_TEMPLATE: &'static str = r#""use strict";
+
+var CACHE_PREFIX = {{{cache_prefix}}};
+var CACHE_NAME = {{{cache_name}}};
+var PRECACHED_URLS = {{{urls}}};
+
+self.addEventListener( "install", function( event ) {
+    event.waitUntil(
+        caches.open( CACHE_NAME )
+            .then( function( cache ) { return cache.addAll( PRECACHED_URLS ); } )
+            .then( function() { return self.skipWaiting(); } )
+    );
+});
+
+self.addEventListener( "activate", function( event ) {
+    event.waitUntil(
+        caches.keys()
+            .then( function( names ) {
+                return Promise.all( names
+                    .filter( function( name ) { return name.indexOf( CACHE_PREFIX ) === 0 && name !== CACHE_NAME; } )
+                    .map( function( name ) { return caches.delete( name ); } ) );
+            })
+            .then( function() { return self.clients.claim(); } )
+    );
+});
+
+self.addEventListener( "fetch", function( event ) {
+    if( event.request.method !== "GET" ) {
+        return;
+    }
+
+    event.respondWith(
+        caches.open( CACHE_NAME )
+            .then( function( cache ) { return cache.match( event.request, { ignoreSearch: true } ); } )
+            .then( function( response ) { return response || fetch( event.request ); } )
+    );
+});
+"#;
+
+fn generate_index_html( filename: &str, head: &str, integrity: Option< &str > ) -> String {
     let handlebars = Handlebars::new();
-    let mut template_data = BTreeMap::new();
-    template_data.insert( "js_url", filename.to_owned() );
+    let template_data = json!({
+        "js_url": filename,
+        "head": head,
+        "integrity": integrity
+    });
     handlebars.render_template( DEFAULT_INDEX_HTML_TEMPLATE, &template_data ).unwrap()
 }
 
+fn sha1sum( bytes: &[u8] ) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update( bytes );
+    format!( "{}", hasher.digest() )
+}
+
 fn are_the_same( a: &Path, b: &Path ) -> bool {
     let a_sum = get_sha1sum( a ).ok();
     let b_sum = get_sha1sum( b ).ok();
@@ -72,7 +161,11 @@ struct Route {
 }
 
 pub struct Deployment {
-    routes: Vec< Route >
+    routes: Vec< Route >,
+    index_html_head: String,
+    js_integrity: Option< String >,
+    uses_shared_memory: bool,
+    is_pwa_enabled: bool
 }
 
 pub enum ArtifactKind {
@@ -108,7 +201,7 @@ impl Artifact {
 }
 
 impl Deployment {
-    pub fn new( package: &CargoPackage, target: &CargoTarget, result: &CargoResult ) -> Result< Self, Error > {
+    pub fn new( package: &CargoPackage, target: &CargoTarget, result: &CargoResult, uses_shared_memory: bool ) -> Result< Self, Error > {
         let crate_static_path = package.crate_root.join( "static" );
         let target_static_path = match target.kind {
             TargetKind::Example => Some( target.source_directory.join( format!( "{}-static", target.name ) ) ),
@@ -142,6 +235,18 @@ impl Deployment {
                 });
             }
 
+            let source_map_path = path.with_extension( "wasm.map" );
+            if !is_js && source_map_path.exists() {
+                // These contain the absolute paths of the source files,
+                // so they're only deployed if explicitly requested.
+                let source_map = read_bytes( &source_map_path ).map_err( |error| Error::CannotLoadFile( source_map_path.clone(), error ) )?;
+                routes.push( Route {
+                    key: format!( "{}.map", key ),
+                    kind: RouteKind::Blob( source_map ),
+                    can_be_deployed: false
+                });
+            }
+
             routes.push( Route {
                 key,
                 kind: RouteKind::Blob( contents ),
@@ -165,15 +270,217 @@ impl Deployment {
 
         routes.push( Route {
             key: "index.html".to_owned(),
-            kind: RouteKind::Blob( generate_index_html( &js_name ).into() ),
+            kind: RouteKind::Blob( generate_index_html( &js_name, "", None ).into() ),
             can_be_deployed: true
         });
 
         Ok( Deployment {
-            routes
+            routes,
+            index_html_head: String::new(),
+            js_integrity: None,
+            is_pwa_enabled: false,
+            uses_shared_memory
         })
     }
 
+    /// Whether the application uses shared memory, in which case it needs
+    /// to be served with the `Cross-Origin-*-Policy` headers.
+    pub fn uses_shared_memory( &self ) -> bool {
+        self.uses_shared_memory
+    }
+
+    /// Makes the `.wasm.map` source maps part of the deployment.
+    pub fn deploy_source_maps( &mut self ) {
+        for route in &mut self.routes {
+            if route.key.ends_with( ".wasm.map" ) {
+                route.can_be_deployed = true;
+            }
+        }
+    }
+
+    /// Returns the user's own `index.html` if there's one which takes precedence over the generated one.
+    fn custom_index_html( &self ) -> Option< PathBuf > {
+        for route in &self.routes {
+            match route.kind {
+                RouteKind::Blob( _ ) if route.key == "index.html" => return None,
+                RouteKind::StaticDirectory( ref source_dir ) if source_dir.join( "index.html" ).exists() => {
+                    return Some( source_dir.join( "index.html" ) );
+                },
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    fn update_index_html( &mut self ) {
+        let index_html = generate_index_html( self.js_url(), &self.index_html_head, self.js_integrity.as_ref().map( |integrity| integrity.as_str() ) );
+        for route in &mut self.routes {
+            if route.key == "index.html" {
+                if let RouteKind::Blob( ref mut bytes ) = route.kind {
+                    *bytes = index_html.clone().into();
+                }
+            }
+        }
+    }
+
+    /// Adds a Subresource Integrity hash of the generated JavaScript
+    /// to the `<script>` tag in the default `index.html`.
+    ///
+    /// This must be called before `enable_pwa` so that the service
+    /// worker's cache gets invalidated when the `index.html` changes.
+    pub fn enable_subresource_integrity( &mut self ) -> Result< (), Error > {
+        if self.is_pwa_enabled {
+            return Err( "internal error: the subresource integrity has to be enabled before the PWA".into() );
+        }
+
+        let integrity = {
+            let js_url = self.js_url();
+            self.routes.iter().filter_map( |route| {
+                match route.kind {
+                    RouteKind::Blob( ref bytes ) if route.key == js_url => Some( get_subresource_integrity( bytes ) ),
+                    _ => None
+                }
+            }).next()
+        };
+
+        self.js_integrity = integrity;
+        self.update_index_html();
+        Ok(())
+    }
+
+    /// Turns the deployment into an installable, offline-capable
+    /// application by generating a web app manifest and a service
+    /// worker which precaches every deployed file.
+    pub fn enable_pwa( &mut self, message_format: MessageFormat, config: &PwaConfig, default_name: &str ) -> Result< (), Error > {
+        if let Some( path ) = self.custom_index_html() {
+            messages::warning( message_format, Some( &path ), &format!(
+                "{:?} replaces the generated `index.html`, so it has to register the service worker itself; \
+                see the README for the snippet to add to its `<head>`",
+                path
+            ));
+        }
+
+        self.is_pwa_enabled = true;
+
+        let handlebars = Handlebars::new();
+        self.index_html_head = handlebars.render_template( PWA_HEAD_TEMPLATE, &json!({ "theme_color": config.theme_color }) ).unwrap();
+        self.update_index_html();
+
+        let name = config.name.clone().unwrap_or_else( || default_name.to_owned() );
+        let mut manifest = json!({
+            "name": name,
+            "short_name": config.short_name.clone().unwrap_or_else( || name.clone() ),
+            "start_url": config.start_url.clone().unwrap_or_else( || ".".to_owned() ),
+            "display": config.display.clone().unwrap_or_else( || "standalone".to_owned() )
+        });
+
+        if let Some( ref description ) = config.description {
+            manifest[ "description" ] = json!( description );
+        }
+
+        if let Some( ref theme_color ) = config.theme_color {
+            manifest[ "theme_color" ] = json!( theme_color );
+        }
+
+        if let Some( ref background_color ) = config.background_color {
+            manifest[ "background_color" ] = json!( background_color );
+        }
+
+        if !config.icons.is_empty() {
+            manifest[ "icons" ] = config.icons.iter().map( |icon| {
+                let mut value = json!({ "src": icon.src });
+                if let Some( ref sizes ) = icon.sizes {
+                    value[ "sizes" ] = json!( sizes );
+                }
+                if let Some( ref mime_type ) = icon.mime_type {
+                    value[ "type" ] = json!( mime_type );
+                }
+                value
+            }).collect();
+        }
+
+        self.routes.push( Route {
+            key: "manifest.webmanifest".to_owned(),
+            kind: RouteKind::Blob( serde_json::to_string_pretty( &manifest ).unwrap().into() ),
+            can_be_deployed: true
+        });
+
+        // If the same file is provided by multiple routes then
+        // the first one wins, just as it does in `get_by_url`.
+        let mut files: BTreeMap< String, String > = BTreeMap::new();
+        for route in &self.routes {
+            if !route.can_be_deployed {
+                continue;
+            }
+
+            match route.kind {
+                RouteKind::Blob( ref bytes ) => {
+                    files.entry( route.key.clone() ).or_insert_with( || sha1sum( bytes ) );
+                },
+                RouteKind::StaticDirectory( ref source_dir ) => {
+                    if !source_dir.exists() {
+                        continue;
+                    }
+
+                    for entry in WalkDir::new( source_dir ) {
+                        let entry = entry.map_err( |err| {
+                            let err_path = err.path().map( |path| path.to_owned() ).unwrap_or_else( || source_dir.clone() );
+                            let err: io::Error = err.into();
+                            Error::CannotLoadFile( err_path, err )
+                        })?;
+
+                        let source_path = entry.path();
+                        if source_path.is_dir() {
+                            continue;
+                        }
+
+                        let relative_path = source_path.strip_prefix( source_dir ).unwrap();
+                        let key: Vec< _ > = relative_path.iter().map( |chunk| chunk.to_string_lossy().into_owned() ).collect();
+                        let key = key.join( "/" );
+                        if files.contains_key( &key ) {
+                            continue;
+                        }
+
+                        let hash = get_sha1sum( source_path ).map_err( |err| Error::CannotLoadFile( source_path.to_owned(), err ) )?;
+                        files.insert( key, hash );
+                    }
+                }
+            }
+        }
+
+        for icon in &config.icons {
+            let key = icon.src.trim_start_matches( "./" ).trim_start_matches( "/" );
+            if !files.contains_key( key ) {
+                messages::warning( message_format, None, &format!( "the icon `{}` from the `pwa` section of the Web.toml is not going to be deployed", icon.src ) );
+            }
+        }
+
+        let mut version = String::new();
+        for (key, hash) in &files {
+            version.push_str( &format!( "{} {}\n", key, hash ) );
+        }
+        let version = sha1sum( version.as_bytes() );
+
+        let mut urls = vec![ "./".to_owned() ];
+        urls.extend( files.keys().filter( |key| *key != "service-worker.js" ).cloned() );
+
+        let cache_prefix = format!( "cargo-web-{}-", name );
+        let mut template_data = BTreeMap::new();
+        template_data.insert( "cache_name", serde_json::to_string( &format!( "{}{}", cache_prefix, &version[ 0..16 ] ) ).unwrap() );
+        template_data.insert( "cache_prefix", serde_json::to_string( &cache_prefix ).unwrap() );
+        template_data.insert( "urls", serde_json::to_string( &urls ).unwrap() );
+        let service_worker = handlebars.render_template( SERVICE_WORKER_TEMPLATE, &template_data ).unwrap();
+
+        self.routes.push( Route {
+            key: "service-worker.js".to_owned(),
+            kind: RouteKind::Blob( service_worker.into() ),
+            can_be_deployed: true
+        });
+
+        Ok(())
+    }
+
     pub fn js_url( &self ) -> &str {
         let route = self.routes.iter().find( |route| route.can_be_deployed && route.key.ends_with( ".js" ) ).unwrap();
         &route.key
@@ -0,0 +1,118 @@
+fn is_valid_name( name: &str ) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some( ch ) if ch.is_ascii_alphabetic() || ch == '_' => {},
+        _ => return false
+    }
+
+    chars.all( |ch| ch.is_ascii_alphanumeric() || ch == '_' )
+}
+
+fn unescape( value: &str ) -> String {
+    let mut output = String::with_capacity( value.len() );
+    let mut chars = value.chars();
+    while let Some( ch ) = chars.next() {
+        if ch != '\\' {
+            output.push( ch );
+            continue;
+        }
+
+        match chars.next() {
+            Some( 'n' ) => output.push( '\n' ),
+            Some( 'r' ) => output.push( '\r' ),
+            Some( 't' ) => output.push( '\t' ),
+            Some( ch ) => output.push( ch ),
+            None => output.push( '\\' )
+        }
+    }
+
+    output
+}
+
+fn parse_value( value: &str ) -> Result< String, &'static str > {
+    let value = value.trim();
+    if value.starts_with( '"' ) || value.starts_with( '\'' ) {
+        let quote = &value[ ..1 ];
+        let inner = &value[ 1.. ];
+        let mut end = None;
+        let mut escaped = false;
+        for (index, ch) in inner.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' && quote == "\"" {
+                escaped = true;
+            } else if inner[ index.. ].starts_with( quote ) {
+                end = Some( index );
+                break;
+            }
+        }
+
+        let end = end.ok_or( "unterminated quoted value" )?;
+        let rest = inner[ end + 1.. ].trim_start();
+        if !rest.is_empty() && !rest.starts_with( '#' ) {
+            return Err( "unexpected characters after the quoted value" );
+        }
+
+        if quote == "\"" {
+            Ok( unescape( &inner[ ..end ] ) )
+        } else {
+            Ok( inner[ ..end ].to_owned() )
+        }
+    } else {
+        // Unquoted values can have comments at the end.
+        let value = match value.find( " #" ) {
+            Some( index ) => &value[ ..index ],
+            None => value
+        };
+
+        Ok( value.trim_end().to_owned() )
+    }
+}
+
+/// Parses the contents of a `.env` file.
+///
+/// Every line is either empty, a comment, or a `NAME=value` assignment
+/// optionally prefixed with `export`; the values can be quoted.
+pub fn parse( contents: &str ) -> Result< Vec< (String, String) >, String > {
+    let mut output = Vec::new();
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with( '#' ) {
+            continue;
+        }
+
+        let line = if line.starts_with( "export " ) { line[ "export ".len().. ].trim_start() } else { line };
+        let index = line.find( '=' ).ok_or_else( || format!( "line {}: expected `NAME=value`", line_number + 1 ) )?;
+        let name = line[ ..index ].trim();
+        if !is_valid_name( name ) {
+            return Err( format!( "line {}: invalid variable name: `{}`", line_number + 1, name ) );
+        }
+
+        let value = parse_value( &line[ index + 1.. ] ).map_err( |error| format!( "line {}: {}", line_number + 1, error ) )?;
+        output.push( (name.to_owned(), value) );
+    }
+
+    Ok( output )
+}
+
+#[test]
+fn test_parse() {
+    let contents = r#"
+        # A comment.
+        API_URL=https://api.example.com # the production API
+        export TOKEN = "a \"quoted\"\nvalue" # trailing comment
+        SINGLE='no $escapes\n'
+        EMPTY=
+    "#;
+
+    assert_eq!( parse( contents ).unwrap(), vec![
+        ("API_URL".to_owned(), "https://api.example.com".to_owned()),
+        ("TOKEN".to_owned(), "a \"quoted\"\nvalue".to_owned()),
+        ("SINGLE".to_owned(), "no $escapes\\n".to_owned()),
+        ("EMPTY".to_owned(), "".to_owned())
+    ]);
+
+    assert!( parse( "1ST=value" ).is_err() );
+    assert!( parse( "NAME" ).is_err() );
+    assert!( parse( "NAME=\"unterminated" ).is_err() );
+}
@@ -1,13 +1,16 @@
 use std::process::exit;
 use std::path::{Path, PathBuf};
 
+use cargo_shim::MessageFormat;
+use error::Error;
 use package::{
+    DownloadSettings,
     PrebuiltPackage,
     download_package
 };
 use utils::find_cmd;
 
-fn emscripten_package() -> Option< PrebuiltPackage > {
+pub fn emscripten_package() -> Option< PrebuiltPackage > {
     let package =
         if cfg!( target_os = "linux" ) && cfg!( target_arch = "x86_64" ) {
             PrebuiltPackage {
@@ -34,7 +37,7 @@ fn emscripten_package() -> Option< PrebuiltPackage > {
     Some( package )
 }
 
-fn binaryen_package() -> Option< PrebuiltPackage > {
+pub fn binaryen_package() -> Option< PrebuiltPackage > {
     let package =
         if cfg!( target_os = "linux" ) && cfg!( target_arch = "x86_64" ) {
             PrebuiltPackage {
@@ -61,7 +64,7 @@ fn binaryen_package() -> Option< PrebuiltPackage > {
     Some( package )
 }
 
-fn check_emscripten() {
+pub fn find_emcc() -> Option< &'static str > {
     let possible_commands =
         if cfg!( windows ) {
             &[ "emcc.bat" ]
@@ -69,7 +72,11 @@ fn check_emscripten() {
             &[ "emcc" ]
         };
 
-    if find_cmd( possible_commands ).is_some() {
+    find_cmd( possible_commands )
+}
+
+fn check_emscripten() {
+    if find_emcc().is_some() {
         return;
     }
 
@@ -112,20 +119,22 @@ pub struct Emscripten {
 }
 
 pub fn initialize_emscripten(
+    message_format: MessageFormat,
+    settings: &DownloadSettings,
     use_system_emscripten: bool,
     targeting_webasm: bool
-) -> Option< Emscripten > {
+) -> Result< Option< Emscripten >, Error > {
 
     if use_system_emscripten {
         check_emscripten();
-        return None;
+        return Ok( None );
     }
 
     let emscripten_package = match emscripten_package() {
         Some( pkg ) => pkg,
         None => {
             check_emscripten();
-            return None;
+            return Ok( None );
         }
     };
 
@@ -134,7 +143,7 @@ pub fn initialize_emscripten(
             Some( pkg ) => Some( pkg ),
             None => {
                 check_emscripten();
-                return None;
+                return Ok( None );
             }
         }
     } else {
@@ -142,19 +151,19 @@ pub fn initialize_emscripten(
     };
 
 
-    let emscripten_root = download_package( &emscripten_package );
+    let emscripten_root = download_package( message_format, settings, &emscripten_package )?;
     let emscripten_path = emscripten_root.join( "emscripten" );
     let emscripten_llvm_path = emscripten_root.join( "emscripten-fastcomp" );
     let binaryen_path = if let Some( binaryen_package ) = binaryen_package {
-        let binaryen_root = download_package( &binaryen_package );
+        let binaryen_root = download_package( message_format, settings, &binaryen_package )?;
         Some( binaryen_root.join( "binaryen" ) )
     } else {
         None
     };
 
-    Some( Emscripten {
+    Ok( Some( Emscripten {
         binaryen_path,
         emscripten_path,
         emscripten_llvm_path
-    })
+    }))
 }
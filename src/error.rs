@@ -4,6 +4,7 @@ use std::io;
 use std::path::PathBuf;
 
 use cargo_shim;
+use wasm_error::ProcessingError;
 
 #[derive(Debug)]
 pub enum Error {
@@ -20,6 +21,8 @@ pub enum Error {
     CannotCreateFile( PathBuf, io::Error ),
     CannotWriteToFile( PathBuf, io::Error ),
     CannotCopyFile( PathBuf, PathBuf, io::Error ),
+    InvalidWasmModule( PathBuf, String ),
+    WasmProcessingFailed( PathBuf, ProcessingError ),
     Other( Box< error::Error > )
 }
 
@@ -39,6 +42,8 @@ impl error::Error for Error {
             Error::CannotCreateFile( .. ) => "cannot create file",
             Error::CannotWriteToFile( .. ) => "cannot write to file",
             Error::CannotCopyFile( .. ) => "cannot copy file",
+            Error::InvalidWasmModule( .. ) => "the processed `.wasm` file is invalid",
+            Error::WasmProcessingFailed( .. ) => "cannot process the `.wasm` file",
             Error::Other( ref error ) => error.description()
         }
     }
@@ -81,6 +86,8 @@ impl fmt::Display for Error {
             &Error::CannotCreateFile( ref path, ref inner ) => write!( formatter, "cannot create file {:?}: {}", path, inner ),
             &Error::CannotWriteToFile( ref path, ref inner ) => write!( formatter, "cannot write to file {:?}: {}", path, inner ),
             &Error::CannotCopyFile( ref src_path, ref dst_path, ref inner ) => write!( formatter, "cannot copy file from {:?} to {:?}: {}", src_path, dst_path, inner ),
+            &Error::InvalidWasmModule( ref path, ref message ) => write!( formatter, "the processed {:?} is invalid: {} (you can pass `--skip-validation` to write it out anyway)", path, message ),
+            &Error::WasmProcessingFailed( ref path, ref inner ) => write!( formatter, "cannot process {:?}: {}", path, inner ),
             &Error::Other( ref inner ) => write!( formatter, "{}", inner ),
             _ => write!( formatter, "{}", self.description() )
         }
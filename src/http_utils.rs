@@ -8,7 +8,7 @@ use hyper::body::Payload;
 use hyper::{self, StatusCode, Request, Response, Server};
 use hyper::service::{NewService, Service};
 use hyper::server::conn::AddrIncoming;
-use hyper::header::{CONTENT_TYPE, CONTENT_LENGTH, CACHE_CONTROL, EXPIRES, PRAGMA, ACCESS_CONTROL_ALLOW_ORIGIN};
+use hyper::header::{HeaderValue, CONTENT_TYPE, CONTENT_LENGTH, CACHE_CONTROL, EXPIRES, PRAGMA, ACCESS_CONTROL_ALLOW_ORIGIN};
 use http::response::Builder;
 use memmap::Mmap;
 use mime_guess::Mime;
@@ -172,6 +172,19 @@ pub fn response_from_data( mime_type: &Mime, data: Vec< u8 > ) -> ResponseFuture
     Box::new( future::ok( sync_response_from_data( mime_type, data ) ) )
 }
 
+/// Adds the headers which browsers require before they make `SharedArrayBuffer` available.
+pub fn with_cross_origin_isolation( response: ResponseFuture ) -> ResponseFuture {
+    Box::new( response.map( |mut response| {
+        {
+            let headers = response.headers_mut();
+            headers.insert( "cross-origin-opener-policy", HeaderValue::from_static( "same-origin" ) );
+            headers.insert( "cross-origin-embedder-policy", HeaderValue::from_static( "require-corp" ) );
+        }
+
+        response
+    }))
+}
+
 pub fn response_from_status( status: StatusCode ) -> ResponseFuture {
     let mut response = sync_response_from_data(
         &"text/plain".parse().unwrap(),
@@ -63,23 +63,37 @@ mod cargo_shim;
 
 #[macro_use]
 mod utils;
+mod budget;
 mod build;
 mod chrome_devtools;
 mod cmd_build;
+mod cmd_clean;
+mod cmd_config;
 mod cmd_deploy;
+mod cmd_doctor;
+mod cmd_new;
 mod cmd_prepare_emscripten;
+mod cmd_size;
 mod cmd_start;
 mod cmd_test;
 mod config;
+mod config_diagnostics;
+mod config_schema;
 mod deployment;
+mod dotenv;
 mod emscripten;
 mod error;
 mod http_utils;
+mod messages;
 mod package;
 mod project_dirs;
 mod test_chromium;
 mod wasm;
+mod wasm_bulk_memory;
 mod wasm_context;
+mod wasm_dead_code;
+mod wasm_dwarf;
+mod wasm_error;
 mod wasm_export_main;
 mod wasm_export_table;
 mod wasm_gc;
@@ -88,7 +102,14 @@ mod wasm_inline_js;
 mod wasm_intrinsics;
 mod wasm_js_export;
 mod wasm_js_snippet;
+mod wasm_opt;
 mod wasm_runtime;
+mod wasm_sections;
+mod wasm_size;
+mod wasm_source_map;
+mod wasm_validate;
+mod wasm_stack_trace;
+mod wasm_threads;
 
 use std::ffi::OsStr;
 use std::net::{IpAddr, ToSocketAddrs};
@@ -96,6 +117,8 @@ use std::path::PathBuf;
 
 use build::{Backend, BuildArgs};
 use cargo_shim::MessageFormat;
+use cmd_clean::CleanArgs;
+use cmd_new::Template;
 use error::Error;
 use wasm_runtime::RuntimeKind;
 
@@ -111,10 +134,22 @@ pub enum CargoWebOpts {
     Build(BuildOpts),
     /// Typecheck a local package and all of its dependencies
     Check(CheckOpts),
+    /// Removes the caches and files generated by `cargo-web`
+    Clean(CleanOpts),
+    /// Inspects the configuration of `cargo-web`
+    Config(ConfigOpts),
     /// Deploys your project so that it's ready to be served statically
     Deploy(DeployOpts),
+    /// Checks whether everything necessary to build and test your project is installed
+    Doctor(DoctorOpts),
+    /// Creates a new project in an existing directory
+    Init(InitOpts),
+    /// Creates a new project
+    New(NewOpts),
     /// Fetches and installs prebuilt Emscripten packages
     PrepareEmscripten(PrepareEmscriptenOpts),
+    /// Prints a breakdown of the size of the final `.wasm` file
+    Size(SizeOpts),
     /// Runs an embedded web server, which serves the built project
     Start(StartOpts),
     /// Compiles and runs tests
@@ -137,10 +172,65 @@ pub fn run(cfg: CargoWebOpts) -> Result<(), Error> {
             build_target,
             ext,
         }) => cmd_build::command_check(BuildArgs::new(build_args, ext, build_target)?),
-        CargoWebOpts::Deploy(DeployOpts { build_args, output }) => {
-            cmd_deploy::command_deploy(build_args.into(), output)
-        }
-        CargoWebOpts::PrepareEmscripten(_) => cmd_prepare_emscripten::command_prepare_emscripten(),
+        CargoWebOpts::Clean(CleanOpts {
+            gc_snippets,
+            snippets,
+            metadata,
+            test_runner,
+            packages,
+            all,
+            dry_run,
+        }) => cmd_clean::command_clean(CleanArgs {
+            gc_snippets,
+            snippets: snippets || all,
+            metadata: metadata || all,
+            test_runner: test_runner || all,
+            packages: packages || all,
+            dry_run,
+        }),
+        CargoWebOpts::Config(ConfigOpts { command }) => match command {
+            ConfigCommand::Schema => cmd_config::command_config_schema(),
+        },
+        CargoWebOpts::Deploy(DeployOpts {
+            build_args,
+            output,
+            pwa,
+            source_maps,
+        }) => cmd_deploy::command_deploy(build_args.into(), output, pwa, source_maps),
+        CargoWebOpts::Doctor(DoctorOpts {
+            build_args,
+            build_target,
+            json,
+        }) => cmd_doctor::command_doctor(
+            BuildArgs::from(build_args).with_target(build_target),
+            json,
+        ),
+        CargoWebOpts::Init(InitOpts { path, template }) => cmd_new::command_init(
+            path,
+            template.name,
+            template.template,
+            template.template_dir,
+        ),
+        CargoWebOpts::New(NewOpts { path, template }) => cmd_new::command_new(
+            path,
+            template.name,
+            template.template,
+            template.template_dir,
+        ),
+        CargoWebOpts::PrepareEmscripten(PrepareEmscriptenOpts {
+            offline,
+            from_archive,
+        }) => cmd_prepare_emscripten::command_prepare_emscripten(offline, from_archive),
+        CargoWebOpts::Size(SizeOpts {
+            build_args,
+            build_target,
+            top,
+            json,
+        }) => cmd_size::command_size(
+            BuildArgs::from(build_args).with_target(build_target),
+            top,
+            json,
+        ),
         CargoWebOpts::Start(StartOpts {
             build_args,
             build_target,
@@ -192,6 +282,52 @@ pub struct CheckOpts {
     build_target: Target,
 }
 
+/// Options for `cargo web clean`
+///
+/// Without any flags only the unused JS snippets are removed.
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct CleanOpts {
+    /// Remove the JS snippets which aren't referenced by any build artifact
+    #[structopt(long)]
+    gc_snippets: bool,
+    /// Remove all of the JS snippets; since they're only generated when a crate
+    /// is compiled you'll have to `cargo clean` the crates which use them to get them back
+    #[structopt(long)]
+    snippets: bool,
+    /// Remove the `.cargoweb-metadata` files along with the `.js` and `.wasm` files they describe
+    #[structopt(long)]
+    metadata: bool,
+    /// Remove the cached test runner
+    #[structopt(long)]
+    test_runner: bool,
+    /// Remove the downloaded prebuilt packages (Emscripten and binaryen)
+    #[structopt(long)]
+    packages: bool,
+    /// Remove everything listed above
+    #[structopt(long)]
+    all: bool,
+    /// Only report what would be removed
+    #[structopt(long)]
+    dry_run: bool,
+}
+
+/// Options for `cargo web config`
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct ConfigOpts {
+    #[structopt(subcommand)]
+    command: ConfigCommand,
+}
+
+/// Subcommands of `cargo web config`
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum ConfigCommand {
+    /// Prints the JSON Schema of `Web.toml`
+    Schema,
+}
+
 /// Options for `cargo web deploy`
 #[derive(Debug, StructOpt)]
 #[structopt(rename_all = "kebab-case")]
@@ -199,17 +335,102 @@ pub struct DeployOpts {
     /// Output directory; the default is `$CARGO_TARGET_DIR/deploy`
     #[structopt(short = "o", long, parse(from_os_str))]
     output: Option<PathBuf>,
+    /// Generate a service worker and a web app manifest so that the
+    /// deployed application can be installed and used offline
+    #[structopt(long)]
+    pwa: bool,
+    /// Also deploy the `.wasm.map` source maps; note that
+    /// these contain the absolute paths of your source files
+    #[structopt(long)]
+    source_maps: bool,
     #[structopt(flatten)]
     build_args: Build,
 }
 
+/// Options for `cargo web doctor`
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct DoctorOpts {
+    /// Print the results as JSON
+    #[structopt(long)]
+    json: bool,
+    #[structopt(flatten)]
+    build_target: Target,
+    #[structopt(flatten)]
+    build_args: Build,
+}
+
+/// Options for `cargo web init`
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct InitOpts {
+    /// The directory in which the project will be created
+    #[structopt(parse(from_os_str), default_value = ".")]
+    path: PathBuf,
+    #[structopt(flatten)]
+    template: TemplateOpts,
+}
+
+/// Options for `cargo web new`
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct NewOpts {
+    /// The directory which will be created
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+    #[structopt(flatten)]
+    template: TemplateOpts,
+}
+
+/// Select the template of a new project
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+struct TemplateOpts {
+    /// The name of the crate; by default it's the name of the directory
+    #[structopt(long)]
+    name: Option<String>,
+    /// The kind of the project which will be generated
+    #[structopt(
+        long,
+        default_value = "app",
+        parse(try_from_str),
+        raw(possible_values = "&[\"app\", \"lib\", \"cdylib\", \"worker\"]")
+    )]
+    template: Template,
+    /// A directory with custom templates; the files from its subdirectory
+    /// named after the template override the built-in ones
+    #[structopt(long, parse(from_os_str))]
+    template_dir: Option<PathBuf>,
+}
+
 /// Options for `cargo web prepare-emscripten`
 #[derive(Debug, StructOpt)]
 #[structopt(rename_all = "kebab-case")]
 pub struct PrepareEmscriptenOpts {
-    #[doc(hidden)]
-    #[structopt(raw(set = "structopt::clap::ArgSettings::Hidden"))]
-    __reserved: bool,
+    /// Don't access the network; only use the already installed packages,
+    /// the archives passed with `--from-archive` and a local package mirror
+    #[structopt(long)]
+    offline: bool,
+    /// Install a package from a previously downloaded archive; its hash
+    /// is still verified (can be specified multiple times)
+    #[structopt(long, parse(from_os_str))]
+    from_archive: Vec<PathBuf>,
+}
+
+/// Options for `cargo web size`
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct SizeOpts {
+    /// Number of the largest functions to show
+    #[structopt(long, default_value = "20")]
+    top: usize,
+    /// Print the breakdown as JSON
+    #[structopt(long)]
+    json: bool,
+    #[structopt(flatten)]
+    build_target: Target,
+    #[structopt(flatten)]
+    build_args: Build,
 }
 
 /// Options for `cargo web start`
@@ -356,6 +577,9 @@ struct Build {
     /// Use verbose output
     #[structopt(short = "v", long)]
     pub verbose: bool,
+    /// Don't validate the `.wasm` file after processing it
+    #[structopt(long)]
+    pub skip_validation: bool,
 }
 
 impl Default for Build {
@@ -374,6 +598,7 @@ impl Default for Build {
             release: false,
             target: None,
             verbose: false,
+            skip_validation: false,
         }
     }
 }
@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json;
+use atty::{self, Stream};
+
+use cargo_shim::{Diagnostic, MessageFormat, render_diagnostic};
+
+fn emit( message: serde_json::Value ) {
+    println!( "{}", serde_json::to_string( &message ).unwrap() );
+}
+
+/// Reports a step of cargo-web's own pipeline, e.g. the processing of a `.wasm` file.
+///
+/// The `message` is what gets printed in the human readable mode.
+pub fn progress( format: MessageFormat, step: &str, path: Option< &Path >, message: &str ) {
+    match format {
+        MessageFormat::Json => {
+            emit( json!({
+                "reason": "cargo-web-progress",
+                "step": step,
+                "path": path.map( |path| path.to_string_lossy() ),
+                "message": message.trim()
+            }));
+        },
+        _ => eprintln!( "{}", message )
+    }
+}
+
+/// Reports a warning, optionally pointing at the file it relates to.
+pub fn warning( format: MessageFormat, path: Option< &Path >, message: &str ) {
+    match format {
+        MessageFormat::Json => {
+            emit( json!({
+                "reason": "cargo-web-warning",
+                "path": path.map( |path| path.to_string_lossy() ),
+                "message": message
+            }));
+        },
+        _ => eprintln!( "warning: {}", message )
+    }
+}
+
+/// Reports a warning pointing at a specific place in a file,
+/// which is printed the same way as rustc's diagnostics.
+pub fn diagnostic( format: MessageFormat, diagnostic: &Diagnostic ) {
+    match format {
+        MessageFormat::Json => {
+            emit( json!({
+                "reason": "cargo-web-warning",
+                "path": diagnostic.spans.first().map( |span| span.file_name.as_str() ),
+                "message": diagnostic.message,
+                "diagnostic": diagnostic
+            }));
+        },
+        _ => eprint!( "{}", render_diagnostic( atty::is( Stream::Stderr ), diagnostic ) )
+    }
+}
+
+/// Reports a file which was produced by cargo-web; only emitted in the JSON mode.
+pub fn artifact( format: MessageFormat, kind: &str, path: &Path ) {
+    if format != MessageFormat::Json {
+        return;
+    }
+
+    let size = fs::metadata( path ).ok().map( |metadata| metadata.len() );
+    emit( json!({
+        "reason": "cargo-web-artifact",
+        "kind": kind,
+        "path": path.to_string_lossy(),
+        "size": size
+    }));
+}
+
+/// Returns the kind under which a file produced by the build is reported.
+pub fn artifact_kind( path: &Path ) -> Option< &'static str > {
+    let filename = path.file_name()?.to_string_lossy();
+    if filename.ends_with( ".worker.wasm" ) {
+        Some( "worker-wasm" )
+    } else if filename.ends_with( ".wasm.map" ) {
+        Some( "source-map" )
+    } else if filename.ends_with( ".wasm" ) {
+        Some( "wasm" )
+    } else if filename.ends_with( ".js" ) {
+        Some( "js" )
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_artifact_kind() {
+    assert_eq!( artifact_kind( Path::new( "target/wasm32-unknown-unknown/release/foo.js" ) ), Some( "js" ) );
+    assert_eq!( artifact_kind( Path::new( "target/wasm32-unknown-unknown/release/foo.wasm" ) ), Some( "wasm" ) );
+    assert_eq!( artifact_kind( Path::new( "target/wasm32-unknown-unknown/release/foo.worker.wasm" ) ), Some( "worker-wasm" ) );
+    assert_eq!( artifact_kind( Path::new( "target/wasm32-unknown-unknown/release/foo.wasm.map" ) ), Some( "source-map" ) );
+    assert_eq!( artifact_kind( Path::new( "target/wasm32-unknown-unknown/release/foo.d" ) ), None );
+}
@@ -1,5 +1,4 @@
-use std::process::exit;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::io::{self, Read, Write};
 use std::fs;
 use std::env;
@@ -8,9 +7,9 @@ use pbr;
 use sha2;
 use reqwest::{
     header,
+    Certificate,
     Client,
     Proxy,
-    Result as ReqResult,
     Url,
 };
 
@@ -25,6 +24,9 @@ use utils::{
     unpack
 };
 
+use cargo_shim::MessageFormat;
+use error::Error;
+use messages;
 use project_dirs::PROJECT_DIRS;
 
 pub struct PrebuiltPackage {
@@ -36,9 +38,93 @@ pub struct PrebuiltPackage {
     pub size: u64,
 }
 
+/// The directory into which the package is unpacked.
+pub fn package_path( package: &PrebuiltPackage ) -> PathBuf {
+    PROJECT_DIRS.data_local_dir().join( package.name ).join( package.arch )
+}
+
+/// Returns the version of the package which is currently unpacked, if any.
+pub fn installed_version( package: &PrebuiltPackage ) -> Option< String > {
+    read( package_path( package ).join( ".version" ) ).ok()
+}
+
+/// Controls where the prebuilt packages are fetched from.
+pub struct DownloadSettings {
+    /// Never access the network; the packages have to be installed
+    /// beforehand or be available in a local `mirror` directory.
+    pub offline: bool,
+    /// Either a base URL or a local directory which contains the package archives.
+    pub mirror: Option< String >,
+    /// A PEM file with additional root certificates to trust.
+    pub ca_bundle: Option< PathBuf >
+}
+
+impl DownloadSettings {
+    pub fn from_env() -> Self {
+        DownloadSettings {
+            offline: env::var( "CARGO_WEB_OFFLINE" ).map( |value| value == "1" || value == "true" ).unwrap_or( false ),
+            mirror: env::var( "CARGO_WEB_PACKAGE_MIRROR" ).ok().filter( |value| !value.is_empty() ),
+            ca_bundle: env::var_os( "CARGO_WEB_CA_BUNDLE" ).map( PathBuf::from )
+        }
+    }
+
+    fn local_mirror( &self ) -> Option< PathBuf > {
+        let mirror = self.mirror.as_ref()?;
+        if mirror.starts_with( "http://" ) || mirror.starts_with( "https://" ) {
+            return None;
+        }
+
+        let path = if mirror.starts_with( "file://" ) { &mirror[ "file://".len().. ] } else { &mirror[..] };
+        Some( PathBuf::from( path ) )
+    }
+
+    fn url( &self, package: &PrebuiltPackage ) -> String {
+        match self.mirror {
+            Some( ref mirror ) => format!( "{}/{}", mirror.trim_end_matches( '/' ), package_filename( package ) ),
+            None => package.url.to_owned()
+        }
+    }
+}
+
+// Splits a PEM bundle into separate certificates.
+fn pem_certificates( bundle: &str ) -> Vec< &str > {
+    const BEGIN: &'static str = "-----BEGIN CERTIFICATE-----";
+    const END: &'static str = "-----END CERTIFICATE-----";
+
+    let mut output = Vec::new();
+    let mut bundle = bundle;
+    while let Some( start ) = bundle.find( BEGIN ) {
+        let end = match bundle[ start.. ].find( END ) {
+            Some( end ) => start + end + END.len(),
+            None => break
+        };
+
+        output.push( &bundle[ start..end ] );
+        bundle = &bundle[ end.. ];
+    }
+
+    output
+}
+
 // Creates a new client, supporting configuration from operating system variables if available.
-fn create_client() -> ReqResult<Client> {
-    let mut builder = Client::builder().danger_accept_invalid_certs( true );
+fn create_client( settings: &DownloadSettings ) -> Result< Client, Error > {
+    let mut builder = Client::builder();
+    if let Some( ref path ) = settings.ca_bundle {
+        let bundle = read( path ).map_err( |error| Error::CannotLoadFile( path.clone(), error ) )?;
+        let certificates = pem_certificates( &bundle );
+        if certificates.is_empty() {
+            return Err( Error::ConfigurationError( format!( "no certificates found in the CA bundle {:?}", path ) ) );
+        }
+
+        for certificate in certificates {
+            let certificate = Certificate::from_pem( certificate.as_bytes() ).map_err( |error| {
+                Error::ConfigurationError( format!( "invalid certificate in the CA bundle {:?}: {}", path, error ) )
+            })?;
+
+            builder = builder.add_root_certificate( certificate );
+        }
+    }
+
     match env::var("HTTPS_PROXY") {
         Err(_) => {},
         Ok(proxy) => { builder = builder.proxy(Proxy::https(&proxy).unwrap()); }
@@ -51,46 +137,97 @@ fn create_client() -> ReqResult<Client> {
         Err(_) => {},
         Ok(proxy) => { builder = builder.proxy(Proxy::all(&proxy).unwrap()); }
     };
-    builder.build()
+    builder.build().map_err( |error| Error::EnvironmentError( format!( "cannot create an HTTP client: {}", error ) ) )
 }
 
-pub fn download_package( package: &PrebuiltPackage ) -> PathBuf {
+fn package_filename( package: &PrebuiltPackage ) -> String {
     let url = Url::parse( package.url ).unwrap();
-    let package_filename = url.path_segments().unwrap().last().unwrap().to_owned();
+    url.path_segments().unwrap().last().unwrap().to_owned()
+}
 
-    let unpack_path = PROJECT_DIRS.data_local_dir().join( package.name ).join( package.arch );
-    let version_path = unpack_path.join( ".version" );
+fn finish_hash( hasher: sha2::Sha256 ) -> String {
+    hasher.result().map( |byte| format!( "{:02x}", byte ) ).join( "" )
+}
 
-    if let Ok( existing_version ) = read( &version_path ) {
-        if existing_version == package.version {
-            return unpack_path;
-        }
+fn hash_archive( path: &Path ) -> Result< String, Error > {
+    let mut fp = fs::File::open( path ).map_err( |error| Error::CannotLoadFile( path.to_owned(), error ) )?;
+    let mut buffer = Vec::new();
+    buffer.resize( 1024 * 1024, 0 );
+
+    let mut hasher = sha2::Sha256::default();
+    loop {
+        let length = match fp.read( &mut buffer ) {
+            Ok( 0 ) => break,
+            Ok( length ) => length,
+            Err( ref err ) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err( err ) => return Err( Error::CannotLoadFile( path.to_owned(), err ) )
+        };
+
+        hasher.input( &buffer[ 0..length ] );
     }
 
+    Ok( finish_hash( hasher ) )
+}
+
+// Verifies the hash of the archive and replaces the currently installed version of the package with it.
+fn install( message_format: MessageFormat, package: &PrebuiltPackage, archive: &Path, actual_hash: &str ) -> Result< PathBuf, Error > {
+    let package_filename = package_filename( package );
+    if actual_hash != package.hash {
+        return Err( Error::EnvironmentError( format!(
+            "the hash of {} doesn't match the expected hash!\n  actual: {}\n  expected: {}",
+            package_filename,
+            actual_hash,
+            package.hash
+        )));
+    }
+
+    let unpack_path = package_path( package );
     if fs::metadata( &unpack_path ).is_ok() {
-        fs::remove_dir_all( &unpack_path ).unwrap();
+        fs::remove_dir_all( &unpack_path ).map_err( |error| Error::CannotRemoveDirectory( unpack_path.clone(), error ) )?;
     }
 
-    fs::create_dir_all( &unpack_path ).unwrap();
+    fs::create_dir_all( &unpack_path ).map_err( |error| Error::CannotCreateFile( unpack_path.clone(), error ) )?;
+
+    messages::progress( message_format, "unpacking", None, &format!( "Unpacking {}...", package_filename ) );
+    unpack( archive, &unpack_path ).map_err( |error| Error::CannotLoadFile( archive.to_owned(), *error ) )?;
 
-    eprintln!( "Downloading {}...", package_filename );
-    let client = create_client().unwrap();
-    let mut response = client.get( url )
+    let version_path = unpack_path.join( ".version" );
+    write( &version_path, package.version ).map_err( |error| Error::CannotWriteToFile( version_path.clone(), error ) )?;
+
+    messages::progress( message_format, "installed", Some( &unpack_path ), &format!( "Package {} was successfully installed!", package_filename ) );
+    Ok( unpack_path )
+}
+
+// Downloads the package into the given path and returns its hash.
+fn download( message_format: MessageFormat, settings: &DownloadSettings, package: &PrebuiltPackage, output_path: &Path ) -> Result< String, Error > {
+    let url = settings.url( package );
+    let url = Url::parse( &url ).map_err( |error| Error::ConfigurationError( format!( "invalid package mirror URL `{}`: {}", url, error ) ) )?;
+
+    messages::progress( message_format, "downloading", None, &format!( "Downloading {}...", url ) );
+    let client = create_client( settings )?;
+    let mut response = client.get( url.clone() )
         .header( header::CONNECTION, "close" )
         .send()
-        .unwrap();
+        .map_err( |error| Error::EnvironmentError( format!( "cannot download {}: {}", url, error ) ) )?;
 
-    let prefix = format!( "cargo-web-{}-download", package.name );
-    let tmpdir = tempfile::Builder::new().prefix( &prefix ).tempdir().unwrap();
-    let dlpath = tmpdir.path().join( &package_filename );
-    let mut fp = fs::File::create( &dlpath ).unwrap();
+    if !response.status().is_success() {
+        return Err( Error::EnvironmentError( format!( "cannot download {}: {}", url, response.status() ) ) );
+    }
+
+    let mut fp = fs::File::create( output_path ).map_err( |error| Error::CannotCreateFile( output_path.to_owned(), error ) )?;
 
     let length = response.headers().get( header::CONTENT_LENGTH )
         .and_then( |len| len.to_str().ok() )
         .and_then( |len| len.parse().ok() )
         .unwrap_or( package.size );
-    let mut pb = pbr::ProgressBar::new( length );
-    pb.set_units( pbr::Units::Bytes );
+    // The progress bar is drawn on stdout, so it would corrupt the JSON messages.
+    let mut pb = if message_format == MessageFormat::Json {
+        None
+    } else {
+        let mut pb = pbr::ProgressBar::new( length );
+        pb.set_units( pbr::Units::Bytes );
+        Some( pb )
+    };
 
     let mut buffer = Vec::new();
     buffer.resize( 1024 * 1024, 0 );
@@ -101,31 +238,106 @@ pub fn download_package( package: &PrebuiltPackage ) -> PathBuf {
             Ok( 0 ) => break,
             Ok( length ) => length,
             Err( ref err ) if err.kind() == io::ErrorKind::Interrupted => continue,
-            Err( err ) => panic!( err )
+            Err( err ) => return Err( Error::EnvironmentError( format!( "cannot download {}: {}", url, err ) ) )
         };
 
         let slice = &buffer[ 0..length ];
         hasher.input( slice );
-        fp.write_all( slice ).unwrap();
-        pb.add( length as u64 );
+        fp.write_all( slice ).map_err( |error| Error::CannotWriteToFile( output_path.to_owned(), error ) )?;
+        if let Some( ref mut pb ) = pb {
+            pb.add( length as u64 );
+        }
+    }
+
+    if let Some( mut pb ) = pb {
+        pb.finish();
     }
 
-    pb.finish();
+    Ok( finish_hash( hasher ) )
+}
 
-    let actual_hash = hasher.result();
-    let actual_hash = actual_hash.map( |byte| format!( "{:02x}", byte ) ).join( "" );
+/// Makes sure the given version of the package is installed, fetching it if necessary.
+pub fn download_package( message_format: MessageFormat, settings: &DownloadSettings, package: &PrebuiltPackage ) -> Result< PathBuf, Error > {
+    if installed_version( package ).map( |version| version == package.version ).unwrap_or( false ) {
+        return Ok( package_path( package ) );
+    }
 
-    if actual_hash != package.hash {
-        eprintln!( "error: the hash of {} doesn't match the expected hash!", package_filename );
-        eprintln!( "  actual: {}", actual_hash );
-        eprintln!( "  expected: {}", package.hash );
-        exit( 101 );
+    let package_filename = package_filename( package );
+    if let Some( mirror ) = settings.local_mirror() {
+        let archive = mirror.join( &package_filename );
+        if !archive.exists() {
+            return Err( Error::EnvironmentError( format!( "{} not found in the package mirror {:?}", package_filename, mirror ) ) );
+        }
+
+        let hash = hash_archive( &archive )?;
+        return install( message_format, package, &archive, &hash );
     }
 
-    eprintln!( "Unpacking {}...", package_filename );
-    unpack( &dlpath, &unpack_path ).unwrap();
-    write( &version_path, package.version ).unwrap();
+    if settings.offline {
+        return Err( Error::EnvironmentError( format!(
+            "{} {} is not installed and the offline mode is enabled; install it with `cargo web prepare-emscripten --from-archive {}` \
+             or point `CARGO_WEB_PACKAGE_MIRROR` to a directory which contains it",
+            package.name,
+            package.version,
+            package_filename
+        )));
+    }
+
+    let prefix = format!( "cargo-web-{}-download", package.name );
+    let tmpdir = tempfile::Builder::new().prefix( &prefix ).tempdir()
+        .map_err( |error| Error::EnvironmentError( format!( "cannot create a temporary directory: {}", error ) ) )?;
+
+    let dlpath = tmpdir.path().join( &package_filename );
+    let hash = download( message_format, settings, package, &dlpath )?;
+    install( message_format, package, &dlpath, &hash )
+}
+
+/// Installs the packages from the given archives; every archive has to match one of the packages.
+pub fn install_archives( message_format: MessageFormat, packages: &[PrebuiltPackage], archives: &[PathBuf] ) -> Result< (), Error > {
+    for archive in archives {
+        let hash = hash_archive( archive )?;
+        let package = packages.iter().find( |package| package.hash == hash ).ok_or_else( || {
+            let expected: Vec< _ > = packages.iter().map( package_filename ).collect();
+            Error::ConfigurationError( format!( "{:?} doesn't match any of the expected packages: {}", archive, expected.join( ", " ) ) )
+        })?;
+
+        install( message_format, package, archive, &hash )?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pem_certificates() {
+    let bundle = "\
+        # First\n\
+        -----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\
+        # Second\n\
+        -----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n\
+        -----BEGIN CERTIFICATE-----\nTRUNCATED\n";
+
+    assert_eq!( pem_certificates( bundle ), vec![
+        "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----",
+        "-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----"
+    ]);
+}
+
+#[test]
+fn test_mirror() {
+    let package = PrebuiltPackage {
+        url: "https://github.com/koute/emscripten-build/releases/download/emscripten-1.38.19-1/binaryen-1.38.19-1-x86_64-unknown-linux-gnu.tgz",
+        name: "binaryen",
+        version: "1.38.19-1",
+        arch: "x86_64-unknown-linux-gnu",
+        hash: "af079258c6f13234541d932b873762910951779c4682fc917255716637383dc9",
+        size: 15818455
+    };
 
-    eprintln!( "Package {} was successfully installed!", package_filename );
-    return unpack_path;
+    let settings = |mirror: Option< &str >| DownloadSettings { offline: false, mirror: mirror.map( |mirror| mirror.to_owned() ), ca_bundle: None };
+    assert_eq!( settings( None ).url( &package ), package.url );
+    assert_eq!( settings( None ).local_mirror(), None );
+    assert_eq!( settings( Some( "https://mirror.example.com/packages/" ) ).url( &package ), "https://mirror.example.com/packages/binaryen-1.38.19-1-x86_64-unknown-linux-gnu.tgz" );
+    assert_eq!( settings( Some( "https://mirror.example.com/packages/" ) ).local_mirror(), None );
+    assert_eq!( settings( Some( "file:///srv/packages" ) ).local_mirror(), Some( PathBuf::from( "/srv/packages" ) ) );
+    assert_eq!( settings( Some( "/srv/packages" ) ).local_mirror(), Some( PathBuf::from( "/srv/packages" ) ) );
 }
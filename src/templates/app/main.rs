@@ -0,0 +1,15 @@
+#[macro_use]
+extern crate stdweb;
+
+fn main() {
+    stdweb::initialize();
+
+    let message = "Hello from Rust!";
+    js! {
+        var heading = document.createElement( "h1" );
+        heading.textContent = @{message};
+        document.body.appendChild( heading );
+    }
+
+    stdweb::event_loop();
+}
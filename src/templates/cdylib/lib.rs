@@ -0,0 +1,6 @@
+use stdweb::js_export;
+
+#[js_export]
+pub fn greet( name: String ) -> String {
+    format!( "Hello, {}!", name )
+}
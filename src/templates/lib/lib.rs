@@ -0,0 +1,9 @@
+#[macro_use]
+extern crate stdweb;
+
+/// Shows a message in the browser's console.
+pub fn log( message: &str ) {
+    js! { @(no_return)
+        console.log( @{message} );
+    }
+}
@@ -0,0 +1,22 @@
+#[macro_use]
+extern crate stdweb;
+
+fn on_message( name: String ) -> String {
+    format!( "Hello, {}!", name )
+}
+
+fn main() {
+    stdweb::initialize();
+
+    js! { @(no_return)
+        var on_message = @{on_message};
+        self.onmessage = function( event ) {
+            self.postMessage( on_message( event.data ) );
+        };
+
+        // Messages sent before the `.wasm` file is loaded would get lost.
+        self.postMessage( "ready" );
+    }
+
+    stdweb::event_loop();
+}
@@ -32,6 +32,7 @@ use http_utils::{
 };
 use chrome_devtools::{Connection, Reply, ReplyError, ConsoleApiCalledBody, ExceptionThrownBody};
 use cmd_test::TEST_RUNNER;
+use wasm_stack_trace::Symbolicator;
 
 const DEFAULT_TEST_INDEX_HTML: &'static str = r#"
 <!DOCTYPE html>
@@ -54,12 +55,7 @@ const DEFAULT_TEST_INDEX_HTML: &'static str = r#"
 </html>
 "#;
 
-pub fn test_in_chromium(
-    backend: Backend,
-    build: CargoResult,
-    arg_passthrough: &Vec< &OsStr >,
-    any_failure: &mut bool
-) -> Result< (), Error > {
+pub fn find_chromium() -> Option< &'static str > {
     let possible_commands =
         if cfg!( windows ) {
             &[ "chrome.exe" ][..]
@@ -67,15 +63,23 @@ pub fn test_in_chromium(
             &[ "chromium", "chromium-browser", "google-chrome", "google-chrome-stable", "Google Chrome" ][..]
         };
 
-    let chromium_executable = find_cmd( possible_commands )
-        .or_else( || {
-            let path = "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome";
-            if Path::new( path ).exists() {
-                Some( path )
-            } else {
-                None
-            }
-        })
+    find_cmd( possible_commands ).or_else( || {
+        let path = "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome";
+        if Path::new( path ).exists() {
+            Some( path )
+        } else {
+            None
+        }
+    })
+}
+
+pub fn test_in_chromium(
+    backend: Backend,
+    build: CargoResult,
+    arg_passthrough: &Vec< &OsStr >,
+    any_failure: &mut bool
+) -> Result< (), Error > {
+    let chromium_executable = find_chromium()
         .ok_or_else( || {
             Error::EnvironmentError( "you need to have either Chromium or Chrome installed and in your PATH to run the tests!".into() )
         })?;
@@ -126,6 +130,7 @@ pub fn test_in_chromium(
         .find( |artifact| artifact.extension().map( |ext| ext == "js" ).unwrap_or( false ) )
         .expect( "internal error: no .js file found" );
 
+    let mut symbolicator = None;
     if backend.is_any_wasm() {
         let wasm_artifact = build.artifacts().iter()
             .find( |artifact| artifact.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) )
@@ -133,8 +138,19 @@ pub fn test_in_chromium(
 
         *wasm_url.lock().unwrap() = Some( format!( "/{}", wasm_artifact.file_name().unwrap().to_str().unwrap() ) );
         *app_wasm.lock().unwrap() = Some( read_bytes( wasm_artifact ).unwrap() );
+
+        if backend.is_native_wasm() {
+            symbolicator = Symbolicator::from_file( wasm_artifact );
+        }
     }
 
+    let symbolicate = |text: &str| {
+        match symbolicator {
+            Some( ref symbolicator ) => symbolicator.symbolicate( text ),
+            None => text.to_owned()
+        }
+    };
+
     *app_js.lock().unwrap() = read( artifact ).unwrap();
 
     let tmpdir = tempfile::Builder::new().prefix( "cargo-web-chromium-profile" ).tempdir().unwrap();
@@ -253,9 +269,19 @@ pub fn test_in_chromium(
             Reply::Event { ref method, ref body } if method == "Runtime.exceptionThrown" => {
                 let body: ExceptionThrownBody = serde_json::from_value( body.clone() ).expect( "Failed to parse `Runtime.exceptionThrown` event" );
                 eprintln!( "error: unhandled exception thrown" );
-                if let Some( exception ) = body.exception_details.exception {
-                    if let Some( description ) = exception.description {
-                        eprintln!( "error:     {}", description );
+                let description = body.exception_details.exception.and_then( |exception| exception.description );
+                if let Some( ref description ) = description {
+                    for line in symbolicate( description ).lines() {
+                        eprintln!( "error:     {}", line );
+                    }
+                } else if let Some( stack_trace ) = body.exception_details.stack_trace {
+                    for frame in stack_trace.call_frames {
+                        let function_name = match symbolicator {
+                            Some( ref symbolicator ) => symbolicator.function_name( &frame.function_name ),
+                            None => frame.function_name
+                        };
+
+                        eprintln!( "error:     at {} ({}:{}:{})", function_name, frame.url, frame.line_number, frame.column_number );
                     }
                 }
                 if let Some( url ) = body.exception_details.url {
@@ -297,7 +323,7 @@ pub fn test_in_chromium(
                             }
                         }
 
-                        println!( "{}", output );
+                        println!( "{}", symbolicate( &output ) );
                         print_counter += 1;
                     },
                     _ => {}
@@ -8,6 +8,8 @@ use std::ffi::OsString;
 use libflate::gzip;
 use tar;
 use sha1::Sha1;
+use sha2::Sha384;
+use digest::Digest;
 
 #[derive(Debug)]
 pub struct ExecutionStatus {
@@ -112,3 +114,54 @@ pub fn get_sha1sum< P: AsRef< Path > >( path: P ) -> io::Result< String > {
 
     Ok( format!( "{}", hasher.digest() ) )
 }
+
+/// Returns the base64 digit for the lowest six bits of the given value.
+pub fn base64_digit( value: u32 ) -> char {
+    const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    ALPHABET[ value as usize & 63 ] as char
+}
+
+fn base64_encode( input: &[u8] ) -> String {
+    let mut output = String::with_capacity( (input.len() + 2) / 3 * 4 );
+    for chunk in input.chunks( 3 ) {
+        let b0 = chunk[ 0 ] as u32;
+        let b1 = chunk.get( 1 ).cloned().unwrap_or( 0 ) as u32;
+        let b2 = chunk.get( 2 ).cloned().unwrap_or( 0 ) as u32;
+        let value = (b0 << 16) | (b1 << 8) | b2;
+
+        output.push( base64_digit( value >> 18 ) );
+        output.push( base64_digit( value >> 12 ) );
+        if chunk.len() > 1 {
+            output.push( base64_digit( value >> 6 ) );
+        } else {
+            output.push( '=' );
+        }
+        if chunk.len() > 2 {
+            output.push( base64_digit( value ) );
+        } else {
+            output.push( '=' );
+        }
+    }
+
+    output
+}
+
+/// Returns a Subresource Integrity string (`sha384-...`) for the given data.
+pub fn get_subresource_integrity( data: &[u8] ) -> String {
+    let mut hasher = Sha384::default();
+    hasher.input( data );
+    format!( "sha384-{}", base64_encode( &hasher.result() ) )
+}
+
+#[test]
+fn test_base64_encode() {
+    // The test vectors from RFC 4648.
+    assert_eq!( base64_encode( b"" ), "" );
+    assert_eq!( base64_encode( b"f" ), "Zg==" );
+    assert_eq!( base64_encode( b"fo" ), "Zm8=" );
+    assert_eq!( base64_encode( b"foo" ), "Zm9v" );
+    assert_eq!( base64_encode( b"foob" ), "Zm9vYg==" );
+    assert_eq!( base64_encode( b"fooba" ), "Zm9vYmE=" );
+    assert_eq!( base64_encode( b"foobar" ), "Zm9vYmFy" );
+    assert_eq!( base64_encode( &[ 0xfb, 0xff, 0xbf ] ), "+/+/" );
+}
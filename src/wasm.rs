@@ -3,12 +3,16 @@ use std::fs::{self, File};
 use std::io::{self, Read, Write};
 
 use parity_wasm;
-use cargo_shim::BuildConfig;
+use cargo_shim::{BuildConfig, MessageFormat};
+use config::{EnvVariable, LoaderConfig, ThreadsConfig, WasmOptConfig};
 use serde_json;
 
 use wasm_gc;
 
+use wasm_bulk_memory;
 use wasm_context::Context;
+use wasm_dead_code;
+use wasm_error::{ProcessingError, Stage};
 use wasm_inline_js;
 use wasm_export_main;
 use wasm_export_table;
@@ -17,93 +21,240 @@ use wasm_intrinsics;
 use wasm_runtime::{self, RuntimeKind};
 use wasm_js_export;
 use wasm_js_snippet;
-use utils::get_sha1sum;
+use wasm_opt;
+use wasm_source_map;
+use wasm_threads;
+use wasm_validate;
+use error::Error;
+use messages;
+use utils::{get_sha1sum, get_subresource_integrity, read_bytes};
 
 #[derive(Serialize, Deserialize)]
 struct Metadata {
     wasm_hash: String
 }
 
-pub fn process_wasm_file< P: AsRef< Path > + ?Sized >( uses_old_stdweb: bool, runtime: RuntimeKind, build: &BuildConfig, prepend_js: &str, target_dir: &Path, artifact: &P ) -> Option< PathBuf > {
+/// The files which were generated while processing a `.wasm` file.
+#[derive(Debug, Default)]
+pub struct ProcessedWasm {
+    pub artifacts: Vec< PathBuf >,
+    pub uses_shared_memory: bool
+}
+
+fn with_path< T >( path: &Path, result: Result< T, ProcessingError > ) -> Result< T, Error > {
+    result.map_err( |error| Error::WasmProcessingFailed( path.to_owned(), error ) )
+}
+
+fn wasm_integrity( loader: &LoaderConfig, path: &Path ) -> Result< Option< String >, Error > {
+    if !loader.integrity {
+        return Ok( None );
+    }
+
+    let wasm = read_bytes( path ).map_err( |error| Error::CannotLoadFile( path.to_owned(), error ) )?;
+    Ok( Some( get_subresource_integrity( &wasm ) ) )
+}
+
+fn serialize_module( path: &Path, module: parity_wasm::elements::Module ) -> Result< (), Error > {
+    let result = wasm_bulk_memory::serialize( module ).map_err( |error| {
+        ProcessingError::new( Stage::Serialization, format!( "{:?}", error ) )
+    });
+
+    let bytes = with_path( path, result )?;
+    write_file( path, &bytes )
+}
+
+fn write_file( path: &Path, contents: &[u8] ) -> Result< (), Error > {
+    let mut fp = File::create( path ).map_err( |error| Error::CannotCreateFile( path.to_owned(), error ) )?;
+    fp.write_all( contents ).map_err( |error| Error::CannotWriteToFile( path.to_owned(), error ) )
+}
+
+fn sha1sum( path: &Path ) -> Result< String, Error > {
+    get_sha1sum( path ).map_err( |error| Error::CannotLoadFile( path.to_owned(), error ) )
+}
+
+pub fn process_wasm_file< P: AsRef< Path > + ?Sized >(
+    uses_old_stdweb: bool,
+    runtime: RuntimeKind,
+    loader: &LoaderConfig,
+    wasm_opt: &WasmOptConfig,
+    threads: &ThreadsConfig,
+    env: &[EnvVariable],
+    skip_validation: bool,
+    build: &BuildConfig,
+    prepend_js: &str,
+    target_dir: &Path,
+    artifact: &P
+) -> Result< ProcessedWasm, Error > {
     if !build.triplet.as_ref().map( |triplet| triplet == "wasm32-unknown-unknown" ).unwrap_or( false ) {
-        return None;
+        return Ok( ProcessedWasm::default() );
     }
 
     let path = artifact.as_ref();
     if !path.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) {
-        return None;
+        return Ok( ProcessedWasm::default() );
     }
 
     if !uses_old_stdweb {
-        new_process_wasm_file( runtime, prepend_js, target_dir, path )
+        new_process_wasm_file( build.message_format, runtime, loader, wasm_opt, threads, env, skip_validation, prepend_js, target_dir, path )
     } else {
-        old_process_wasm_file( runtime, prepend_js, path )
+        old_process_wasm_file( build.message_format, runtime, loader, env, prepend_js, path )
     }
 }
 
-fn new_process_wasm_file( runtime: RuntimeKind, prepend_js: &str, target_dir: &Path, path: &Path ) -> Option< PathBuf > {
-    eprintln!( "    Processing {:?}...", path.file_name().unwrap() );
+fn new_process_wasm_file( message_format: MessageFormat, runtime: RuntimeKind, loader: &LoaderConfig, wasm_opt: &WasmOptConfig, threads: &ThreadsConfig, env: &[EnvVariable], skip_validation: bool, prepend_js: &str, target_dir: &Path, path: &Path ) -> Result< ProcessedWasm, Error > {
+    messages::progress( message_format, "processing", Some( path ), &format!( "    Processing {:?}...", path.file_name().unwrap() ) );
 
-    let mut module = parity_wasm::deserialize_file( &path ).unwrap();
-    let mut ctx = Context::from_module( module );
-    let snippets = wasm_js_snippet::process( target_dir, &mut ctx );
-    let intrinsics = wasm_intrinsics::process( &mut ctx );
-    let main_symbol = wasm_export_main::process( &mut ctx );
-    let exports = wasm_js_export::process( &mut ctx );
-    wasm_export_main::process( &mut ctx );
-    wasm_export_table::process( &mut ctx );
+    let bytes = read_bytes( path ).map_err( |error| Error::CannotLoadFile( path.to_owned(), error ) )?;
+    let mut ctx = with_path( path, Context::from_bytes( &bytes ) )?;
+    let mut snippets = with_path( path, wasm_js_snippet::process( target_dir, &mut ctx ) )?;
+    let mut intrinsics = wasm_intrinsics::process( &mut ctx );
+    let main_symbol = with_path( path, wasm_export_main::process( &mut ctx ) )?;
+    let exports = with_path( path, wasm_js_export::process( &mut ctx ) )?;
+    with_path( path, wasm_export_main::process( &mut ctx ) )?;
+    with_path( path, wasm_export_table::process( &mut ctx ) )?;
     wasm_hook_grow::process( &mut ctx );
-    module = ctx.into_module();
+
+    let shared_memory = with_path( path, wasm_threads::process( &mut ctx ) )?;
+    if shared_memory.is_some() && runtime != RuntimeKind::Standalone {
+        return with_path( path, Err( ProcessingError::new( Stage::Threads, "shared memory is only supported with the `standalone` runtime" ) ) );
+    }
+
+    let removed = wasm_dead_code::process( &mut ctx );
+    wasm_dead_code::retain_imported_snippets( &ctx, &mut snippets );
+    wasm_dead_code::retain_imported_snippets( &ctx, &mut intrinsics );
+    if removed.bytes > 0 {
+        let message = format!(
+            "    Removed {} unused function(s), {} unused import(s), {} unused global(s) and {} unused type(s) ({} bytes)",
+            removed.functions,
+            removed.imports,
+            removed.globals,
+            removed.types,
+            removed.bytes
+        );
+
+        messages::progress( message_format, "dead-code-elimination", Some( path ), &message );
+    }
+
+    let source_map_path = path.with_extension( "wasm.map" );
+    let has_line_info = ctx.debug_sections.iter().any( |&(ref name, _)| name == ".debug_line" );
+    if has_line_info {
+        ctx.source_mapping_url = Some( source_map_path.file_name().unwrap().to_string_lossy().into_owned() );
+    }
+
+    let worker_path = wasm_threads::worker_path( path );
+    let worker_module = match shared_memory {
+        Some( _ ) => Some( wasm_threads::worker_context( &ctx ).into_module() ),
+        None => None
+    };
+    let (module, warnings) = ctx.into_module_with_warnings();
+    for warning in warnings {
+        messages::warning( message_format, Some( path ), &warning );
+    }
+
+    if !skip_validation {
+        wasm_validate::validate( &module ).map_err( |error| Error::InvalidWasmModule( path.to_owned(), error.to_string() ) )?;
+        if let Some( ref worker_module ) = worker_module {
+            wasm_validate::validate( worker_module ).map_err( |error| Error::InvalidWasmModule( worker_path.clone(), error.to_string() ) )?;
+        }
+    }
 
     // TODO: Remove this once we stop losing information when we process the `.wasm` file.
     //       (That is - migrate the `#[js_export]` macro to use another mechanism.)
     let _ = fs::remove_file( path );
+    let _ = fs::remove_file( &worker_path );
+
+    serialize_module( path, module )?;
+    if let Some( worker_module ) = worker_module {
+        serialize_module( &worker_path, worker_module )?;
+    }
+
+    let uses_shared_memory = shared_memory.is_some();
+    with_path( path, wasm_opt::run( message_format, wasm_opt, path, uses_shared_memory ).map_err( |error| ProcessingError::new( Stage::WasmOpt, error ) ) )?;
+    if uses_shared_memory {
+        with_path( &worker_path, wasm_opt::run( message_format, wasm_opt, &worker_path, true ).map_err( |error| ProcessingError::new( Stage::WasmOpt, error ) ) )?;
+    }
+
+    let _ = fs::remove_file( &source_map_path );
+    let mut has_source_map = false;
+    if has_line_info {
+        let bytes = read_bytes( path ).map_err( |error| Error::CannotLoadFile( path.to_owned(), error ) )?;
+        match wasm_source_map::generate( &bytes ) {
+            Ok( Some( source_map ) ) => {
+                write_file( &source_map_path, source_map.as_bytes() )?;
+                has_source_map = true;
+            },
+            Ok( None ) => {},
+            Err( error ) => {
+                messages::warning( message_format, Some( path ), &format!( "cannot generate a source map for {:?}: {}", path.file_name().unwrap(), error ) );
+            }
+        }
+    }
 
-    parity_wasm::serialize_to_file( path, module ).unwrap();
+    let wasm_hash = sha1sum( path )?;
+    debug!( "Hash of {:?}: {}", path, wasm_hash );
 
     let mut all_snippets: Vec< _ > = snippets.into_iter().chain( intrinsics.into_iter() ).collect();
     all_snippets.sort_by( |a, b| a.name.cmp( &b.name ) );
 
+    let worker_integrity = match shared_memory {
+        Some( _ ) => wasm_integrity( loader, &worker_path )?,
+        None => None
+    };
+    let wasm_integrity = wasm_integrity( loader, path )?;
+
     let js_path = path.with_extension( "js" );
-    let js = wasm_runtime::generate_js( runtime, main_symbol, path, prepend_js, &all_snippets, &exports );
-    let mut fp = File::create( &js_path ).unwrap();
-    fp.write_all( js.as_bytes() ).unwrap();
+    let js = wasm_runtime::generate_js( runtime, loader, main_symbol, path, &wasm_hash, wasm_integrity.as_ref().map( |integrity| integrity.as_str() ), worker_integrity.as_ref().map( |integrity| integrity.as_str() ), prepend_js, &all_snippets, &exports, shared_memory.as_ref(), threads, env );
+    write_file( &js_path, js.as_bytes() )?;
 
-    eprintln!( "    Finished processing of {:?}!", path.file_name().unwrap() );
-    Some( js_path )
+    messages::progress( message_format, "finished", Some( path ), &format!( "    Finished processing of {:?}!", path.file_name().unwrap() ) );
+    let mut artifacts = vec![ js_path ];
+    if shared_memory.is_some() {
+        artifacts.push( worker_path );
+    }
 
+    if has_source_map {
+        artifacts.push( source_map_path );
+    }
+
+    Ok( ProcessedWasm { artifacts, uses_shared_memory: shared_memory.is_some() } )
 }
 
-fn old_process_wasm_file( runtime: RuntimeKind, prepend_js: &str, path: &Path ) -> Option< PathBuf > {
-    let wasm_hash = get_sha1sum( path ).expect( "cannot calculate sha1sum of the `.wasm` file" );
+fn old_process_wasm_file( message_format: MessageFormat, runtime: RuntimeKind, loader: &LoaderConfig, env: &[EnvVariable], prepend_js: &str, path: &Path ) -> Result< ProcessedWasm, Error > {
+    let wasm_hash = sha1sum( path )?;
     debug!( "Hash of {:?}: {}", path, wasm_hash );
 
     let js_path = path.with_extension( "js" );
     let metadata_path = path.with_extension( "cargoweb-metadata" );
     if js_path.exists() && metadata_path.exists() {
         // TODO: This is just a quick workaround. We should always regenerate the `.js` file.
-        let fp = File::open( &metadata_path ).expect( "cannot open the metadata file" );
-        let metadata: Metadata = serde_json::from_reader( fp ).expect( "cannot deserialize metadata; delete your `target` directory" );
-        if metadata.wasm_hash == wasm_hash {
-            debug!( "Skipping `.js` generation and `.wasm` processing!" );
-            return Some( js_path );
+        let metadata: Option< Metadata > = File::open( &metadata_path ).ok().and_then( |fp| serde_json::from_reader( fp ).ok() );
+        match metadata {
+            Some( metadata ) => {
+                if metadata.wasm_hash == wasm_hash {
+                    debug!( "Skipping `.js` generation and `.wasm` processing!" );
+                    return Ok( ProcessedWasm { artifacts: vec![ js_path ], uses_shared_memory: false } );
+                }
+            },
+            None => {
+                warn!( "Cannot load {:?}; regenerating it", metadata_path );
+            }
         }
     }
 
-    eprintln!( "    Garbage collecting {:?}...", path.file_name().unwrap() );
+    messages::progress( message_format, "garbage-collecting", Some( path ), &format!( "    Garbage collecting {:?}...", path.file_name().unwrap() ) );
     wasm_gc::run( &path, &path );
 
-    eprintln!( "    Processing {:?}...", path.file_name().unwrap() );
-    let mut module = parity_wasm::deserialize_file( &path ).unwrap();
-    let mut ctx = Context::from_module( module );
-    let snippets = wasm_inline_js::process_and_extract( &mut ctx );
+    messages::progress( message_format, "processing", Some( path ), &format!( "    Processing {:?}...", path.file_name().unwrap() ) );
+    let module = parity_wasm::deserialize_file( path ).map_err( |error| ProcessingError::new( Stage::Parsing, format!( "{:?}", error ) ) );
+    let mut ctx = with_path( path, module.and_then( Context::from_module ) )?;
+    let snippets = with_path( path, wasm_inline_js::process_and_extract( &mut ctx ) )?;
     let intrinsics = wasm_intrinsics::process( &mut ctx );
-    let main_symbol = wasm_export_main::process( &mut ctx );
-    let exports = wasm_js_export::process( &mut ctx );
-    wasm_export_main::process( &mut ctx );
-    wasm_export_table::process( &mut ctx );
+    let main_symbol = with_path( path, wasm_export_main::process( &mut ctx ) )?;
+    let exports = with_path( path, wasm_js_export::process( &mut ctx ) )?;
+    with_path( path, wasm_export_main::process( &mut ctx ) )?;
+    with_path( path, wasm_export_table::process( &mut ctx ) )?;
     wasm_hook_grow::process( &mut ctx );
-    module = ctx.into_module();
+    let module = ctx.into_module();
 
     // At least on Linux when a `.wasm` file is built it's
     // hard-linked from two places:
@@ -123,22 +274,22 @@ fn old_process_wasm_file( runtime: RuntimeKind, prepend_js: &str, path: &Path )
     // overwriting it to get rid of the hard-link.
     let _ = fs::remove_file( path );
 
-    parity_wasm::serialize_to_file( path, module ).unwrap();
+    serialize_module( path, module )?;
+
+    let new_wasm_hash = sha1sum( path )?;
+    debug!( "New hash of {:?}: {}", path, new_wasm_hash );
 
     let mut all_snippets: Vec< _ > = snippets.into_iter().chain( intrinsics.into_iter() ).collect();
 
     all_snippets.sort_by( |a, b| a.name.cmp( &b.name ) );
 
-    let js = wasm_runtime::generate_js( runtime, main_symbol, path, prepend_js, &all_snippets, &exports );
-    let mut fp = File::create( &js_path ).unwrap();
-    fp.write_all( js.as_bytes() ).unwrap();
-
-    let new_wasm_hash = get_sha1sum( path ).expect( "cannot calculate sha1sum of the `.wasm` file" );
-    debug!( "New hash of {:?}: {}", path, new_wasm_hash );
+    let wasm_integrity = wasm_integrity( loader, path )?;
+    let js = wasm_runtime::generate_js( runtime, loader, main_symbol, path, &new_wasm_hash, wasm_integrity.as_ref().map( |integrity| integrity.as_str() ), None, prepend_js, &all_snippets, &exports, None, &ThreadsConfig::default(), env );
+    write_file( &js_path, js.as_bytes() )?;
 
-    let fp = File::create( &metadata_path ).unwrap();
-    serde_json::to_writer( fp, &Metadata { wasm_hash: new_wasm_hash } ).unwrap();
+    let metadata = serde_json::to_vec( &Metadata { wasm_hash: new_wasm_hash } ).unwrap();
+    write_file( &metadata_path, &metadata )?;
 
-    eprintln!( "    Finished processing of {:?}!", path.file_name().unwrap() );
-    Some( js_path )
+    messages::progress( message_format, "finished", Some( path ), &format!( "    Finished processing of {:?}!", path.file_name().unwrap() ) );
+    Ok( ProcessedWasm { artifacts: vec![ js_path ], uses_shared_memory: false } )
 }
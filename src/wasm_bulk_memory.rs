@@ -0,0 +1,416 @@
+use parity_wasm;
+use parity_wasm::elements as pw;
+use parity_wasm::elements::{Deserialize, Instruction};
+
+use wasm_sections::{self, CODE_SECTION_ID};
+
+// `parity-wasm` implements an early draft of the bulk memory proposal
+// which encodes some of the instructions differently than the final spec
+// and which doesn't know about the `DataCount` section. Modules compiled
+// with `+bulk-memory` (implied by `+atomics`) are converted from and into
+// the final encoding around the processing.
+//
+// The conversion never changes the length of any instruction so that
+// the code offsets in the DWARF debug info stay valid.
+
+pub const DATA_SECTION_ID: u8 = 11;
+pub const DATA_COUNT_SECTION_ID: u8 = 12;
+
+const NOP: u8 = 0x01;
+const BULK_PREFIX: u8 = 0xfc;
+const MEMORY_INIT: u8 = 0x08;
+const DATA_DROP: u8 = 0x09;
+const MEMORY_COPY: u8 = 0x0a;
+const MEMORY_FILL: u8 = 0x0b;
+const TABLE_INIT: u8 = 0x0c;
+const ELEM_DROP: u8 = 0x0d;
+const TABLE_COPY: u8 = 0x0e;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Encoding {
+    Standard,
+    Parity
+}
+
+fn read_u32( p: &mut &[u8] ) -> Result< u32, pw::Error > {
+    Ok( u32::from( pw::VarUint32::deserialize( p )? ) )
+}
+
+fn write_u32( output: &mut Vec< u8 >, value: u32 ) {
+    pw::Serialize::serialize( pw::VarUint32::from( value ), output ).unwrap();
+}
+
+fn length_of_u32( bytes: &[u8] ) -> Result< usize, pw::Error > {
+    let mut p = bytes;
+    read_u32( &mut p )?;
+    Ok( bytes.len() - p.len() )
+}
+
+fn expect_byte( bytes: &[u8], position: usize, value: u8 ) -> Result< (), pw::Error > {
+    match bytes.get( position ) {
+        Some( &byte ) if byte == value => Ok(()),
+        Some( _ ) => Err( pw::Error::Other( "unsupported bulk memory instruction immediate" ) ),
+        None => Err( pw::Error::UnexpectedEof )
+    }
+}
+
+// In the final spec the segment index of `memory.init` and `table.init` comes
+// before the memory or the table index, while in the draft it comes after it.
+fn swap_segment_index( code: &mut [u8], position: usize, encoding: Encoding ) -> Result< usize, pw::Error > {
+    match encoding {
+        Encoding::Standard => {
+            let length = length_of_u32( &code[ position.. ] )?;
+            expect_byte( code, position + length, 0 )?;
+            code[ position..position + length + 1 ].rotate_right( 1 );
+            Ok( length + 1 )
+        },
+        Encoding::Parity => {
+            expect_byte( code, position, 0 )?;
+            let length = length_of_u32( &code[ position + 1.. ] )?;
+            code[ position..position + length + 1 ].rotate_left( 1 );
+            Ok( length + 1 )
+        }
+    }
+}
+
+// In the final spec `memory.copy` and `table.copy` have two
+// zero immediates while in the draft they have only one, so
+// the second one is turned into a `nop` and back.
+fn swap_copy_immediate( code: &mut [u8], position: usize, encoding: Encoding ) -> Result< usize, pw::Error > {
+    expect_byte( code, position, 0 )?;
+    match encoding {
+        Encoding::Standard => {
+            expect_byte( code, position + 1, 0 )?;
+            code[ position + 1 ] = NOP;
+        },
+        Encoding::Parity => {
+            if code.get( position + 1 ) != Some( &NOP ) {
+                return Err( pw::Error::Other( "`memory.copy` or `table.copy` is not followed by a `nop`" ) );
+            }
+            code[ position + 1 ] = 0;
+        }
+    }
+
+    Ok( 2 )
+}
+
+// Returns the length of the immediates of the bulk memory instruction at the given position.
+fn convert_bulk_instruction( code: &mut [u8], position: usize, encoding: Encoding ) -> Result< usize, pw::Error > {
+    let opcode = *code.get( position ).ok_or( pw::Error::UnexpectedEof )?;
+    let position = position + 1;
+    let length = match opcode {
+        MEMORY_INIT | TABLE_INIT => swap_segment_index( code, position, encoding )?,
+        MEMORY_COPY | TABLE_COPY => swap_copy_immediate( code, position, encoding )?,
+        DATA_DROP | ELEM_DROP => length_of_u32( &code[ position.. ] )?,
+        MEMORY_FILL => {
+            expect_byte( code, position, 0 )?;
+            1
+        },
+        opcode => return Err( pw::Error::UnknownOpcode( opcode ) )
+    };
+
+    Ok( 1 + length )
+}
+
+// Converts the code section's payload in place; returns whether
+// any of the functions refers to the data segments by index.
+fn convert_code_section( code: &mut [u8], encoding: Encoding ) -> Result< bool, pw::Error > {
+    let mut uses_data_segments = false;
+    let mut position = {
+        let mut p = &code[..];
+        read_u32( &mut p )?;
+        code.len() - p.len()
+    };
+
+    while position < code.len() {
+        let body_end = {
+            let mut p = &code[ position.. ];
+            let size = read_u32( &mut p )? as usize;
+            let body_start = code.len() - p.len();
+            if size > p.len() {
+                return Err( pw::Error::UnexpectedEof );
+            }
+
+            let mut body = &p[ ..size ];
+            let local_count = read_u32( &mut body )?;
+            for _ in 0..local_count {
+                pw::Local::deserialize( &mut body )?;
+            }
+
+            position = body_start + size - body.len();
+            body_start + size
+        };
+
+        while position < body_end {
+            if code[ position ] == BULK_PREFIX {
+                match code.get( position + 1 ) {
+                    Some( &MEMORY_INIT ) | Some( &DATA_DROP ) => uses_data_segments = true,
+                    _ => {}
+                }
+
+                position += 1 + convert_bulk_instruction( &mut code[ ..body_end ], position + 1, encoding )?;
+            } else {
+                let mut p = &code[ position..body_end ];
+                pw::Instruction::deserialize( &mut p )?;
+                position = body_end - p.len();
+            }
+        }
+    }
+
+    Ok( uses_data_segments )
+}
+
+/// Converts a module into an encoding which `parity-wasm` can parse.
+pub fn from_standard_encoding( bytes: &[u8] ) -> Result< Vec< u8 >, pw::Error > {
+    let sections = wasm_sections::sections( bytes )?;
+    let mut output = Vec::with_capacity( bytes.len() );
+    output.extend_from_slice( &bytes[ ..8 ] );
+
+    let mut position = output.len();
+    for section in sections {
+        let (id, payload) = section?;
+        let payload_start = payload.as_ptr() as usize - bytes.as_ptr() as usize;
+        let end = payload_start + payload.len();
+        if id != DATA_COUNT_SECTION_ID {
+            let payload_start = output.len() + (payload_start - position);
+            output.extend_from_slice( &bytes[ position..end ] );
+            if id == CODE_SECTION_ID {
+                convert_code_section( &mut output[ payload_start.. ], Encoding::Standard )?;
+            }
+        }
+
+        position = end;
+    }
+
+    Ok( output )
+}
+
+/// Converts a module serialized by `parity-wasm` into the final encoding,
+/// adding the `DataCount` section if any function refers to the data segments.
+pub fn to_standard_encoding( bytes: &[u8] ) -> Result< Vec< u8 >, pw::Error > {
+    let data_count = match wasm_sections::find_section( bytes, DATA_SECTION_ID )? {
+        Some( mut payload ) => read_u32( &mut payload )?,
+        None => 0
+    };
+
+    let sections = wasm_sections::sections( bytes )?;
+    let mut output = Vec::with_capacity( bytes.len() + 3 );
+    output.extend_from_slice( &bytes[ ..8 ] );
+
+    let mut position = output.len();
+    for section in sections {
+        let (id, payload) = section?;
+        let payload_start = payload.as_ptr() as usize - bytes.as_ptr() as usize;
+        let end = payload_start + payload.len();
+        if id == CODE_SECTION_ID {
+            let mut section = bytes[ position..end ].to_vec();
+            let header_length = payload_start - position;
+            let uses_data_segments = convert_code_section( &mut section[ header_length.. ], Encoding::Parity )?;
+
+            // This has to come right before the code section.
+            if uses_data_segments {
+                let mut data_count_payload = Vec::new();
+                write_u32( &mut data_count_payload, data_count );
+                output.push( DATA_COUNT_SECTION_ID );
+                write_u32( &mut output, data_count_payload.len() as u32 );
+                output.extend_from_slice( &data_count_payload );
+            }
+
+            output.extend_from_slice( &section );
+        } else {
+            output.extend_from_slice( &bytes[ position..end ] );
+        }
+
+        position = end;
+    }
+
+    Ok( output )
+}
+
+/// Same as `parity_wasm::deserialize_buffer` except it also accepts the final bulk memory encoding.
+pub fn deserialize( bytes: &[u8] ) -> Result< pw::Module, pw::Error > {
+    parity_wasm::deserialize_buffer( &from_standard_encoding( bytes )? )
+}
+
+/// Same as `parity_wasm::serialize` except it outputs the final bulk memory encoding.
+pub fn serialize( module: pw::Module ) -> Result< Vec< u8 >, pw::Error > {
+    to_standard_encoding( &parity_wasm::serialize( module )? )
+}
+
+/// Makes sure that every `memory.copy` and `table.copy` is followed
+/// by a `nop` which `to_standard_encoding` can turn into their
+/// second immediate without changing the length of the code.
+pub fn pad_copy_instructions( instructions: &mut Vec< Instruction > ) {
+    let mut index = 0;
+    while index < instructions.len() {
+        match instructions[ index ] {
+            Instruction::MemoryCopy | Instruction::TableCopy => {
+                if instructions.get( index + 1 ) != Some( &Instruction::Nop ) {
+                    instructions.insert( index + 1, Instruction::Nop );
+                }
+                index += 2;
+            },
+            _ => index += 1
+        }
+    }
+}
+
+// A module with shared memory and passive data segments like the ones
+// emitted by LLVM for `+atomics`, compiled from:
+//
+//    (module
+//      (memory (export "memory") 2 16384 shared)
+//      (global $__stack_pointer (mut i32) (i32.const 65536))
+//      (global $__tls_base (mut i32) (i32.const 0))
+//      (global $__tls_size i32 (i32.const 4))
+//      (func $__wasm_init_memory
+//        block
+//          i32.const 1024
+//          i32.const 0
+//          i32.const 1
+//          i32.atomic.rmw.cmpxchg
+//          br_if 0
+//          i32.const 1028
+//          i32.const 0
+//          i32.const 5
+//          memory.init 1
+//          data.drop 1
+//        end)
+//      (func $__wasm_init_tls (param i32)
+//        local.get 0
+//        global.set $__tls_base
+//        local.get 0
+//        i32.const 0
+//        i32.const 4
+//        memory.init 0)
+//      (func $copy (export "copy") (param i32 i32 i32)
+//        local.get 0
+//        local.get 1
+//        local.get 2
+//        memory.copy
+//        local.get 1
+//        i32.const 0
+//        local.get 2
+//        memory.fill)
+//      (start $__wasm_init_memory)
+//      (data $.tdata "\2a\00\00\00")
+//      (data $.rodata "hello"))
+#[cfg(test)]
+pub const THREADED_MODULE: &'static [u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x0e, 0x03, 0x60, 0x00, 0x00, 0x60, 0x01,
+    0x7f, 0x00, 0x60, 0x03, 0x7f, 0x7f, 0x7f, 0x00, 0x03, 0x04, 0x03, 0x00, 0x01, 0x02, 0x05, 0x06,
+    0x01, 0x03, 0x02, 0x80, 0x80, 0x01, 0x06, 0x12, 0x03, 0x7f, 0x01, 0x41, 0x80, 0x80, 0x04, 0x0b,
+    0x7f, 0x01, 0x41, 0x00, 0x0b, 0x7f, 0x00, 0x41, 0x04, 0x0b, 0x07, 0x11, 0x02, 0x06, 0x6d, 0x65,
+    0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, 0x04, 0x63, 0x6f, 0x70, 0x79, 0x00, 0x02, 0x08, 0x01, 0x00,
+    0x0c, 0x01, 0x02, 0x0a, 0x49, 0x03, 0x20, 0x00, 0x02, 0x40, 0x41, 0x80, 0x08, 0x41, 0x00, 0x41,
+    0x01, 0xfe, 0x48, 0x02, 0x00, 0x0d, 0x00, 0x41, 0x84, 0x08, 0x41, 0x00, 0x41, 0x05, 0xfc, 0x08,
+    0x01, 0x00, 0xfc, 0x09, 0x01, 0x0b, 0x0b, 0x10, 0x00, 0x20, 0x00, 0x24, 0x01, 0x20, 0x00, 0x41,
+    0x00, 0x41, 0x04, 0xfc, 0x08, 0x00, 0x00, 0x0b, 0x15, 0x00, 0x20, 0x00, 0x20, 0x01, 0x20, 0x02,
+    0xfc, 0x0a, 0x00, 0x00, 0x20, 0x01, 0x41, 0x00, 0x20, 0x02, 0xfc, 0x0b, 0x00, 0x0b, 0x0b, 0x0e,
+    0x02, 0x01, 0x04, 0x2a, 0x00, 0x00, 0x00, 0x01, 0x05, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x00, 0x73,
+    0x04, 0x6e, 0x61, 0x6d, 0x65, 0x01, 0x2c, 0x03, 0x00, 0x12, 0x5f, 0x5f, 0x77, 0x61, 0x73, 0x6d,
+    0x5f, 0x69, 0x6e, 0x69, 0x74, 0x5f, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x01, 0x0f, 0x5f, 0x5f,
+    0x77, 0x61, 0x73, 0x6d, 0x5f, 0x69, 0x6e, 0x69, 0x74, 0x5f, 0x74, 0x6c, 0x73, 0x02, 0x04, 0x63,
+    0x6f, 0x70, 0x79, 0x07, 0x2a, 0x03, 0x00, 0x0f, 0x5f, 0x5f, 0x73, 0x74, 0x61, 0x63, 0x6b, 0x5f,
+    0x70, 0x6f, 0x69, 0x6e, 0x74, 0x65, 0x72, 0x01, 0x0a, 0x5f, 0x5f, 0x74, 0x6c, 0x73, 0x5f, 0x62,
+    0x61, 0x73, 0x65, 0x02, 0x0a, 0x5f, 0x5f, 0x74, 0x6c, 0x73, 0x5f, 0x73, 0x69, 0x7a, 0x65, 0x09,
+    0x12, 0x02, 0x00, 0x06, 0x2e, 0x74, 0x64, 0x61, 0x74, 0x61, 0x01, 0x07, 0x2e, 0x72, 0x6f, 0x64,
+    0x61, 0x74, 0x61,
+];
+
+#[cfg(test)]
+fn definition< 'a >( ctx: &'a ::wasm_context::Context, name: &str ) -> &'a [Instruction] {
+    use wasm_context::FunctionKind;
+    ctx.functions.values().find( |function| function.name() == Some( name ) ).map( |function| {
+        match *function {
+            FunctionKind::Definition { ref instructions, .. } => instructions.as_slice(),
+            FunctionKind::Import { .. } => panic!()
+        }
+    }).unwrap()
+}
+
+#[test]
+fn test_from_standard_encoding() {
+    use wasm_context::Context;
+
+    let bytes = from_standard_encoding( THREADED_MODULE ).unwrap();
+    assert_eq!( bytes.len(), THREADED_MODULE.len() - 3 );
+    assert_eq!( wasm_sections::find_section( &bytes, DATA_COUNT_SECTION_ID ).unwrap(), None );
+    assert_eq!( to_standard_encoding( &bytes ).unwrap(), THREADED_MODULE );
+
+    let ctx = Context::from_module( parity_wasm::deserialize_buffer( &bytes ).unwrap() ).unwrap();
+    assert_eq!( ctx.data.iter().map( |data| (data.offset.clone(), data.value.as_slice()) ).collect::< Vec< _ > >(), vec![
+        (None, &b"\x2a\0\0\0"[..]),
+        (None, &b"hello"[..])
+    ]);
+
+    assert_eq!( &definition( &ctx, "__wasm_init_memory" )[ 9.. ], &[
+        Instruction::MemoryInit( 1 ),
+        Instruction::MemoryDrop( 1 ),
+        Instruction::End,
+        Instruction::End
+    ]);
+
+    assert_eq!( definition( &ctx, "copy" ), &[
+        Instruction::GetLocal( 0 ),
+        Instruction::GetLocal( 1 ),
+        Instruction::GetLocal( 2 ),
+        Instruction::MemoryCopy,
+        Instruction::Nop,
+        Instruction::GetLocal( 1 ),
+        Instruction::I32Const( 0 ),
+        Instruction::GetLocal( 2 ),
+        Instruction::MemoryFill,
+        Instruction::End
+    ]);
+}
+
+#[test]
+fn test_round_trip() {
+    use wasm_context::Context;
+    use wasm_validate::validate;
+
+    let module = Context::from_bytes( THREADED_MODULE ).unwrap().into_module();
+    validate( &module ).unwrap();
+
+    let bytes = serialize( module ).unwrap();
+    assert_eq!( wasm_sections::find_section( &bytes, DATA_COUNT_SECTION_ID ).unwrap(), Some( &[ 2 ][..] ) );
+    assert!( bytes.windows( 4 ).any( |window| window == [ BULK_PREFIX, MEMORY_INIT, 1, 0 ] ) );
+    assert!( bytes.windows( 4 ).any( |window| window == [ BULK_PREFIX, MEMORY_COPY, 0, 0 ] ) );
+
+    let ctx = Context::from_bytes( &bytes ).unwrap();
+    assert_eq!( definition( &ctx, "__wasm_init_tls" ).last(), Some( &Instruction::End ) );
+    assert!( definition( &ctx, "__wasm_init_tls" ).contains( &Instruction::MemoryInit( 0 ) ) );
+    assert_eq!( serialize( ctx.into_module() ).unwrap(), bytes );
+}
+
+#[test]
+fn test_without_data_segment_instructions() {
+    let bytes = from_standard_encoding( THREADED_MODULE ).unwrap();
+    let mut module: pw::Module = parity_wasm::deserialize_buffer( &bytes ).unwrap();
+    for body in module.code_section_mut().unwrap().bodies_mut() {
+        body.code_mut().elements_mut().retain( |instruction| {
+            match *instruction {
+                Instruction::MemoryInit( _ ) | Instruction::MemoryDrop( _ ) => false,
+                _ => true
+            }
+        });
+    }
+
+    let bytes = serialize( module ).unwrap();
+    assert_eq!( wasm_sections::find_section( &bytes, DATA_COUNT_SECTION_ID ).unwrap(), None );
+}
+
+#[test]
+fn test_pad_copy_instructions() {
+    let mut instructions = vec![ Instruction::MemoryCopy, Instruction::TableCopy, Instruction::Nop, Instruction::End ];
+    pad_copy_instructions( &mut instructions );
+    assert_eq!( instructions, vec![ Instruction::MemoryCopy, Instruction::Nop, Instruction::TableCopy, Instruction::Nop, Instruction::End ] );
+
+    let bytes = from_standard_encoding( THREADED_MODULE ).unwrap();
+    let mut module: pw::Module = parity_wasm::deserialize_buffer( &bytes ).unwrap();
+    for body in module.code_section_mut().unwrap().bodies_mut() {
+        body.code_mut().elements_mut().retain( |instruction| *instruction != Instruction::Nop );
+    }
+    assert!( serialize( module ).is_err() );
+}
@@ -8,6 +8,10 @@ use indexmap::IndexMap;
 use parity_wasm::elements as pw;
 use parity_wasm::elements::Deserialize;
 
+use wasm_bulk_memory;
+use wasm_dwarf::{self, FunctionLayout, RemappedFunction};
+use wasm_error::{ProcessingError, Stage};
+
 trait IterExt: Iterator + Sized {
     fn enumerate_u32( self ) -> iter::Map< iter::Enumerate< Self >, fn( (usize, Self::Item) ) -> (u32, Self::Item) > {
         self.enumerate().map( |(index, value)| (index as u32, value) )
@@ -123,6 +127,13 @@ impl ImportExport for FunctionKind {
 }
 
 impl FunctionKind {
+    pub fn name( &self ) -> Option< &str > {
+        match self {
+            &FunctionKind::Import { ref name, .. } => name.as_ref().map( |name| name.as_str() ),
+            &FunctionKind::Definition { ref name, .. } => name.as_ref().map( |name| name.as_str() )
+        }
+    }
+
     fn name_mut( &mut self ) -> &mut Option< String > {
         match self {
             &mut FunctionKind::Import { ref mut name, .. } => name,
@@ -134,7 +145,8 @@ impl FunctionKind {
 #[derive(Clone, PartialEq, Debug)]
 pub struct Limits {
     pub min: u32,
-    pub max: Option< u32 >
+    pub max: Option< u32 >,
+    pub shared: bool
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -211,24 +223,49 @@ impl ImportExport for MemoryKind {
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct GlobalType {
-    ty: ValueType,
-    is_mutable: bool
+    pub ty: ValueType,
+    pub is_mutable: bool
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum GlobalKind {
     Import {
         export: Export,
+        name: Option< String >,
         global_type: GlobalType,
         import: Import
     },
     Definition {
         export: Export,
+        name: Option< String >,
         global_type: GlobalType,
         initializer: Vec< Instruction >
     }
 }
 
+impl GlobalKind {
+    pub fn name( &self ) -> Option< &str > {
+        match self {
+            &GlobalKind::Import { ref name, .. } => name.as_ref().map( |name| name.as_str() ),
+            &GlobalKind::Definition { ref name, .. } => name.as_ref().map( |name| name.as_str() )
+        }
+    }
+
+    pub fn global_type( &self ) -> &GlobalType {
+        match self {
+            &GlobalKind::Import { ref global_type, .. } => global_type,
+            &GlobalKind::Definition { ref global_type, .. } => global_type
+        }
+    }
+
+    fn name_mut( &mut self ) -> &mut Option< String > {
+        match self {
+            &mut GlobalKind::Import { ref mut name, .. } => name,
+            &mut GlobalKind::Definition { ref mut name, .. } => name
+        }
+    }
+}
+
 impl ImportExport for GlobalKind {
     fn is_imported( &self ) -> bool {
         match self {
@@ -254,29 +291,38 @@ impl ImportExport for GlobalKind {
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct FnPointerTable {
-    members: Vec< FunctionIndex >,
-    offset: Vec< Instruction >
+    pub members: Vec< FunctionIndex >,
+    pub offset: Vec< Instruction >
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Data {
-    pub offset: Vec< Instruction >,
+    /// `None` for passive segments which are only copied
+    /// into the memory through `memory.init`.
+    pub offset: Option< Vec< Instruction > >,
     pub value: Vec< u8 >
 }
 
 impl Data {
     pub fn constant_offset( &self ) -> Option< i32 > {
-        if self.offset.len() != 2 {
+        let offset = self.offset.as_ref()?;
+        if offset.len() != 2 {
             return None;
         }
 
-        match (&self.offset[ 0 ], &self.offset[ 1 ]) {
+        match (&offset[ 0 ], &offset[ 1 ]) {
             (&Instruction::I32Const( offset ), &Instruction::End) => Some( offset ),
             _ => None
         }
     }
 }
 
+#[derive(Clone, Debug)]
+struct OriginalCode {
+    layout: FunctionLayout,
+    instructions: Vec< Instruction >
+}
+
 #[derive(Clone, Debug)]
 pub struct Context {
     pub types: IndexMap< TypeIndex, FnTy >,
@@ -289,6 +335,11 @@ pub struct Context {
     pub data: Vec< Data >,
     pub module_name: Option< String >,
     pub source_mapping_url: Option< String >,
+    pub debug_sections: Vec< (String, Vec< u8 >) >,
+    /// Problems with carrying over the debug info which didn't stop
+    /// the processing, but which the user should know about.
+    pub warnings: Vec< String >,
+    original_code: HashMap< FunctionIndex, OriginalCode >,
     next_function_index: u32,
     next_type_index: u32
 }
@@ -299,17 +350,35 @@ fn take< T: Default >( reference: &mut T ) -> T {
     out
 }
 
+fn parse_error< S: Into< String > >( message: S ) -> ProcessingError {
+    ProcessingError::new( Stage::Parsing, message )
+}
+
+fn read_var_u32( p: &mut &[u8] ) -> Result< u32, ProcessingError > {
+    pw::VarUint32::deserialize( p )
+        .map( u32::from )
+        .map_err( |error| parse_error( format!( "malformed custom section: {:?}", error ) ) )
+}
+
+fn split_payload< 'a >( p: &mut &'a [u8], length: u32 ) -> Result< &'a [u8], ProcessingError > {
+    if length as usize > p.len() {
+        return Err( parse_error( "malformed custom section: unexpected end of payload" ) );
+    }
+
+    let (payload, next_p) = p.split_at( length as usize );
+    *p = next_p;
+    Ok( payload )
+}
+
 // This is based on code from wasm-gc.
-fn decode_name_map< F: for< 'a > FnMut( u32, &'a str ) > ( p: &mut &[u8], mut callback: F ) -> Result< (), pw::Error > {
-    let count = u32::from( pw::VarUint32::deserialize( p )? );
+fn decode_name_map< F: for< 'a > FnMut( u32, &'a str ) -> Result< (), ProcessingError > > ( p: &mut &[u8], mut callback: F ) -> Result< (), ProcessingError > {
+    let count = read_var_u32( p )?;
     for _ in 0..count {
-        let index = u32::from( pw::VarUint32::deserialize( p )? );
-        let name_length = u32::from( pw::VarUint32::deserialize( p )? );
-        let (name, next_p) = p.split_at( name_length as usize );
-        *p = next_p;
-
-        let name = str::from_utf8( name ).expect( "function has an ill-formed name" );
-        callback( index, name );
+        let index = read_var_u32( p )?;
+        let name_length = read_var_u32( p )?;
+        let name = split_payload( p, name_length )?;
+        let name = str::from_utf8( name ).map_err( |_| parse_error( "function has an ill-formed name" ).in_function( index ) )?;
+        callback( index, name )?;
     }
 
     Ok(())
@@ -339,7 +408,8 @@ fn write_string( output: &mut Vec< u8 >, string: &str ) {
 fn serialize_name_section(
     module_name: Option< String >,
     function_names: Vec< (u32, String) >,
-    local_names: Vec< (u32, Vec< (u32, String) >) >
+    local_names: Vec< (u32, Vec< (u32, String) >) >,
+    global_names: Vec< (u32, String) >
 ) -> Vec< u8 > {
     let mut output = Vec::new();
     write_with_length( &mut output, move |body| {
@@ -367,6 +437,13 @@ fn serialize_name_section(
                 }
             });
         }
+
+        if !global_names.is_empty() {
+            pw::Serialize::serialize( pw::VarUint7::from( 7 ), body ).unwrap();
+            write_with_length( body, |inner_body| {
+                write_name_map( inner_body, &global_names );
+            });
+        }
     });
 
     output
@@ -413,12 +490,15 @@ impl Context {
             data: Default::default(),
             module_name: Default::default(),
             source_mapping_url: Default::default(),
+            debug_sections: Default::default(),
+            warnings: Default::default(),
+            original_code: Default::default(),
             next_function_index: 0,
             next_type_index: 0
         }
     }
 
-    pub fn from_module( mut module: pw::Module ) -> Self {
+    pub fn from_module( mut module: pw::Module ) -> Result< Self, ProcessingError > {
         let mut ctx = Self::new();
 
         let mut next_table_index = 0;
@@ -451,7 +531,9 @@ impl Context {
 
                         match entry.external() {
                             &pw::External::Function( type_index ) => {
-                                assert!( ctx.types.get( &type_index ).is_some() );
+                                if ctx.types.get( &type_index ).is_none() {
+                                    return Err( parse_error( format!( "import `{}.{}` refers to a non-existent type #{}", import.module, import.field, type_index ) ) );
+                                }
                                 ctx.functions.insert( ctx.next_function_index, FunctionKind::Import {
                                     export: Export::none(),
                                     type_index,
@@ -464,7 +546,8 @@ impl Context {
                             &pw::External::Table( ref table_type ) => {
                                 let limits = Limits {
                                     min: table_type.limits().initial(),
-                                    max: table_type.limits().maximum()
+                                    max: table_type.limits().maximum(),
+                                    shared: false
                                 };
                                 ctx.tables.insert( next_table_index, TableKind::Import {
                                     export: Export::none(),
@@ -476,7 +559,8 @@ impl Context {
                             &pw::External::Memory( ref memory_type ) => {
                                 let limits = Limits {
                                     min: memory_type.limits().initial(),
-                                    max: memory_type.limits().maximum()
+                                    max: memory_type.limits().maximum(),
+                                    shared: memory_type.limits().shared()
                                 };
                                 ctx.memories.insert( next_memory_index, MemoryKind::Import {
                                     export: Export::none(),
@@ -488,6 +572,7 @@ impl Context {
                             &pw::External::Global( ref global_type ) => {
                                 ctx.globals.insert( next_global_index, GlobalKind::Import {
                                     export: Export::none(),
+                                    name: None,
                                     global_type: GlobalType {
                                         ty: global_type.content_type(),
                                         is_mutable: global_type.is_mutable()
@@ -503,7 +588,8 @@ impl Context {
                     for table_type in take( section.entries_mut() ) {
                         let limits = Limits {
                             min: table_type.limits().initial(),
-                            max: table_type.limits().maximum()
+                            max: table_type.limits().maximum(),
+                            shared: false
                         };
                         ctx.tables.insert( next_table_index, TableKind::Definition {
                             export: Export::none(),
@@ -516,7 +602,8 @@ impl Context {
                     for memory_type in take( section.entries_mut() ) {
                         let limits = Limits {
                             min: memory_type.limits().initial(),
-                            max: memory_type.limits().maximum()
+                            max: memory_type.limits().maximum(),
+                            shared: memory_type.limits().shared()
                         };
                         ctx.memories.insert( next_memory_index, MemoryKind::Definition {
                             export: Export::none(),
@@ -529,6 +616,7 @@ impl Context {
                     for mut global in take( section.entries_mut() ) {
                         ctx.globals.insert( next_global_index, GlobalKind::Definition {
                             export: Export::none(),
+                            name: None,
                             global_type: GlobalType {
                                 ty: global.global_type().content_type(),
                                 is_mutable: global.global_type().is_mutable()
@@ -549,12 +637,20 @@ impl Context {
                     if entries.is_empty() {
                         continue;
                     }
-                    assert_eq!( entries.len(), 1, "multiple Element tables are not supported" );
-                    assert!( ctx.fn_pointer_tables.is_none(), "duplicate Element table" );
+                    if entries.len() != 1 {
+                        return Err( parse_error( "multiple Element tables are not supported" ) );
+                    }
+                    if ctx.fn_pointer_tables.is_some() {
+                        return Err( parse_error( "duplicate Element table" ) );
+                    }
                     let mut entry = entries.into_iter().next().unwrap();
+                    let offset = match entry.offset_mut().as_mut() {
+                        Some( offset ) => take( offset.code_mut() ),
+                        None => return Err( parse_error( "passive Element segments are not supported" ) )
+                    };
                     ctx.fn_pointer_tables = Some( FnPointerTable {
                         members: take( entry.members_mut() ),
-                        offset: take( entry.offset_mut().as_mut().unwrap().code_mut() )
+                        offset
                     });
                 },
                 pw::Section::Code( mut section ) => {
@@ -582,9 +678,14 @@ impl Context {
                     function_sections.push( section );
                 },
                 pw::Section::Data( mut section ) => {
-                    for mut entry in take( section.entries_mut() ) {
-                        if ctx.data.last().map( |last_data| {
-                            last_data.offset == entry.offset().as_ref().unwrap().code() && last_data.value.is_empty()
+                    let entries = take( section.entries_mut() );
+
+                    // Passive segments are referred to by their index, so we can't remove any.
+                    let has_passive_segments = entries.iter().any( |entry| entry.passive() );
+                    for mut entry in entries {
+                        let offset = entry.offset_mut().as_mut().map( |offset| take( offset.code_mut() ) );
+                        if !has_passive_segments && ctx.data.last().map( |last_data| {
+                            last_data.offset == offset && last_data.value.is_empty()
                         }).unwrap_or( false ) {
                             // Workaround for a `rustc`/LLVM bug where a duplicate empty data
                             // entries are generated.
@@ -592,7 +693,7 @@ impl Context {
                         }
 
                         ctx.data.push( Data {
-                            offset: take( entry.offset_mut().as_mut().unwrap().code_mut() ),
+                            offset,
                             value: take( entry.value_mut() )
                         });
                     }
@@ -603,52 +704,83 @@ impl Context {
 
                         let mut p: &[u8] = &payload;
                         while p.len() > 0 {
-                            let kind = u8::from( pw::VarUint7::deserialize( &mut p ).unwrap() );
-                            let payload_length = u32::from( pw::VarUint32::deserialize( &mut p ).unwrap() );
-                            let (mut payload, next_p) = p.split_at( payload_length as usize );
-                            p = next_p;
+                            let kind = u8::from( pw::VarUint7::deserialize( &mut p ).map_err( |error| parse_error( format!( "malformed name section: {:?}", error ) ) )? );
+                            let payload_length = read_var_u32( &mut p )?;
+                            let mut payload = split_payload( &mut p, payload_length )?;
 
                             match kind {
                                 0 => {
                                     ctx.module_name = Some(
                                         String::from_utf8( payload.to_vec() )
-                                            .expect( "module has an ill-formed name" )
+                                            .map_err( |_| parse_error( "module has an ill-formed name" ) )?
                                     );
                                 },
                                 1 => {
+                                    let functions = &mut ctx.functions;
                                     decode_name_map( &mut payload, |function_index, function_name| {
-                                        let name = ctx.functions.get_mut( &function_index ).unwrap().name_mut();
-                                        assert!( name.is_none() );
+                                        let name = functions.get_mut( &function_index )
+                                            .ok_or_else( || parse_error( "name section refers to a non-existent function" ).in_function( function_index ) )?
+                                            .name_mut();
+                                        if name.is_some() {
+                                            return Err( parse_error( "duplicate function name" ).in_function( function_index ) );
+                                        }
                                         *name = Some( function_name.to_owned() );
-                                    }).unwrap();
+                                        Ok(())
+                                    })?;
                                 },
                                 2 => {
-                                    let count = u32::from( pw::VarUint32::deserialize( &mut payload ).unwrap() );
+                                    let count = read_var_u32( &mut payload )?;
                                     for _ in 0..count {
-                                        let function_index = u32::from( pw::VarUint32::deserialize( &mut payload ).unwrap() );
+                                        let function_index = read_var_u32( &mut payload )?;
+                                        let functions = &mut ctx.functions;
                                         decode_name_map( &mut payload, |local_index, name| {
-                                            match ctx.functions.get_mut( &function_index ).unwrap() {
-                                                &mut FunctionKind::Definition { ref mut locals, .. } => {
-                                                    let local = &mut locals[ local_index as usize ];
-                                                    assert!( local.name.is_none(), "duplicate local variable name" );
+                                            match functions.get_mut( &function_index ) {
+                                                Some( &mut FunctionKind::Definition { ref mut locals, .. } ) => {
+                                                    let local = locals.get_mut( local_index as usize )
+                                                        .ok_or_else( || parse_error( format!( "name section refers to a non-existent local #{}", local_index ) ).in_function( function_index ) )?;
+                                                    if local.name.is_some() {
+                                                        return Err( parse_error( "duplicate local variable name" ).in_function( function_index ) );
+                                                    }
                                                     local.name = Some( name.to_owned() );
+                                                    Ok(())
                                                 },
-                                                _ => panic!()
+                                                _ => Err( parse_error( "name section has local names for something which isn't a function definition" ).in_function( function_index ) )
                                             }
-                                        }).unwrap();
+                                        })?;
                                     }
 
                                 },
-                                kind => panic!( "unknown name section chunk type: {}", kind )
+                                7 => {
+                                    let globals = &mut ctx.globals;
+                                    decode_name_map( &mut payload, |global_index, global_name| {
+                                        let name = globals.get_mut( &global_index )
+                                            .ok_or_else( || parse_error( format!( "name section refers to a non-existent global #{}", global_index ) ) )?
+                                            .name_mut();
+                                        if name.is_some() {
+                                            return Err( parse_error( format!( "duplicate name of global #{}", global_index ) ) );
+                                        }
+                                        *name = Some( global_name.to_owned() );
+                                        Ok(())
+                                    })?;
+                                },
+                                kind => {
+                                    // The rest of the extended name section (labels, types, data segments, etc.)
+                                    // is purely informational, so it's fine to drop it.
+                                    debug!( "Dropping unsupported name section chunk of type {}", kind );
+                                }
                             }
                         }
                     } else if section.name() == "sourceMappingURL" {
                         let payload = take( section.payload_mut() );
                         let mut p: &[u8] = &payload;
-                        let url_length = u32::from( pw::VarUint32::deserialize( &mut p ).unwrap() );
-                        let url = str::from_utf8( &p[ 0..url_length as usize ] ).expect( "invalid sourceMappingURL" );
+                        let url_length = read_var_u32( &mut p )?;
+                        let url = split_payload( &mut p, url_length )?;
+                        let url = str::from_utf8( url ).map_err( |_| parse_error( "invalid sourceMappingURL" ) )?;
                         ctx.source_mapping_url = Some( url.to_owned() );
-                    } else if section.name() == "linking" || section.name().starts_with( ".debug_" ) {
+                    } else if section.name().starts_with( ".debug_" ) {
+                        let name = section.name().to_owned();
+                        ctx.debug_sections.push( (name, take( section.payload_mut() )) );
+                    } else if section.name() == "linking" {
                         // TODO: Support this section.
                     } else {
                         debug!( "unsupported custom section: '{}'", section.name() );
@@ -658,20 +790,26 @@ impl Context {
                     // Section::Name is only emitted when calling module.parse_names()
                     unreachable!()
                 },
-                pw::Section::Reloc(_) |
-                pw::Section::Unparsed { .. } => { unimplemented!() },
+                pw::Section::Reloc(_) => {
+                    return Err( parse_error( "relocatable modules are not supported" ) );
+                },
+                pw::Section::Unparsed { id, .. } => {
+                    return Err( parse_error( format!( "unsupported section with ID {}", id ) ) );
+                }
             }
         }
 
         for mut section in function_sections {
             let mut function_index = function_imports_count;
             for entry in take( section.entries_mut() ) {
-                match ctx.functions.get_mut( &function_index ).unwrap() {
-                    &mut FunctionKind::Definition { ref mut type_index, .. } => {
-                        assert_eq!( *type_index, 0xFFFFFFFF, "function type was already set" );
+                match ctx.functions.get_mut( &function_index ) {
+                    Some( &mut FunctionKind::Definition { ref mut type_index, .. } ) => {
+                        if *type_index != 0xFFFFFFFF {
+                            return Err( parse_error( "function type was already set" ).in_function( function_index ) );
+                        }
                         *type_index = entry.type_ref();
                     },
-                    _ => panic!()
+                    _ => return Err( parse_error( "function section doesn't match the code section" ).in_function( function_index ) )
                 }
                 function_index += 1;
             }
@@ -680,27 +818,79 @@ impl Context {
         for mut section in exports_sections {
             for mut entry in take( section.entries_mut() ) {
                 let name = take( entry.field_mut() );
-                match entry.internal() {
-                    &pw::Internal::Function( function_index ) => {
-                        ctx.functions.get_mut( &function_index ).unwrap().as_export_mut().names.push( name );
-                    },
-                    &pw::Internal::Table( table_index ) => {
-                        ctx.tables.get_mut( &table_index ).unwrap().as_export_mut().names.push( name );
-                    },
-                    &pw::Internal::Memory( memory_index ) => {
-                        ctx.memories.get_mut( &memory_index ).unwrap().as_export_mut().names.push( name );
-                    },
-                    &pw::Internal::Global( global_index ) => {
-                        ctx.globals.get_mut( &global_index ).unwrap().as_export_mut().names.push( name );
-                    }
+                let export = match entry.internal() {
+                    &pw::Internal::Function( function_index ) => ctx.functions.get_mut( &function_index ).map( |function| function.as_export_mut() ),
+                    &pw::Internal::Table( table_index ) => ctx.tables.get_mut( &table_index ).map( |table| table.as_export_mut() ),
+                    &pw::Internal::Memory( memory_index ) => ctx.memories.get_mut( &memory_index ).map( |memory| memory.as_export_mut() ),
+                    &pw::Internal::Global( global_index ) => ctx.globals.get_mut( &global_index ).map( |global| global.as_export_mut() )
+                };
+
+                match export {
+                    Some( export ) => export.names.push( name ),
+                    None => return Err( parse_error( format!( "export `{}` refers to a non-existent entity", name ) ) )
                 }
             }
         }
 
-        ctx
+        Ok( ctx )
+    }
+
+    /// Same as `from_module`, except it also keeps track of where
+    /// every instruction was in the original code section so that
+    /// the DWARF debug info can be carried over by `into_module`.
+    ///
+    /// Modules which use the bulk memory instructions are accepted too.
+    pub fn from_bytes( bytes: &[u8] ) -> Result< Self, ProcessingError > {
+        let bytes = wasm_bulk_memory::from_standard_encoding( bytes ).map_err( |error| parse_error( format!( "{:?}", error ) ) )?;
+        let module = pw::deserialize_buffer( &bytes ).map_err( |error| parse_error( format!( "{:?}", error ) ) )?;
+        let mut ctx = Self::from_module( module )?;
+        if ctx.debug_sections.is_empty() {
+            return Ok( ctx );
+        }
+
+        let layouts = match wasm_dwarf::scan_code_layout( &bytes ) {
+            Ok( layouts ) => layouts,
+            Err( error ) => {
+                ctx.warnings.push( format!( "cannot scan the code section; the DWARF debug info will be dropped: {:?}", error ) );
+                return Ok( ctx );
+            }
+        };
+
+        let definitions = ctx.functions.iter().filter_map( |(&function_index, function)| {
+            match function {
+                &FunctionKind::Definition { ref instructions, .. } => Some( (function_index, instructions) ),
+                _ => None
+            }
+        });
+
+        for ((function_index, instructions), layout) in definitions.zip( layouts ) {
+            if layout.instructions.len() != instructions.len() {
+                let warning = format!(
+                    "function #{} has {} instructions in the code section, but {} were parsed; its debug info will be dropped",
+                    function_index,
+                    layout.instructions.len(),
+                    instructions.len()
+                );
+                ctx.warnings.push( warning );
+                continue;
+            }
+
+            ctx.original_code.insert( function_index, OriginalCode {
+                layout,
+                instructions: instructions.clone()
+            });
+        }
+
+        Ok( ctx )
     }
 
     pub fn into_module( self ) -> pw::Module {
+        self.into_module_with_warnings().0
+    }
+
+    /// Same as `into_module`, except it also returns the `warnings`,
+    /// including the ones about the debug info which couldn't be carried over.
+    pub fn into_module_with_warnings( self ) -> (pw::Module, Vec< String >) {
         fn process_instructions(
             function_index_map: &HashMap< FunctionIndex, FunctionIndex >,
             type_index_map: &HashMap< TypeIndex, TypeIndex >,
@@ -739,6 +929,7 @@ impl Context {
         let mut section_data = Vec::new();
         let mut function_names = Vec::new();
         let mut function_variable_names = Vec::new();
+        let mut global_names = Vec::new();
 
         for (new_type_index, (old_type_index, ty)) in self.types.into_iter().enumerate_u32() {
             type_map.insert( old_type_index, new_type_index );
@@ -748,6 +939,14 @@ impl Context {
         }
 
         let functions = preprocess_entities( self.functions );
+        let mut original_code = self.original_code;
+        let mut remapped_functions = Vec::new();
+        let old_function_indexes: HashMap< FunctionIndex, FunctionIndex > = if original_code.is_empty() {
+            HashMap::new()
+        } else {
+            functions.index_map.iter().map( |(&old_index, &new_index)| (new_index, old_index) ).collect()
+        };
+
         let tables = preprocess_entities( self.tables );
         let memories = preprocess_entities( self.memories );
         let globals = preprocess_entities( self.globals );
@@ -770,6 +969,7 @@ impl Context {
                 },
                 FunctionKind::Definition { name, type_index, locals, mut instructions, export } => {
                     let type_index = type_map.get( &type_index ).cloned().unwrap();
+                    wasm_bulk_memory::pad_copy_instructions( &mut instructions );
                     let mut local_names = Vec::new();
                     let locals = locals.into_iter().enumerate_u32().map( |(local_index, local)| {
                         if let Some( local_name ) = local.name {
@@ -778,6 +978,15 @@ impl Context {
                         pw::Local::new( local.count, local.ty )
                     }).collect();
 
+                    let original = old_function_indexes.get( &new_index ).and_then( |old_index| original_code.remove( old_index ) );
+                    if let Some( original ) = original {
+                        remapped_functions.push( RemappedFunction {
+                            new_body_index: section_code.len(),
+                            alignment: wasm_dwarf::align( &original.instructions, &instructions ),
+                            old_layout: original.layout
+                        });
+                    }
+
                     process_instructions( &functions.index_map, &type_map, &globals.index_map, &mut instructions );
 
                     if let Some( name ) = name {
@@ -826,12 +1035,12 @@ impl Context {
                     section_imports.push( pw::ImportEntry::new(
                         import.module,
                         import.field,
-                        pw::External::Memory( pw::MemoryType::new( limits.min, limits.max, false ) )
+                        pw::External::Memory( pw::MemoryType::new( limits.min, limits.max, limits.shared ) )
                     ));
                     export
                 },
                 MemoryKind::Definition { limits, export } => {
-                    section_memories.push( pw::MemoryType::new( limits.min, limits.max, false ) );
+                    section_memories.push( pw::MemoryType::new( limits.min, limits.max, limits.shared ) );
                     export
                 }
             };
@@ -843,7 +1052,11 @@ impl Context {
 
         for (new_index, global) in globals.entries {
             let export = match global {
-                GlobalKind::Import { global_type, import, export } => {
+                GlobalKind::Import { global_type, import, export, name } => {
+                    if let Some( name ) = name {
+                        global_names.push( (new_index, name) );
+                    }
+
                     section_imports.push( pw::ImportEntry::new(
                         import.module,
                         import.field,
@@ -852,7 +1065,11 @@ impl Context {
 
                     export
                 },
-                GlobalKind::Definition { global_type, mut initializer, export } => {
+                GlobalKind::Definition { global_type, mut initializer, export, name } => {
+                    if let Some( name ) = name {
+                        global_names.push( (new_index, name) );
+                    }
+
                     process_instructions( &functions.index_map, &type_map, &globals.index_map, &mut initializer );
 
                     let global_type = pw::GlobalType::new( global_type.ty, global_type.is_mutable );
@@ -879,7 +1096,8 @@ impl Context {
         }
 
         for data in self.data {
-            section_data.push( pw::DataSegment::new( 0, Some( pw::InitExpr::new( data.offset ) ), data.value, false ) );
+            let passive = data.offset.is_none();
+            section_data.push( pw::DataSegment::new( 0, data.offset.map( pw::InitExpr::new ), data.value, passive ) );
         }
 
         if !section_types.is_empty() {
@@ -927,8 +1145,8 @@ impl Context {
             sections.push( pw::Section::Data( pw::DataSection::with_entries( section_data ) ) );
         }
 
-        if self.module_name.is_some() || !function_names.is_empty() || !function_variable_names.is_empty() {
-            let name_section_bytes = serialize_name_section( self.module_name, function_names, function_variable_names );
+        if self.module_name.is_some() || !function_names.is_empty() || !function_variable_names.is_empty() || !global_names.is_empty() {
+            let name_section_bytes = serialize_name_section( self.module_name, function_names, function_variable_names, global_names );
             sections.push( pw::Section::Custom(
                 pw::CustomSection::deserialize( &mut name_section_bytes.as_slice() ).unwrap()
             ));
@@ -945,7 +1163,33 @@ impl Context {
             ));
         }
 
-        pw::Module::new( sections )
+        let mut warnings = self.warnings;
+        let mut module = pw::Module::new( sections );
+        if !self.debug_sections.is_empty() {
+            if remapped_functions.is_empty() {
+                debug!( "Dropping the DWARF debug info since the original code offsets are unknown" );
+            } else {
+                match wasm_dwarf::remap( &module, remapped_functions, self.debug_sections ) {
+                    Ok( debug_sections ) => {
+                        for (name, payload) in debug_sections {
+                            let mut section_bytes = Vec::new();
+                            write_with_length( &mut section_bytes, move |body| {
+                                write_string( body, &name );
+                                body.extend_from_slice( &payload );
+                            });
+                            module.sections_mut().push( pw::Section::Custom(
+                                pw::CustomSection::deserialize( &mut section_bytes.as_slice() ).unwrap()
+                            ));
+                        }
+                    },
+                    Err( error ) => {
+                        warnings.push( format!( "cannot carry over the DWARF debug info: {}", error ) );
+                    }
+                }
+            }
+        }
+
+        (module, warnings)
     }
 
     pub fn add_function( &mut self, function: FunctionKind ) -> FunctionIndex {
@@ -1003,7 +1247,7 @@ fn test_serialization_deserialization() {
         instructions: vec![]
     });
 
-    let new_ctx = Context::from_module( ctx.clone().into_module() );
+    let new_ctx = Context::from_module( ctx.clone().into_module() ).unwrap();
     assert_eq!( new_ctx.module_name, ctx.module_name );
     assert_eq!( new_ctx.functions, ctx.functions );
     assert_eq!( new_ctx.types, ctx.types );
@@ -1040,7 +1284,7 @@ fn test_function_import_removal() {
     ctx.start = Some( 1 );
 
     ctx.functions.remove( &0 );
-    let new_ctx = Context::from_module( ctx.clone().into_module() );
+    let new_ctx = Context::from_module( ctx.clone().into_module() ).unwrap();
     assert_eq!( new_ctx.functions.len(), 1 );
 
     match new_ctx.functions[ &0 ] {
@@ -1054,3 +1298,141 @@ fn test_function_import_removal() {
 
     assert_eq!( new_ctx.start, Some( 0 ) );
 }
+
+#[cfg(test)]
+fn context_with_every_entity() -> Context {
+    let mut ctx = Context::new();
+    let void_type = ctx.get_or_add_fn_type( FnTy { params: vec![], return_type: None } );
+    let unary_type = ctx.get_or_add_fn_type( FnTy { params: vec![ ValueType::I32 ], return_type: Some( ValueType::I32 ) } );
+
+    let log = ctx.add_function( FunctionKind::Import {
+        type_index: unary_type,
+        export: Export::none(),
+        import: Import {
+            module: "env".to_owned(),
+            field: "log".to_owned()
+        },
+        name: Some( "log".to_owned() )
+    });
+
+    ctx.add_function( FunctionKind::Definition {
+        type_index: void_type,
+        export: Export::none(),
+        name: Some( "unused".to_owned() ),
+        locals: vec![],
+        instructions: vec![ Instruction::End ]
+    });
+
+    let callee = ctx.add_function( FunctionKind::Definition {
+        type_index: unary_type,
+        export: Export::none(),
+        name: Some( "callee".to_owned() ),
+        locals: vec![],
+        instructions: vec![
+            Instruction::GetLocal( 0 ),
+            Instruction::GetGlobal( 1 ),
+            Instruction::I32Add,
+            Instruction::End
+        ]
+    });
+
+    let main = ctx.add_function( FunctionKind::Definition {
+        type_index: void_type,
+        export: Export::some( "main".to_owned() ),
+        name: Some( "main".to_owned() ),
+        locals: vec![],
+        instructions: vec![
+            Instruction::I32Const( 1 ),
+            Instruction::Call( callee ),
+            Instruction::Call( log ),
+            Instruction::I32Const( 0 ),
+            Instruction::CallIndirect( unary_type, 0 ),
+            Instruction::SetGlobal( 1 ),
+            Instruction::End
+        ]
+    });
+
+    ctx.memories.insert( 0, MemoryKind::Definition {
+        export: Export::some( "memory".to_owned() ),
+        limits: Limits { min: 1, max: None, shared: false }
+    });
+
+    ctx.tables.insert( 0, TableKind::Definition {
+        export: Export::none(),
+        limits: Limits { min: 2, max: None, shared: false }
+    });
+
+    ctx.globals.insert( 0, GlobalKind::Import {
+        export: Export::none(),
+        name: None,
+        global_type: GlobalType { ty: ValueType::I32, is_mutable: false },
+        import: Import {
+            module: "env".to_owned(),
+            field: "base".to_owned()
+        }
+    });
+
+    ctx.globals.insert( 1, GlobalKind::Definition {
+        export: Export::none(),
+        name: Some( "counter".to_owned() ),
+        global_type: GlobalType { ty: ValueType::I32, is_mutable: true },
+        initializer: vec![ Instruction::I32Const( 1024 ), Instruction::End ]
+    });
+
+    ctx.data.push( Data {
+        offset: Some( vec![ Instruction::GetGlobal( 0 ), Instruction::End ] ),
+        value: b"hello".to_vec()
+    });
+
+    ctx.fn_pointer_tables = Some( FnPointerTable {
+        members: vec![ callee ],
+        offset: vec![ Instruction::I32Const( 1 ), Instruction::End ]
+    });
+
+    ctx.start = Some( main );
+    ctx
+}
+
+#[test]
+fn test_round_trip_validation() {
+    use wasm_validate::validate;
+
+    let ctx = context_with_every_entity();
+    let module = ctx.clone().into_module();
+    validate( &module ).unwrap();
+
+    let new_ctx = Context::from_module( module ).unwrap();
+    assert_eq!( new_ctx.functions, ctx.functions );
+    assert_eq!( new_ctx.globals, ctx.globals );
+    assert_eq!( new_ctx.data, ctx.data );
+    assert_eq!( new_ctx.fn_pointer_tables, ctx.fn_pointer_tables );
+    assert_eq!( new_ctx.start, ctx.start );
+    validate( &new_ctx.into_module() ).unwrap();
+}
+
+#[test]
+fn test_round_trip_validation_after_removal() {
+    use wasm_validate::validate;
+
+    let mut ctx = context_with_every_entity();
+    ctx.functions.remove( &1 );
+
+    let module = ctx.into_module();
+    validate( &module ).unwrap();
+
+    let new_ctx = Context::from_module( module ).unwrap();
+    assert_eq!( new_ctx.functions.len(), 3 );
+
+    let index_of = |name| new_ctx.functions.iter().find( |&(_, function)| function.name() == Some( name ) ).map( |(&index, _)| index ).unwrap();
+    let (log, callee, main) = (index_of( "log" ), index_of( "callee" ), index_of( "main" ));
+    assert_eq!( new_ctx.fn_pointer_tables.as_ref().unwrap().members, vec![ callee ] );
+    assert_eq!( new_ctx.start, Some( main ) );
+
+    match new_ctx.functions[ &main ] {
+        FunctionKind::Definition { ref instructions, .. } => {
+            assert_eq!( instructions[ 1 ], Instruction::Call( callee ) );
+            assert_eq!( instructions[ 2 ], Instruction::Call( log ) );
+        },
+        _ => panic!()
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+
+use parity_wasm::elements as pw;
+
+use wasm_inline_js::JsSnippet;
+use wasm_context::{
+    Context,
+    FunctionKind,
+    FunctionIndex,
+    GlobalIndex,
+    GlobalKind,
+    ImportExport,
+    Instruction,
+    TypeIndex
+};
+
+#[derive(Default, Debug)]
+pub struct RemovedStats {
+    pub functions: usize,
+    pub imports: usize,
+    pub globals: usize,
+    pub types: usize,
+    pub bytes: usize
+}
+
+fn serialized_size< T: pw::Serialize >( value: T ) -> usize {
+    let mut buffer = Vec::new();
+    value.serialize( &mut buffer ).map( |_| buffer.len() ).unwrap_or( 0 )
+}
+
+fn import_size( module: &str, field: &str ) -> usize {
+    // The module name, the field name and the kind with its index.
+    serialized_size( pw::VarUint32::from( module.len() ) ) + module.len() +
+    serialized_size( pw::VarUint32::from( field.len() ) ) + field.len() +
+    2
+}
+
+#[derive(Default)]
+struct Live {
+    functions: HashSet< FunctionIndex >,
+    globals: HashSet< GlobalIndex >,
+    types: HashSet< TypeIndex >,
+    pending_functions: Vec< FunctionIndex >
+}
+
+impl Live {
+    fn add_function( &mut self, function_index: FunctionIndex ) {
+        if self.functions.insert( function_index ) {
+            self.pending_functions.push( function_index );
+        }
+    }
+
+    fn visit_instructions( &mut self, instructions: &[Instruction] ) {
+        for instruction in instructions {
+            match instruction {
+                &Instruction::Call( function_index ) => self.add_function( function_index ),
+                &Instruction::CallIndirect( type_index, _ ) => {
+                    self.types.insert( type_index );
+                },
+                &Instruction::GetGlobal( global_index ) |
+                &Instruction::SetGlobal( global_index ) => {
+                    self.globals.insert( global_index );
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Removes every function, global and type which isn't reachable
+/// from the exports, the start function or the function table.
+///
+/// This should be run after every other pass since those can
+/// leave behind imports and helpers which are no longer used.
+pub fn process( ctx: &mut Context ) -> RemovedStats {
+    let mut live = Live::default();
+
+    for (&function_index, function) in &ctx.functions {
+        if function.is_exported() {
+            live.add_function( function_index );
+        }
+    }
+
+    if let Some( start ) = ctx.start {
+        live.add_function( start );
+    }
+
+    if let Some( ref table ) = ctx.fn_pointer_tables {
+        for &function_index in &table.members {
+            live.add_function( function_index );
+        }
+        live.visit_instructions( &table.offset );
+    }
+
+    for data in &ctx.data {
+        if let Some( ref offset ) = data.offset {
+            live.visit_instructions( offset );
+        }
+    }
+
+    for (&global_index, global) in &ctx.globals {
+        if global.is_exported() {
+            live.globals.insert( global_index );
+        }
+
+        // Initializers can only refer to imported globals, so we conservatively
+        // keep those alive even if the global itself turns out to be unused.
+        if let &GlobalKind::Definition { ref initializer, .. } = global {
+            live.visit_instructions( initializer );
+        }
+    }
+
+    while let Some( function_index ) = live.pending_functions.pop() {
+        match ctx.functions.get( &function_index ) {
+            Some( &FunctionKind::Import { type_index, .. } ) => {
+                live.types.insert( type_index );
+            },
+            Some( &FunctionKind::Definition { type_index, ref instructions, .. } ) => {
+                live.types.insert( type_index );
+                live.visit_instructions( instructions );
+            },
+            None => {}
+        }
+    }
+
+    let mut stats = RemovedStats::default();
+
+    for (function_index, function) in &ctx.functions {
+        if live.functions.contains( function_index ) {
+            continue;
+        }
+
+        match function {
+            &FunctionKind::Import { ref import, .. } => {
+                debug!( "Removing unused import: {}.{}", import.module, import.field );
+                stats.imports += 1;
+                stats.bytes += import_size( &import.module, &import.field );
+            },
+            &FunctionKind::Definition { ref name, ref locals, ref instructions, .. } => {
+                debug!( "Removing unused function: {}", name.as_ref().map( |name| name.as_str() ).unwrap_or( "<unnamed>" ) );
+                let locals = locals.iter().map( |local| pw::Local::new( local.count, local.ty ) ).collect();
+                stats.functions += 1;
+                stats.bytes += serialized_size( pw::FuncBody::new( locals, pw::Instructions::new( instructions.clone() ) ) );
+
+                // The entry in the function section.
+                stats.bytes += 1;
+            }
+        }
+    }
+
+    for (global_index, global) in &ctx.globals {
+        if live.globals.contains( global_index ) {
+            continue;
+        }
+
+        match global {
+            &GlobalKind::Import { ref import, .. } => {
+                stats.imports += 1;
+                stats.bytes += import_size( &import.module, &import.field );
+            },
+            &GlobalKind::Definition { ref initializer, .. } => {
+                stats.globals += 1;
+                stats.bytes += 2 + serialized_size( pw::InitExpr::new( initializer.clone() ) );
+            }
+        }
+    }
+
+    for (type_index, ty) in &ctx.types {
+        if live.types.contains( type_index ) {
+            continue;
+        }
+
+        stats.types += 1;
+        stats.bytes += serialized_size( pw::Type::Function( pw::FunctionType::new( ty.params.clone(), ty.return_type ) ) );
+    }
+
+    // We use `retain` since it preserves the order of the remaining entries.
+    ctx.functions.retain( |index, _| live.functions.contains( index ) );
+    ctx.globals.retain( |index, _| live.globals.contains( index ) );
+    ctx.types.retain( |index, _| live.types.contains( index ) );
+
+    stats
+}
+
+/// Drops the JS snippets whose imports were removed as unused.
+pub fn retain_imported_snippets( ctx: &Context, snippets: &mut Vec< JsSnippet > ) {
+    let imported: HashSet< &str > = ctx.functions.values().filter_map( |function| {
+        match *function {
+            FunctionKind::Import { ref import, .. } if import.module == "env" => Some( import.field.as_str() ),
+            _ => None
+        }
+    }).collect();
+
+    snippets.retain( |snippet| imported.contains( snippet.name.as_str() ) );
+}
+
+#[test]
+fn test_dead_code_removal() {
+    use wasm_context::{Export, FnTy, Import, ValueType};
+
+    let mut ctx = Context::new();
+    let used_type = ctx.get_or_add_fn_type( FnTy { params: vec![], return_type: None } );
+    let unused_type = ctx.get_or_add_fn_type( FnTy { params: vec![ ValueType::I32 ], return_type: None } );
+    let used_import = ctx.add_function( FunctionKind::Import {
+        type_index: used_type,
+        export: Export::none(),
+        import: Import { module: "env".to_owned(), field: "used".to_owned() },
+        name: None
+    });
+    ctx.add_function( FunctionKind::Import {
+        type_index: unused_type,
+        export: Export::none(),
+        import: Import { module: "env".to_owned(), field: "unused".to_owned() },
+        name: None
+    });
+    ctx.add_function( FunctionKind::Definition {
+        type_index: used_type,
+        export: Export::some( "main".to_owned() ),
+        name: None,
+        locals: vec![],
+        instructions: vec![ Instruction::Call( used_import ), Instruction::End ]
+    });
+    ctx.add_function( FunctionKind::Definition {
+        type_index: used_type,
+        export: Export::none(),
+        name: None,
+        locals: vec![],
+        instructions: vec![ Instruction::Nop, Instruction::End ]
+    });
+
+    let stats = process( &mut ctx );
+    assert_eq!( stats.imports, 1 );
+    assert_eq!( stats.functions, 1 );
+    assert_eq!( stats.types, 1 );
+    assert!( stats.bytes > 0 );
+    assert_eq!( ctx.functions.keys().cloned().collect::< Vec< _ > >(), vec![ 0, 2 ] );
+    assert_eq!( ctx.types.keys().cloned().collect::< Vec< _ > >(), vec![ used_type ] );
+
+    let snippet = |name: &str| JsSnippet { name: name.to_owned(), code: "return 1;".to_owned(), arg_count: 0 };
+    let mut snippets = vec![ snippet( "used" ), snippet( "unused" ) ];
+    retain_imported_snippets( &ctx, &mut snippets );
+    assert_eq!( snippets.iter().map( |snippet| snippet.name.as_str() ).collect::< Vec< _ > >(), vec![ "used" ] );
+
+    let new_ctx = Context::from_module( ctx.into_module() ).unwrap();
+    assert_eq!( new_ctx.functions.len(), 2 );
+}
@@ -0,0 +1,1493 @@
+// In WebAssembly the code addresses in DWARF are offsets relative
+// to the start of the code section's payload, so every time we
+// regenerate the code section we need to remap them.
+//
+// Only 32-bit DWARF versions 2 to 4 are supported, which is
+// what LLVM emits for the `wasm32-unknown-unknown` target.
+
+use std::collections::HashMap;
+use std::cmp;
+use std::mem;
+
+use parity_wasm::elements as pw;
+use parity_wasm::elements::Deserialize;
+
+use wasm_sections::{self, CODE_SECTION_ID};
+
+// The same values `lld` uses for addresses of code which was removed.
+const TOMBSTONE: u32 = 0xffffffff;
+const LIST_TOMBSTONE: u32 = 0xfffffffe;
+
+const DW_AT_LOCATION: u64 = 0x02;
+const DW_AT_STMT_LIST: u64 = 0x10;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_STRING_LENGTH: u64 = 0x19;
+const DW_AT_RETURN_ADDR: u64 = 0x2a;
+const DW_AT_SEGMENT: u64 = 0x2e;
+const DW_AT_DATA_MEMBER_LOCATION: u64 = 0x38;
+const DW_AT_FRAME_BASE: u64 = 0x40;
+const DW_AT_STATIC_LINK: u64 = 0x48;
+const DW_AT_USE_LOCATION: u64 = 0x4a;
+const DW_AT_VTABLE_ELEM_LOCATION: u64 = 0x4d;
+const DW_AT_ENTRY_PC: u64 = 0x52;
+const DW_AT_RANGES: u64 = 0x55;
+
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_BLOCK2: u64 = 0x03;
+const DW_FORM_BLOCK4: u64 = 0x04;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_BLOCK1: u64 = 0x0a;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_FLAG: u64 = 0x0c;
+const DW_FORM_SDATA: u64 = 0x0d;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_REF_ADDR: u64 = 0x10;
+const DW_FORM_REF1: u64 = 0x11;
+const DW_FORM_REF2: u64 = 0x12;
+const DW_FORM_REF4: u64 = 0x13;
+const DW_FORM_REF8: u64 = 0x14;
+const DW_FORM_REF_UDATA: u64 = 0x15;
+const DW_FORM_INDIRECT: u64 = 0x16;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_FORM_EXPRLOC: u64 = 0x18;
+const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+const DW_FORM_REF_SIG8: u64 = 0x20;
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_SET_COLUMN: u8 = 5;
+const DW_LNS_NEGATE_STMT: u8 = 6;
+const DW_LNS_SET_BASIC_BLOCK: u8 = 7;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+const DW_LNS_SET_PROLOGUE_END: u8 = 10;
+const DW_LNS_SET_EPILOGUE_BEGIN: u8 = 11;
+const DW_LNS_SET_ISA: u8 = 12;
+
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+const DW_LNE_SET_DISCRIMINATOR: u8 = 4;
+
+/// The offsets of a function body within the code section's payload.
+#[derive(Clone, Debug)]
+pub struct FunctionLayout {
+    pub start: u32,
+    pub locals_start: u32,
+    pub instructions: Vec< u32 >,
+    pub end: u32
+}
+
+fn offset_of( base: &[u8], slice: &[u8] ) -> u32 {
+    (slice.as_ptr() as usize - base.as_ptr() as usize) as u32
+}
+
+fn split_off< 'a >( p: &mut &'a [u8], length: usize ) -> Result< &'a [u8], pw::Error > {
+    if length > p.len() {
+        return Err( pw::Error::UnexpectedEof );
+    }
+
+    let (head, tail) = p.split_at( length );
+    *p = tail;
+    Ok( head )
+}
+
+/// Returns the offset of the code section's payload within the module.
+pub fn code_section_offset( bytes: &[u8] ) -> Result< Option< u32 >, pw::Error > {
+    let payload = wasm_sections::find_section( bytes, CODE_SECTION_ID )?;
+    Ok( payload.map( |payload| offset_of( bytes, payload ) ) )
+}
+
+/// Returns the layout of every function body in the code section, in order.
+pub fn scan_code_layout( bytes: &[u8] ) -> Result< Vec< FunctionLayout >, pw::Error > {
+    match wasm_sections::find_section( bytes, CODE_SECTION_ID )? {
+        Some( payload ) => scan_code_section( payload ),
+        None => Ok( Vec::new() )
+    }
+}
+
+fn scan_code_section( payload: &[u8] ) -> Result< Vec< FunctionLayout >, pw::Error > {
+    let mut p = payload;
+    let count = u32::from( pw::VarUint32::deserialize( &mut p )? );
+    let mut functions = Vec::with_capacity( count as usize );
+    for _ in 0..count {
+        let start = offset_of( payload, p );
+        let size = u32::from( pw::VarUint32::deserialize( &mut p )? ) as usize;
+        let mut body = split_off( &mut p, size )?;
+
+        let locals_start = offset_of( payload, body );
+        let local_count = u32::from( pw::VarUint32::deserialize( &mut body )? );
+        for _ in 0..local_count {
+            pw::Local::deserialize( &mut body )?;
+        }
+
+        let mut instructions = Vec::new();
+        while !body.is_empty() {
+            instructions.push( offset_of( payload, body ) );
+            pw::Instruction::deserialize( &mut body )?;
+        }
+
+        functions.push( FunctionLayout {
+            start,
+            locals_start,
+            instructions,
+            end: offset_of( payload, p )
+        });
+    }
+
+    Ok( functions )
+}
+
+/// Matches the instructions of a modified function with its original
+/// instructions; returns the index of the original instruction for every
+/// new instruction, or `None` if it was inserted.
+pub fn align< T: PartialEq >( old: &[T], new: &[T] ) -> Vec< Option< usize > > {
+    let mut output = vec![ None; new.len() ];
+
+    let prefix = old.iter().zip( new.iter() ).take_while( |&(a, b)| a == b ).count();
+    for index in 0..prefix {
+        output[ index ] = Some( index );
+    }
+
+    let suffix = old[ prefix.. ].iter().rev().zip( new[ prefix.. ].iter().rev() ).take_while( |&(a, b)| a == b ).count();
+    for index in 0..suffix {
+        output[ new.len() - 1 - index ] = Some( old.len() - 1 - index );
+    }
+
+    let old_middle = &old[ prefix..old.len() - suffix ];
+    let new_middle = &new[ prefix..new.len() - suffix ];
+    if let Some( matches ) = diff( old_middle, new_middle ) {
+        for (old_index, new_index) in matches {
+            output[ prefix + new_index ] = Some( prefix + old_index );
+        }
+    }
+
+    output
+}
+
+// We only ever insert or replace a few instructions here and there,
+// so if there are more edits than this then something else is going on.
+const MAXIMUM_EDIT_DISTANCE: isize = 1024;
+
+// Picks the furthest reaching path on diagonal `k` given the furthest
+// reaching paths on the neighbouring diagonals (`-1` if there are none).
+fn choose_path( k: isize, down: isize, right: isize, n: isize, m: isize ) -> Option< (isize, isize) > {
+    let down = if down >= 0 && down - k <= m { Some( down ) } else { None };
+    let right = if right >= 0 && right < n { Some( right + 1 ) } else { None };
+    match (down, right) {
+        (Some( down ), Some( right )) => if right > down { Some( (k - 1, right) ) } else { Some( (k + 1, down) ) },
+        (Some( down ), None) => Some( (k + 1, down) ),
+        (None, Some( right )) => Some( (k - 1, right) ),
+        (None, None) => None
+    }
+}
+
+// This is Myers' O(ND) difference algorithm.
+fn diff< T: PartialEq >( a: &[T], b: &[T] ) -> Option< Vec< (usize, usize) > > {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = cmp::min( n + m, MAXIMUM_EDIT_DISTANCE );
+    let offset = max + 1;
+    let mut v = vec![ -1_isize; (2 * max + 3) as usize ];
+    let mut trace: Vec< Vec< isize > > = Vec::new();
+    v[ (offset + 1) as usize ] = 0;
+
+    let mut found = false;
+    'outer: for d in 0..max + 1 {
+        let previous = v.clone();
+        let mut k = -d;
+        while k <= d {
+            let down = previous[ (offset + k + 1) as usize ];
+            let right = previous[ (offset + k - 1) as usize ];
+            let mut x = match choose_path( k, down, right, n, m ) {
+                Some( (_, x) ) => x,
+                None => {
+                    v[ (offset + k) as usize ] = -1;
+                    k += 2;
+                    continue;
+                }
+            };
+
+            let mut y = x - k;
+            while x < n && y < m && a[ x as usize ] == b[ y as usize ] {
+                x += 1;
+                y += 1;
+            }
+
+            v[ (offset + k) as usize ] = x;
+            if x == n && y == m {
+                found = true;
+                break 'outer;
+            }
+
+            k += 2;
+        }
+
+        trace.push( v[ (offset - d) as usize..(offset + d + 1) as usize ].to_vec() );
+    }
+
+    if !found {
+        return None;
+    }
+
+    let mut matches = Vec::new();
+    let mut x = n;
+    let mut y = m;
+    for d in (1..trace.len() + 1).rev() {
+        let d = d as isize;
+        let previous = &trace[ (d - 1) as usize ];
+        let get = |k: isize| if k.abs() <= d - 1 { previous[ (k + d - 1) as usize ] } else { -1 };
+
+        let k = x - y;
+        let (previous_k, _) = choose_path( k, get( k + 1 ), get( k - 1 ), n, m ).unwrap();
+        let previous_x = get( previous_k );
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            matches.push( (x as usize, y as usize) );
+        }
+
+        x = previous_x;
+        y = previous_y;
+    }
+
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push( (x as usize, y as usize) );
+    }
+
+    matches.reverse();
+    Some( matches )
+}
+
+struct MappedFunction {
+    old_start: u32,
+    old_end: u32,
+    new_end: u32,
+    // Pairs of old and new addresses, sorted by the old address.
+    points: Vec< (u32, u32) >
+}
+
+/// Maps code addresses from the original code section to the new one.
+pub struct AddressMap {
+    functions: Vec< MappedFunction >
+}
+
+impl AddressMap {
+    fn new( mut functions: Vec< MappedFunction > ) -> Self {
+        functions.sort_by_key( |function| function.old_start );
+        AddressMap {
+            functions
+        }
+    }
+
+    fn lookup( function: &MappedFunction, address: u32 ) -> u32 {
+        match function.points.binary_search_by_key( &address, |&(old, _)| old ) {
+            Ok( index ) => function.points[ index ].1,
+            Err( index ) => function.points[ index - 1 ].1
+        }
+    }
+
+    /// Maps an address which is the start of something, e.g. a `DW_AT_low_pc`.
+    pub fn map_start( &self, address: u32 ) -> Option< u32 > {
+        if address == 0 {
+            return Some( 0 );
+        }
+
+        let index = match self.functions.binary_search_by_key( &address, |function| function.old_start ) {
+            Ok( index ) => index,
+            Err( 0 ) => return None,
+            Err( index ) => index - 1
+        };
+
+        let function = &self.functions[ index ];
+        if address >= function.old_end {
+            return None;
+        }
+
+        Some( Self::lookup( function, address ) )
+    }
+
+    /// Maps an address which is the (exclusive) end of something, e.g. a `DW_AT_high_pc`.
+    pub fn map_end( &self, address: u32 ) -> Option< u32 > {
+        if address == 0 {
+            return Some( 0 );
+        }
+
+        let index = match self.functions.binary_search_by_key( &address, |function| function.old_start ) {
+            Ok( 0 ) | Err( 0 ) => return None,
+            Ok( index ) | Err( index ) => index - 1
+        };
+
+        let function = &self.functions[ index ];
+        if address > function.old_end {
+            return None;
+        }
+
+        if address == function.old_end {
+            return Some( function.new_end );
+        }
+
+        Some( Self::lookup( function, address ) )
+    }
+}
+
+/// An original function which made it into the new code section.
+pub struct RemappedFunction {
+    /// The index of the function's body in the new code section.
+    pub new_body_index: usize,
+    pub old_layout: FunctionLayout,
+    /// The result of `align`ing the new instructions with the old ones.
+    pub alignment: Vec< Option< usize > >
+}
+
+/// Rewrites the code addresses in the `.debug_*` sections
+/// to match the code section of the given `module`.
+pub fn remap( module: &pw::Module, functions: Vec< RemappedFunction >, sections: Vec< (String, Vec< u8 >) > ) -> Result< Vec< (String, Vec< u8 >) >, String > {
+    let bytes = pw::serialize( module.clone() ).map_err( |error| format!( "cannot serialize the module: {:?}", error ) )?;
+    let new_layouts = scan_code_layout( &bytes ).map_err( |error| format!( "cannot scan the code section: {:?}", error ) )?;
+
+    let mut mapped_functions = Vec::with_capacity( functions.len() );
+    for function in functions {
+        let new_layout = match new_layouts.get( function.new_body_index ) {
+            Some( layout ) => layout,
+            None => return Err( "function body is missing from the new code section".to_owned() )
+        };
+
+        let old_layout = function.old_layout;
+        let mut points = Vec::with_capacity( old_layout.instructions.len() + 2 );
+        points.push( (old_layout.start, new_layout.start) );
+        points.push( (old_layout.locals_start, new_layout.locals_start) );
+        for (new_index, old_index) in function.alignment.into_iter().enumerate() {
+            let old_index = match old_index {
+                Some( old_index ) => old_index,
+                None => continue
+            };
+
+            if let (Some( &old ), Some( &new )) = (old_layout.instructions.get( old_index ), new_layout.instructions.get( new_index )) {
+                points.push( (old, new) );
+            }
+        }
+
+        mapped_functions.push( MappedFunction {
+            old_start: old_layout.start,
+            old_end: old_layout.end,
+            new_end: new_layout.end,
+            points
+        });
+    }
+
+    let map = AddressMap::new( mapped_functions );
+    remap_sections( sections, &map )
+}
+
+fn section_mut< 'a >( sections: &'a mut Vec< (String, Vec< u8 >) >, name: &str ) -> Option< &'a mut Vec< u8 > > {
+    sections.iter_mut().find( |section| section.0 == name ).map( |section| &mut section.1 )
+}
+
+// Whether the code didn't move at all, in which case every section can be kept as it is.
+fn is_identity( map: &AddressMap ) -> bool {
+    map.functions.iter().all( |function| {
+        function.old_end == function.new_end && function.points.iter().all( |&(old, new)| old == new )
+    })
+}
+
+fn remap_sections( mut sections: Vec< (String, Vec< u8 >) >, map: &AddressMap ) -> Result< Vec< (String, Vec< u8 >) >, String > {
+    if is_identity( map ) {
+        return Ok( sections );
+    }
+
+    let abbreviations = section_mut( &mut sections, ".debug_abbrev" ).map( |payload| payload.clone() ).unwrap_or_default();
+
+    let mut line_offsets = HashMap::new();
+    if let Some( payload ) = section_mut( &mut sections, ".debug_line" ) {
+        let (new_payload, offsets) = remap_debug_line( payload, map )?;
+        *payload = new_payload;
+        line_offsets = offsets;
+    }
+
+    let mut range_lists = Vec::new();
+    let mut location_lists = Vec::new();
+    if let Some( payload ) = section_mut( &mut sections, ".debug_info" ) {
+        remap_debug_info( payload, &abbreviations, map, &line_offsets, &mut range_lists, &mut location_lists )?;
+    }
+
+    if let Some( payload ) = section_mut( &mut sections, ".debug_ranges" ) {
+        remap_lists( payload, &range_lists, map, false )?;
+    }
+
+    if let Some( payload ) = section_mut( &mut sections, ".debug_loc" ) {
+        remap_lists( payload, &location_lists, map, true )?;
+    }
+
+    if let Some( payload ) = section_mut( &mut sections, ".debug_aranges" ) {
+        remap_debug_aranges( payload, map )?;
+    }
+
+    Ok( sections )
+}
+
+struct Reader< 'a > {
+    data: &'a [u8],
+    position: usize
+}
+
+impl< 'a > Reader< 'a > {
+    fn new( data: &'a [u8], position: usize ) -> Self {
+        Reader {
+            data,
+            position
+        }
+    }
+
+    fn bytes( &mut self, length: usize ) -> Result< &'a [u8], String > {
+        if self.position + length > self.data.len() {
+            return Err( "unexpected end of a DWARF section".to_owned() );
+        }
+
+        let slice = &self.data[ self.position..self.position + length ];
+        self.position += length;
+        Ok( slice )
+    }
+
+    fn u8( &mut self ) -> Result< u8, String > {
+        Ok( self.bytes( 1 )?[ 0 ] )
+    }
+
+    fn u16( &mut self ) -> Result< u16, String > {
+        let bytes = self.bytes( 2 )?;
+        Ok( bytes[ 0 ] as u16 | (bytes[ 1 ] as u16) << 8 )
+    }
+
+    fn u32( &mut self ) -> Result< u32, String > {
+        let bytes = self.bytes( 4 )?;
+        Ok( bytes[ 0 ] as u32 | (bytes[ 1 ] as u32) << 8 | (bytes[ 2 ] as u32) << 16 | (bytes[ 3 ] as u32) << 24 )
+    }
+
+    fn u64( &mut self ) -> Result< u64, String > {
+        let low = self.u32()? as u64;
+        let high = self.u32()? as u64;
+        Ok( low | high << 32 )
+    }
+
+    fn uleb( &mut self ) -> Result< u64, String > {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok( result );
+            }
+        }
+    }
+
+    fn sleb( &mut self ) -> Result< i64, String > {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1 << shift;
+                }
+                return Ok( result );
+            }
+        }
+    }
+
+    fn skip_string( &mut self ) -> Result< (), String > {
+        while self.u8()? != 0 {}
+        Ok(())
+    }
+}
+
+fn put_u32( data: &mut [u8], position: usize, value: u32 ) {
+    data[ position ] = value as u8;
+    data[ position + 1 ] = (value >> 8) as u8;
+    data[ position + 2 ] = (value >> 16) as u8;
+    data[ position + 3 ] = (value >> 24) as u8;
+}
+
+fn put_sized( data: &mut [u8], position: usize, size: usize, value: u64 ) -> Result< (), String > {
+    if size < 8 && value >> (size * 8) != 0 {
+        return Err( format!( "value {} doesn't fit in {} bytes", value, size ) );
+    }
+
+    for index in 0..size {
+        data[ position + index ] = (value >> (index * 8)) as u8;
+    }
+
+    Ok(())
+}
+
+fn write_uleb( output: &mut Vec< u8 >, mut value: u64 ) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push( byte );
+            return;
+        }
+        output.push( byte | 0x80 );
+    }
+}
+
+fn write_sleb( output: &mut Vec< u8 >, mut value: i64 ) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            output.push( byte );
+            return;
+        }
+        output.push( byte | 0x80 );
+    }
+}
+
+fn write_u32( output: &mut Vec< u8 >, value: u32 ) {
+    output.extend_from_slice( &[ value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8 ] );
+}
+
+fn read_unit_length( reader: &mut Reader ) -> Result< usize, String > {
+    let unit_length = reader.u32()?;
+    if unit_length >= 0xfffffff0 {
+        return Err( "64-bit DWARF is not supported".to_owned() );
+    }
+
+    let unit_end = reader.position + unit_length as usize;
+    if unit_end > reader.data.len() {
+        return Err( "unexpected end of a DWARF section".to_owned() );
+    }
+
+    Ok( unit_end )
+}
+
+fn check_version( version: u16 ) -> Result< (), String > {
+    if version < 2 || version > 4 {
+        return Err( format!( "DWARF version {} is not supported", version ) );
+    }
+
+    Ok(())
+}
+
+#[derive(Clone)]
+struct LineRow {
+    address: u32,
+    file: u64,
+    line: u64,
+    column: u64,
+    is_stmt: bool,
+    basic_block: bool,
+    end_sequence: bool,
+    prologue_end: bool,
+    epilogue_begin: bool,
+    isa: u64,
+    discriminator: u64
+}
+
+impl LineRow {
+    fn new( default_is_stmt: bool ) -> Self {
+        LineRow {
+            address: 0,
+            file: 1,
+            line: 1,
+            column: 0,
+            is_stmt: default_is_stmt,
+            basic_block: false,
+            end_sequence: false,
+            prologue_end: false,
+            epilogue_begin: false,
+            isa: 0,
+            discriminator: 0
+        }
+    }
+}
+
+struct LineProgramHeader {
+    minimum_instruction_length: u32,
+    default_is_stmt: bool,
+    line_base: i64,
+    line_range: u64,
+    opcode_base: u8,
+    standard_opcode_lengths: Vec< u8 >,
+    include_directories: Vec< String >,
+    // Pairs of file names and directory indexes.
+    file_names: Vec< (String, u64) >
+}
+
+impl LineProgramHeader {
+    fn path( &self, file: u64 ) -> Option< String > {
+        let &(ref name, directory) = self.file_names.get( (file as usize).wrapping_sub( 1 ) )?;
+        if name.starts_with( "/" ) || directory == 0 {
+            return Some( name.clone() );
+        }
+
+        let directory = self.include_directories.get( directory as usize - 1 )?;
+        Some( format!( "{}/{}", directory.trim_end_matches( "/" ), name ) )
+    }
+}
+
+fn read_string( reader: &mut Reader ) -> Result< String, String > {
+    let start = reader.position;
+    reader.skip_string()?;
+    Ok( String::from_utf8_lossy( &reader.data[ start..reader.position - 1 ] ).into_owned() )
+}
+
+// Returns the header, the offset of the line program and the offset of the end of the unit.
+fn read_line_program_header( data: &[u8], position: usize ) -> Result< (LineProgramHeader, usize, usize), String > {
+    let mut reader = Reader::new( data, position );
+    let unit_end = read_unit_length( &mut reader )?;
+    let version = reader.u16()?;
+    check_version( version )?;
+
+    let header_length = reader.u32()? as usize;
+    let program_start = reader.position + header_length;
+    if program_start > unit_end {
+        return Err( "invalid line program header length".to_owned() );
+    }
+
+    let minimum_instruction_length = reader.u8()? as u32;
+    if version >= 4 {
+        let _maximum_operations_per_instruction = reader.u8()?;
+    }
+
+    let default_is_stmt = reader.u8()? != 0;
+    let line_base = reader.u8()? as i8 as i64;
+    let line_range = reader.u8()? as u64;
+    let opcode_base = reader.u8()?;
+    let standard_opcode_lengths = reader.bytes( opcode_base.saturating_sub( 1 ) as usize )?.to_vec();
+    if minimum_instruction_length == 0 || line_range == 0 || opcode_base == 0 {
+        return Err( "invalid line program header".to_owned() );
+    }
+
+    let mut include_directories = Vec::new();
+    loop {
+        let directory = read_string( &mut reader )?;
+        if directory.is_empty() {
+            break;
+        }
+        include_directories.push( directory );
+    }
+
+    let mut file_names = Vec::new();
+    loop {
+        let name = read_string( &mut reader )?;
+        if name.is_empty() {
+            break;
+        }
+        let directory = reader.uleb()?;
+        let _modification_time = reader.uleb()?;
+        let _length = reader.uleb()?;
+        file_names.push( (name, directory) );
+    }
+
+    let header = LineProgramHeader {
+        minimum_instruction_length,
+        default_is_stmt,
+        line_base,
+        line_range,
+        opcode_base,
+        standard_opcode_lengths,
+        include_directories,
+        file_names
+    };
+
+    Ok( (header, program_start, unit_end) )
+}
+
+/// A row of the line number table.
+pub struct LineEntry {
+    pub address: u32,
+    pub path: String,
+    pub line: u64,
+    pub column: u64
+}
+
+/// Decodes every line program in the `.debug_line` section.
+pub fn read_line_entries( data: &[u8] ) -> Result< Vec< LineEntry >, String > {
+    let mut entries = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let (header, program_start, unit_end) = read_line_program_header( data, position )?;
+        for sequence in decode_line_program( &data[ program_start..unit_end ], &header )? {
+            for row in sequence {
+                if row.end_sequence || row.address == TOMBSTONE {
+                    continue;
+                }
+
+                if let Some( path ) = header.path( row.file ) {
+                    entries.push( LineEntry {
+                        address: row.address,
+                        path,
+                        line: row.line,
+                        column: row.column
+                    });
+                }
+            }
+        }
+
+        position = unit_end;
+    }
+
+    Ok( entries )
+}
+
+fn remap_debug_line( data: &[u8], map: &AddressMap ) -> Result< (Vec< u8 >, HashMap< u32, u32 >), String > {
+    let mut output = Vec::with_capacity( data.len() );
+    let mut offsets = HashMap::new();
+    let mut position = 0;
+    while position < data.len() {
+        let (header, program_start, unit_end) = read_line_program_header( data, position )?;
+        let sequences = decode_line_program( &data[ program_start..unit_end ], &header )?;
+
+        let new_position = output.len();
+        offsets.insert( position as u32, new_position as u32 );
+        output.extend_from_slice( &data[ position..program_start ] );
+        for sequence in sequences {
+            if let Some( sequence ) = remap_line_sequence( sequence, map ) {
+                encode_line_sequence( &mut output, &sequence, &header );
+            }
+        }
+
+        let new_unit_length = (output.len() - new_position - 4) as u32;
+        put_u32( &mut output, new_position, new_unit_length );
+        position = unit_end;
+    }
+
+    Ok( (output, offsets) )
+}
+
+fn emit_row( sequence: &mut Vec< LineRow >, row: &mut LineRow ) {
+    sequence.push( row.clone() );
+    row.basic_block = false;
+    row.prologue_end = false;
+    row.epilogue_begin = false;
+    row.discriminator = 0;
+}
+
+fn decode_line_program( program: &[u8], header: &LineProgramHeader ) -> Result< Vec< Vec< LineRow > >, String > {
+    let mut reader = Reader::new( program, 0 );
+    let mut sequences = Vec::new();
+    let mut sequence = Vec::new();
+    let mut row = LineRow::new( header.default_is_stmt );
+    while reader.position < program.len() {
+        let opcode = reader.u8()?;
+        if opcode >= header.opcode_base {
+            let adjusted = (opcode - header.opcode_base) as u64;
+            let address_advance = (adjusted / header.line_range) as u32 * header.minimum_instruction_length;
+            row.address = row.address.wrapping_add( address_advance );
+            row.line = (row.line as i64 + header.line_base + (adjusted % header.line_range) as i64) as u64;
+            emit_row( &mut sequence, &mut row );
+            continue;
+        }
+
+        match opcode {
+            0 => {
+                let length = reader.uleb()? as usize;
+                let end = reader.position + length;
+                if length == 0 {
+                    continue;
+                }
+
+                match reader.u8()? {
+                    DW_LNE_END_SEQUENCE => {
+                        row.end_sequence = true;
+                        sequence.push( row.clone() );
+                        sequences.push( mem::replace( &mut sequence, Vec::new() ) );
+                        row = LineRow::new( header.default_is_stmt );
+                    },
+                    DW_LNE_SET_ADDRESS => {
+                        if length != 5 {
+                            return Err( "only 32-bit addresses are supported in line programs".to_owned() );
+                        }
+                        row.address = reader.u32()?;
+                    },
+                    DW_LNE_DEFINE_FILE => {
+                        return Err( "`DW_LNE_define_file` is not supported".to_owned() );
+                    },
+                    DW_LNE_SET_DISCRIMINATOR => {
+                        row.discriminator = reader.uleb()?;
+                    },
+                    _ => {}
+                }
+
+                if end > program.len() {
+                    return Err( "unexpected end of a line program".to_owned() );
+                }
+                reader.position = end;
+            },
+            DW_LNS_COPY => emit_row( &mut sequence, &mut row ),
+            DW_LNS_ADVANCE_PC => {
+                let advance = reader.uleb()? as u32;
+                row.address = row.address.wrapping_add( advance.wrapping_mul( header.minimum_instruction_length ) );
+            },
+            DW_LNS_ADVANCE_LINE => row.line = (row.line as i64).wrapping_add( reader.sleb()? ) as u64,
+            DW_LNS_SET_FILE => row.file = reader.uleb()?,
+            DW_LNS_SET_COLUMN => row.column = reader.uleb()?,
+            DW_LNS_NEGATE_STMT => row.is_stmt = !row.is_stmt,
+            DW_LNS_SET_BASIC_BLOCK => row.basic_block = true,
+            DW_LNS_CONST_ADD_PC => {
+                let adjusted = (255 - header.opcode_base) as u64;
+                let address_advance = (adjusted / header.line_range) as u32 * header.minimum_instruction_length;
+                row.address = row.address.wrapping_add( address_advance );
+            },
+            DW_LNS_FIXED_ADVANCE_PC => row.address = row.address.wrapping_add( reader.u16()? as u32 ),
+            DW_LNS_SET_PROLOGUE_END => row.prologue_end = true,
+            DW_LNS_SET_EPILOGUE_BEGIN => row.epilogue_begin = true,
+            DW_LNS_SET_ISA => row.isa = reader.uleb()?,
+            _ => {
+                for _ in 0..header.standard_opcode_lengths[ opcode as usize - 1 ] {
+                    reader.uleb()?;
+                }
+            }
+        }
+    }
+
+    Ok( sequences )
+}
+
+fn remap_line_sequence( sequence: Vec< LineRow >, map: &AddressMap ) -> Option< Vec< LineRow > > {
+    let mut output: Vec< LineRow > = Vec::with_capacity( sequence.len() );
+    for mut row in sequence {
+        let last_address = output.last().map( |last| last.address );
+        if row.end_sequence {
+            let last_address = match last_address {
+                Some( last_address ) => last_address,
+                None => return None
+            };
+
+            row.address = cmp::max( map.map_end( row.address ).unwrap_or( last_address ), last_address );
+            output.push( row );
+            return Some( output );
+        }
+
+        let address = match map.map_start( row.address ) {
+            Some( address ) => address,
+            None => continue
+        };
+
+        if last_address.map( |last_address| address < last_address ).unwrap_or( false ) {
+            continue;
+        }
+
+        row.address = address;
+        output.push( row );
+    }
+
+    None
+}
+
+fn write_set_address( output: &mut Vec< u8 >, address: u32 ) {
+    output.extend_from_slice( &[ 0, 5, DW_LNE_SET_ADDRESS ] );
+    write_u32( output, address );
+}
+
+fn encode_line_sequence( output: &mut Vec< u8 >, sequence: &[LineRow], header: &LineProgramHeader ) {
+    let mut state = LineRow::new( header.default_is_stmt );
+    state.address = sequence[ 0 ].address;
+    write_set_address( output, state.address );
+
+    for row in sequence {
+        if row.address != state.address {
+            let delta = row.address - state.address;
+            if delta % header.minimum_instruction_length == 0 {
+                output.push( DW_LNS_ADVANCE_PC );
+                write_uleb( output, (delta / header.minimum_instruction_length) as u64 );
+            } else {
+                write_set_address( output, row.address );
+            }
+        }
+
+        if row.end_sequence {
+            output.extend_from_slice( &[ 0, 1, DW_LNE_END_SEQUENCE ] );
+            return;
+        }
+
+        if row.file != state.file {
+            output.push( DW_LNS_SET_FILE );
+            write_uleb( output, row.file );
+        }
+
+        if row.line != state.line {
+            output.push( DW_LNS_ADVANCE_LINE );
+            write_sleb( output, (row.line as i64).wrapping_sub( state.line as i64 ) );
+        }
+
+        if row.column != state.column {
+            output.push( DW_LNS_SET_COLUMN );
+            write_uleb( output, row.column );
+        }
+
+        if row.is_stmt != state.is_stmt {
+            output.push( DW_LNS_NEGATE_STMT );
+        }
+
+        if row.basic_block {
+            output.push( DW_LNS_SET_BASIC_BLOCK );
+        }
+
+        if row.prologue_end && header.opcode_base > DW_LNS_SET_PROLOGUE_END {
+            output.push( DW_LNS_SET_PROLOGUE_END );
+        }
+
+        if row.epilogue_begin && header.opcode_base > DW_LNS_SET_EPILOGUE_BEGIN {
+            output.push( DW_LNS_SET_EPILOGUE_BEGIN );
+        }
+
+        if row.isa != state.isa && header.opcode_base > DW_LNS_SET_ISA {
+            output.push( DW_LNS_SET_ISA );
+            write_uleb( output, row.isa );
+        }
+
+        if row.discriminator != 0 {
+            let mut operand = Vec::new();
+            write_uleb( &mut operand, row.discriminator );
+            output.push( 0 );
+            write_uleb( output, 1 + operand.len() as u64 );
+            output.push( DW_LNE_SET_DISCRIMINATOR );
+            output.extend_from_slice( &operand );
+        }
+
+        output.push( DW_LNS_COPY );
+        state = row.clone();
+    }
+}
+
+fn parse_abbreviations( data: &[u8], offset: usize ) -> Result< HashMap< u64, Vec< (u64, u64) > >, String > {
+    let mut reader = Reader::new( data, offset );
+    let mut abbreviations = HashMap::new();
+    loop {
+        let code = reader.uleb()?;
+        if code == 0 {
+            return Ok( abbreviations );
+        }
+
+        let _tag = reader.uleb()?;
+        let _has_children = reader.u8()?;
+        let mut attributes = Vec::new();
+        loop {
+            let name = reader.uleb()?;
+            let form = reader.uleb()?;
+            if name == 0 && form == 0 {
+                break;
+            }
+
+            attributes.push( (name, form) );
+        }
+
+        abbreviations.insert( code, attributes );
+    }
+}
+
+fn skip_form( reader: &mut Reader, form: u64, version: u16 ) -> Result< (), String > {
+    let length = match form {
+        DW_FORM_FLAG_PRESENT => 0,
+        DW_FORM_DATA1 | DW_FORM_REF1 | DW_FORM_FLAG => 1,
+        DW_FORM_DATA2 | DW_FORM_REF2 => 2,
+        DW_FORM_ADDR | DW_FORM_DATA4 | DW_FORM_REF4 | DW_FORM_STRP | DW_FORM_SEC_OFFSET | DW_FORM_REF_ADDR => 4,
+        DW_FORM_DATA8 | DW_FORM_REF8 | DW_FORM_REF_SIG8 => 8,
+        DW_FORM_SDATA => { reader.sleb()?; 0 },
+        DW_FORM_UDATA | DW_FORM_REF_UDATA => { reader.uleb()?; 0 },
+        DW_FORM_STRING => { reader.skip_string()?; 0 },
+        DW_FORM_BLOCK1 => reader.u8()? as usize,
+        DW_FORM_BLOCK2 => reader.u16()? as usize,
+        DW_FORM_BLOCK4 => reader.u32()? as usize,
+        DW_FORM_BLOCK | DW_FORM_EXPRLOC => reader.uleb()? as usize,
+        _ => return Err( format!( "unsupported DWARF form 0x{:x} (version {})", form, version ) )
+    };
+
+    reader.bytes( length )?;
+    Ok(())
+}
+
+fn is_location_list_attribute( name: u64, version: u16 ) -> bool {
+    match name {
+        DW_AT_LOCATION | DW_AT_FRAME_BASE => true,
+        DW_AT_STRING_LENGTH |
+        DW_AT_RETURN_ADDR |
+        DW_AT_SEGMENT |
+        DW_AT_DATA_MEMBER_LOCATION |
+        DW_AT_STATIC_LINK |
+        DW_AT_USE_LOCATION |
+        DW_AT_VTABLE_ELEM_LOCATION => version >= 4,
+        _ => false
+    }
+}
+
+struct ListReference {
+    offset: u32,
+    base: u32
+}
+
+enum HighPc {
+    Address( usize, u32 ),
+    Length( usize, usize, u64 )
+}
+
+fn remap_debug_info(
+    data: &mut [u8],
+    abbreviations: &[u8],
+    map: &AddressMap,
+    line_offsets: &HashMap< u32, u32 >,
+    range_lists: &mut Vec< ListReference >,
+    location_lists: &mut Vec< ListReference >
+) -> Result< (), String > {
+    let original = data.to_vec();
+    let mut abbreviation_tables = HashMap::new();
+    let mut position = 0;
+    while position < original.len() {
+        let mut reader = Reader::new( &original, position );
+        let unit_end = read_unit_length( &mut reader )?;
+        let version = reader.u16()?;
+        check_version( version )?;
+
+        let abbreviations_offset = reader.u32()? as usize;
+        let address_size = reader.u8()?;
+        if address_size != 4 {
+            return Err( format!( "unsupported address size: {}", address_size ) );
+        }
+
+        if !abbreviation_tables.contains_key( &abbreviations_offset ) {
+            let table = parse_abbreviations( abbreviations, abbreviations_offset )?;
+            abbreviation_tables.insert( abbreviations_offset, table );
+        }
+        let table = &abbreviation_tables[ &abbreviations_offset ];
+
+        let mut unit_base = None;
+        while reader.position < unit_end {
+            let code = reader.uleb()?;
+            if code == 0 {
+                continue;
+            }
+
+            let attributes = table.get( &code ).ok_or_else( || format!( "invalid abbreviation code: {}", code ) )?;
+            let mut low_pc = None;
+            let mut high_pc = None;
+            let mut ranges = None;
+            let mut locations = Vec::new();
+            for &(name, form) in attributes {
+                let mut form = form;
+                while form == DW_FORM_INDIRECT {
+                    form = reader.uleb()?;
+                }
+
+                let value_position = reader.position;
+                match (name, form) {
+                    (DW_AT_LOW_PC, DW_FORM_ADDR) => {
+                        let address = reader.u32()?;
+                        low_pc = Some( address );
+                        put_u32( data, value_position, map.map_start( address ).unwrap_or( TOMBSTONE ) );
+                    },
+                    (DW_AT_ENTRY_PC, DW_FORM_ADDR) => {
+                        let address = reader.u32()?;
+                        put_u32( data, value_position, map.map_start( address ).unwrap_or( TOMBSTONE ) );
+                    },
+                    (DW_AT_HIGH_PC, DW_FORM_ADDR) => {
+                        high_pc = Some( HighPc::Address( value_position, reader.u32()? ) );
+                    },
+                    (DW_AT_HIGH_PC, DW_FORM_DATA1) => high_pc = Some( HighPc::Length( value_position, 1, reader.u8()? as u64 ) ),
+                    (DW_AT_HIGH_PC, DW_FORM_DATA2) => high_pc = Some( HighPc::Length( value_position, 2, reader.u16()? as u64 ) ),
+                    (DW_AT_HIGH_PC, DW_FORM_DATA4) => high_pc = Some( HighPc::Length( value_position, 4, reader.u32()? as u64 ) ),
+                    (DW_AT_HIGH_PC, DW_FORM_DATA8) => high_pc = Some( HighPc::Length( value_position, 8, reader.u64()? ) ),
+                    (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET) |
+                    (DW_AT_STMT_LIST, DW_FORM_DATA4) => {
+                        let offset = reader.u32()?;
+                        if let Some( &new_offset ) = line_offsets.get( &offset ) {
+                            put_u32( data, value_position, new_offset );
+                        }
+                    },
+                    (DW_AT_RANGES, DW_FORM_SEC_OFFSET) |
+                    (DW_AT_RANGES, DW_FORM_DATA4) => {
+                        ranges = Some( reader.u32()? );
+                    },
+                    (name, DW_FORM_SEC_OFFSET) |
+                    (name, DW_FORM_DATA4) if is_location_list_attribute( name, version ) && (form == DW_FORM_SEC_OFFSET || version < 4) => {
+                        locations.push( reader.u32()? );
+                    },
+                    _ => skip_form( &mut reader, form, version )?
+                }
+            }
+
+            if unit_base.is_none() {
+                unit_base = Some( low_pc.unwrap_or( 0 ) );
+            }
+
+            match high_pc {
+                Some( HighPc::Address( value_position, address ) ) => {
+                    put_u32( data, value_position, map.map_end( address ).unwrap_or( TOMBSTONE ) );
+                },
+                Some( HighPc::Length( value_position, size, length ) ) => {
+                    let low_pc = low_pc.unwrap_or( 0 );
+                    let new_start = map.map_start( low_pc );
+                    let new_end = map.map_end( low_pc.wrapping_add( length as u32 ) );
+                    let new_length = match (new_start, new_end) {
+                        (Some( new_start ), Some( new_end )) if new_end >= new_start => new_end - new_start,
+                        _ => 0
+                    };
+                    put_sized( data, value_position, size, new_length as u64 )?;
+                },
+                None => {}
+            }
+
+            let base = unit_base.unwrap_or( 0 );
+            if let Some( offset ) = ranges {
+                range_lists.push( ListReference { offset, base } );
+            }
+
+            for offset in locations {
+                location_lists.push( ListReference { offset, base } );
+            }
+        }
+
+        position = unit_end;
+    }
+
+    Ok(())
+}
+
+fn remap_lists( data: &mut [u8], references: &[ListReference], map: &AddressMap, has_expressions: bool ) -> Result< (), String > {
+    let original = data.to_vec();
+    for reference in references {
+        let mut reader = Reader::new( &original, reference.offset as usize );
+        let mut old_base = reference.base;
+        let mut new_base = map.map_start( old_base );
+        loop {
+            let position = reader.position;
+            let begin = reader.u32()?;
+            let end = reader.u32()?;
+            if begin == 0 && end == 0 {
+                break;
+            }
+
+            if begin == 0xffffffff {
+                old_base = end;
+                new_base = map.map_start( end );
+                put_u32( data, position + 4, new_base.unwrap_or( TOMBSTONE ) );
+                continue;
+            }
+
+            let new_range = new_base.and_then( |new_base| {
+                let new_begin = map.map_start( old_base.wrapping_add( begin ) );
+                let new_end = map.map_end( old_base.wrapping_add( end ) );
+                match (new_begin, new_end) {
+                    (Some( new_begin ), Some( new_end )) if new_end >= new_begin => {
+                        Some( (new_begin.wrapping_sub( new_base ), new_end.wrapping_sub( new_base )) )
+                    },
+                    _ => None
+                }
+            });
+
+            let (new_begin, new_end) = match new_range {
+                Some( (0, 0) ) | None => (LIST_TOMBSTONE, LIST_TOMBSTONE),
+                Some( range ) => range
+            };
+
+            put_u32( data, position, new_begin );
+            put_u32( data, position + 4, new_end );
+
+            if has_expressions {
+                let length = reader.u16()? as usize;
+                reader.bytes( length )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn remap_debug_aranges( data: &mut [u8], map: &AddressMap ) -> Result< (), String > {
+    let original = data.to_vec();
+    let mut position = 0;
+    while position < original.len() {
+        let mut reader = Reader::new( &original, position );
+        let unit_end = read_unit_length( &mut reader )?;
+        let _version = reader.u16()?;
+        let _debug_info_offset = reader.u32()?;
+        let address_size = reader.u8()?;
+        let segment_size = reader.u8()?;
+        if address_size != 4 || segment_size != 0 {
+            return Err( "unsupported `.debug_aranges` layout".to_owned() );
+        }
+
+        // The tuples are aligned to twice the address size.
+        let padding = (8 - (reader.position - position) % 8) % 8;
+        reader.bytes( padding )?;
+
+        while reader.position + 8 <= unit_end {
+            let tuple_position = reader.position;
+            let address = reader.u32()?;
+            let length = reader.u32()?;
+            if address == 0 && length == 0 {
+                break;
+            }
+
+            let new_start = map.map_start( address );
+            let new_end = map.map_end( address.wrapping_add( length ) );
+            let (new_address, new_length) = match (new_start, new_end) {
+                (Some( new_start ), Some( new_end )) if new_end >= new_start => (new_start, new_end - new_start),
+                _ => (TOMBSTONE, 0)
+            };
+
+            put_u32( data, tuple_position, new_address );
+            put_u32( data, tuple_position + 4, new_length );
+        }
+
+        position = unit_end;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_align() {
+    let old = [ 1, 2, 3, 4, 5, 6 ];
+    let new = [ 1, 2, 10, 3, 4, 11, 6 ];
+    assert_eq!( align( &old, &new ), vec![ Some( 0 ), Some( 1 ), None, Some( 2 ), Some( 3 ), None, Some( 5 ) ] );
+
+    let old: [u32; 0] = [];
+    assert_eq!( align( &old, &[ 1 ] ), vec![ None ] );
+    assert_eq!( align( &[ 1, 2 ], &old ), vec![] );
+}
+
+#[test]
+fn test_address_map() {
+    let old = FunctionLayout { start: 1, locals_start: 2, instructions: vec![ 3, 5, 6 ], end: 7 };
+    let new = FunctionLayout { start: 1, locals_start: 2, instructions: vec![ 3, 5, 7, 8 ], end: 9 };
+    let map = AddressMap::new( vec![ MappedFunction {
+        old_start: old.start,
+        old_end: old.end,
+        new_end: new.end,
+        points: vec![ (1, 1), (2, 2), (3, 3), (5, 5), (6, 8) ]
+    }]);
+
+    assert_eq!( map.map_start( 5 ), Some( 5 ) );
+    assert_eq!( map.map_start( 6 ), Some( 8 ) );
+    assert_eq!( map.map_start( 7 ), None );
+    assert_eq!( map.map_end( 7 ), Some( 9 ) );
+    assert_eq!( map.map_end( 6 ), Some( 8 ) );
+    assert_eq!( map.map_start( 0 ), Some( 0 ) );
+}
+
+#[test]
+fn test_line_program_roundtrip() {
+    let header = LineProgramHeader {
+        minimum_instruction_length: 1,
+        default_is_stmt: true,
+        line_base: -5,
+        line_range: 14,
+        opcode_base: 13,
+        standard_opcode_lengths: vec![ 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1 ],
+        include_directories: Vec::new(),
+        file_names: Vec::new()
+    };
+
+    let mut first = LineRow::new( true );
+    first.address = 10;
+    first.line = 7;
+    first.column = 3;
+    first.prologue_end = true;
+    let mut second = first.clone();
+    second.address = 14;
+    second.line = 5;
+    second.prologue_end = false;
+    let mut last = second.clone();
+    last.address = 20;
+    last.end_sequence = true;
+
+    let mut program = Vec::new();
+    encode_line_sequence( &mut program, &[ first.clone(), second.clone(), last.clone() ], &header );
+    let sequences = decode_line_program( &program, &header ).unwrap();
+    assert_eq!( sequences.len(), 1 );
+
+    let rows: Vec< _ > = sequences[ 0 ].iter().map( |row| (row.address, row.line, row.column, row.prologue_end, row.end_sequence) ).collect();
+    assert_eq!( rows, vec![
+        (10, 7, 3, true, false),
+        (14, 5, 3, false, false),
+        (20, 5, 3, false, true)
+    ]);
+}
+
+#[test]
+fn test_remap_sections() {
+    let header = LineProgramHeader {
+        minimum_instruction_length: 1,
+        default_is_stmt: true,
+        line_base: -5,
+        line_range: 14,
+        opcode_base: 13,
+        standard_opcode_lengths: vec![ 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1 ],
+        include_directories: Vec::new(),
+        file_names: vec![ ("a.rs".to_owned(), 0) ]
+    };
+
+    let mut first = LineRow::new( true );
+    first.address = 3;
+    first.line = 7;
+    let mut second = first.clone();
+    second.address = 6;
+    second.line = 8;
+    let mut last = second.clone();
+    last.address = 7;
+    last.end_sequence = true;
+
+    let mut program = Vec::new();
+    encode_line_sequence( &mut program, &[ first, second, last ], &header );
+
+    let mut header_bytes = vec![ 1, 1, 1, 0xfb, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1, 0 ];
+    header_bytes.extend_from_slice( b"a.rs\0\0\0\0\0" );
+
+    let mut debug_line = vec![ 0, 0, 0, 0, 4, 0 ];
+    write_u32( &mut debug_line, header_bytes.len() as u32 );
+    debug_line.extend_from_slice( &header_bytes );
+    debug_line.extend_from_slice( &program );
+    let unit_length = debug_line.len() as u32 - 4;
+    put_u32( &mut debug_line, 0, unit_length );
+
+    let sections = vec![
+        (".debug_str".to_owned(), b"main\0".to_vec()),
+        (".debug_line".to_owned(), debug_line.clone())
+    ];
+
+    let identity = AddressMap::new( vec![ MappedFunction {
+        old_start: 1,
+        old_end: 7,
+        new_end: 7,
+        points: vec![ (1, 1), (2, 2), (3, 3), (5, 5), (6, 6) ]
+    }]);
+
+    let output = remap_sections( sections.clone(), &identity ).unwrap();
+    assert_eq!( output, sections );
+
+    let moved = AddressMap::new( vec![ MappedFunction {
+        old_start: 1,
+        old_end: 7,
+        new_end: 9,
+        points: vec![ (1, 1), (2, 2), (3, 3), (5, 5), (6, 8) ]
+    }]);
+
+    let output = remap_sections( sections, &moved ).unwrap();
+    assert_eq!( output.len(), 2 );
+    assert_eq!( output[ 0 ], (".debug_str".to_owned(), b"main\0".to_vec()) );
+    assert_eq!( output[ 1 ].0, ".debug_line" );
+
+    let entries = read_line_entries( &output[ 1 ].1 ).unwrap();
+    let entries: Vec< _ > = entries.into_iter().map( |entry| (entry.address, entry.path, entry.line) ).collect();
+    assert_eq!( entries, vec![ (3, "a.rs".to_owned(), 7), (8, "a.rs".to_owned(), 8) ] );
+}
+
+#[test]
+fn test_remap_debug_info() {
+    let debug_abbrev = vec![
+        // A compile unit with children.
+        1, 0x11, 1,
+        DW_AT_STMT_LIST as u8, DW_FORM_SEC_OFFSET as u8,
+        DW_AT_LOW_PC as u8, DW_FORM_ADDR as u8,
+        DW_AT_RANGES as u8, DW_FORM_SEC_OFFSET as u8,
+        0, 0,
+        // A subprogram.
+        2, 0x2e, 0,
+        DW_AT_LOW_PC as u8, DW_FORM_ADDR as u8,
+        DW_AT_HIGH_PC as u8, DW_FORM_DATA4 as u8,
+        DW_AT_FRAME_BASE as u8, DW_FORM_EXPRLOC as u8,
+        0, 0,
+        // A variable.
+        3, 0x34, 0,
+        DW_AT_LOCATION as u8, DW_FORM_SEC_OFFSET as u8,
+        0, 0,
+        0
+    ];
+
+    let mut debug_info = vec![ 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 4 ];
+    debug_info.push( 1 );
+    write_u32( &mut debug_info, 0 );
+    write_u32( &mut debug_info, 0 );
+    write_u32( &mut debug_info, 0 );
+    debug_info.push( 2 );
+    write_u32( &mut debug_info, 3 );
+    write_u32( &mut debug_info, 4 );
+    debug_info.extend_from_slice( &[ 1, 0x9f ] );
+    debug_info.push( 3 );
+    write_u32( &mut debug_info, 0 );
+    debug_info.push( 0 );
+    let unit_length = debug_info.len() as u32 - 4;
+    put_u32( &mut debug_info, 0, unit_length );
+
+    let mut debug_ranges = Vec::new();
+    for &value in &[ 3, 7, 20, 30, 0, 0 ] {
+        write_u32( &mut debug_ranges, value );
+    }
+
+    let mut debug_loc = Vec::new();
+    write_u32( &mut debug_loc, 3 );
+    write_u32( &mut debug_loc, 6 );
+    debug_loc.extend_from_slice( &[ 1, 0, 0x50 ] );
+    write_u32( &mut debug_loc, 0 );
+    write_u32( &mut debug_loc, 0 );
+
+    let mut debug_aranges = vec![ 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0 ];
+    for &value in &[ 3, 4, 20, 10, 0, 0 ] {
+        write_u32( &mut debug_aranges, value );
+    }
+    let unit_length = debug_aranges.len() as u32 - 4;
+    put_u32( &mut debug_aranges, 0, unit_length );
+
+    let sections = vec![
+        (".debug_abbrev".to_owned(), debug_abbrev.clone()),
+        (".debug_info".to_owned(), debug_info.clone()),
+        (".debug_ranges".to_owned(), debug_ranges),
+        (".debug_loc".to_owned(), debug_loc),
+        (".debug_aranges".to_owned(), debug_aranges)
+    ];
+
+    let moved = AddressMap::new( vec![ MappedFunction {
+        old_start: 1,
+        old_end: 7,
+        new_end: 9,
+        points: vec![ (1, 1), (2, 2), (3, 3), (5, 5), (6, 8) ]
+    }]);
+
+    let output = remap_sections( sections, &moved ).unwrap();
+    assert_eq!( output[ 0 ], (".debug_abbrev".to_owned(), debug_abbrev) );
+
+    // The subprogram starts at the same address, but it got longer.
+    let mut expected_debug_info = debug_info;
+    put_u32( &mut expected_debug_info, 29, 6 );
+    assert_eq!( output[ 1 ].1, expected_debug_info );
+
+    let read_u32s = |data: &[u8]| -> Vec< u32 > {
+        let mut reader = Reader::new( data, 0 );
+        let mut values = Vec::new();
+        while reader.position < data.len() {
+            values.push( reader.u32().unwrap() );
+        }
+        values
+    };
+
+    // The range of the code which was removed is replaced with a tombstone.
+    assert_eq!( read_u32s( &output[ 2 ].1 ), vec![ 3, 9, LIST_TOMBSTONE, LIST_TOMBSTONE, 0, 0 ] );
+
+    let debug_loc = &output[ 3 ].1;
+    assert_eq!( read_u32s( &debug_loc[ ..8 ] ), vec![ 3, 8 ] );
+    assert_eq!( &debug_loc[ 8..11 ], &[ 1, 0, 0x50 ] );
+
+    assert_eq!( read_u32s( &output[ 4 ].1[ 16.. ] ), vec![ 3, 6, TOMBSTONE, 0, 0, 0 ] );
+}
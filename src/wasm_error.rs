@@ -0,0 +1,84 @@
+use std::error;
+use std::fmt;
+
+use wasm_context::FunctionIndex;
+
+/// The stage of the `.wasm` processing pipeline in which an error happened.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Stage {
+    Parsing,
+    InlineJs,
+    JsExport,
+    JsSnippet,
+    ExportMain,
+    ExportTable,
+    Threads,
+    Serialization,
+    WasmOpt
+}
+
+impl fmt::Display for Stage {
+    fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        let name = match *self {
+            Stage::Parsing => "parsing",
+            Stage::InlineJs => "inline JS extraction",
+            Stage::JsExport => "`js_export!` processing",
+            Stage::JsSnippet => "JS snippet loading",
+            Stage::ExportMain => "`main` export",
+            Stage::ExportTable => "function table export",
+            Stage::Threads => "shared memory setup",
+            Stage::Serialization => "serialization",
+            Stage::WasmOpt => "`wasm-opt`"
+        };
+
+        write!( formatter, "{}", name )
+    }
+}
+
+#[derive(Debug)]
+pub struct ProcessingError {
+    pub stage: Stage,
+    pub function: Option< FunctionIndex >,
+    pub message: String
+}
+
+impl ProcessingError {
+    pub fn new< S: Into< String > >( stage: Stage, message: S ) -> Self {
+        ProcessingError {
+            stage,
+            function: None,
+            message: message.into()
+        }
+    }
+
+    pub fn in_function( mut self, function_index: FunctionIndex ) -> Self {
+        self.function = Some( function_index );
+        self
+    }
+}
+
+impl error::Error for ProcessingError {
+    fn description( &self ) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ProcessingError {
+    fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        write!( formatter, "{} failed", self.stage )?;
+        if let Some( function_index ) = self.function {
+            write!( formatter, " in function #{}", function_index )?;
+        }
+
+        write!( formatter, ": {}", self.message )
+    }
+}
+
+#[test]
+fn test_processing_error_display() {
+    let error = ProcessingError::new( Stage::InlineJs, "unexpected way of calling JS shims" ).in_function( 12 );
+    assert_eq!( format!( "{}", error ), "inline JS extraction failed in function #12: unexpected way of calling JS shims" );
+
+    let error = ProcessingError::new( Stage::Parsing, "unexpected end of file" );
+    assert_eq!( format!( "{}", error ), "parsing failed: unexpected end of file" );
+}
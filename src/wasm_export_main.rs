@@ -4,8 +4,9 @@ use wasm_context::{
     FunctionKind,
     Context
 };
+use wasm_error::{ProcessingError, Stage};
 
-pub fn process( ctx: &mut Context ) -> Option< String > {
+pub fn process( ctx: &mut Context ) -> Result< Option< String >, ProcessingError > {
     let main_index = ctx.functions.iter().find( |&(_, function)| {
         match *function {
             FunctionKind::Definition { ref export, .. } => {
@@ -17,12 +18,14 @@ pub fn process( ctx: &mut Context ) -> Option< String > {
 
     let start_index = ctx.start.take();
     if main_index.is_some() {
-        Some( "main".to_owned() )
+        Ok( Some( "main".to_owned() ) )
     } else if let Some( start_index ) = start_index {
-        let start_fn = ctx.functions.get_mut( &start_index ).unwrap();
+        let start_fn = ctx.functions.get_mut( &start_index ).ok_or_else( || {
+            ProcessingError::new( Stage::ExportMain, "the start function doesn't exist" ).in_function( start_index )
+        })?;
         *start_fn.as_export_mut() = Export::some( "main".to_owned() );
-        Some( "main".to_owned() )
+        Ok( Some( "main".to_owned() ) )
     } else {
-        None
+        Ok( None )
     }
 }
@@ -3,8 +3,12 @@ use wasm_context::{
     ImportExport,
     Context
 };
+use wasm_error::{ProcessingError, Stage};
 
-pub fn process( ctx: &mut Context ) {
-    let table = ctx.tables.values_mut().next().unwrap();
+pub fn process( ctx: &mut Context ) -> Result< (), ProcessingError > {
+    let table = ctx.tables.values_mut().next().ok_or_else( || {
+        ProcessingError::new( Stage::ExportTable, "the module has no function table" )
+    })?;
     *table.as_export_mut() = Export::some( "__indirect_function_table".to_owned() );
+    Ok(())
 }
@@ -13,6 +13,7 @@ use wasm_context::{
     Context,
     Instruction
 };
+use wasm_error::{ProcessingError, Stage};
 
 fn hash( string: &str ) -> String {
     let mut hasher = Sha1::new();
@@ -40,7 +41,7 @@ struct Snippet {
     offset: i32
 }
 
-pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
+pub fn process_and_extract( ctx: &mut Context ) -> Result< Vec< JsSnippet >, ProcessingError > {
     let mut shim_map: HashMap< FunctionIndex, TypeIndex > = HashMap::new();
     let mut snippet_offset_to_type_index = HashMap::new();
     let mut snippet_index_by_offset = HashMap::new();
@@ -56,23 +57,24 @@ pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
         }
     }
 
-    for (_, function) in &ctx.functions {
+    for (&caller_index, function) in &ctx.functions {
         if let &FunctionKind::Definition { ref instructions, .. } = function {
             for (index, instruction) in instructions.iter().enumerate() {
                 match instruction {
                     &Instruction::Call( function_index ) => {
                         if let Some( &type_index ) = shim_map.get( &function_index ) {
-                            match instructions[ index - 1 ] {
-                                Instruction::I32Const( offset ) => {
+                            let previous = if index > 0 { instructions.get( index - 1 ) } else { None };
+                            match previous {
+                                Some( &Instruction::I32Const( offset ) ) => {
                                     if let Some( previous_ty ) = snippet_offset_to_type_index.get( &offset ).cloned() {
                                         if type_index != previous_ty {
-                                            panic!( "internal error: same snippet of JS (by offset) is used with two different shims; please report this!" );
+                                            return Err( ProcessingError::new( Stage::InlineJs, "internal error: same snippet of JS (by offset) is used with two different shims; please report this!" ).in_function( caller_index ) );
                                         }
                                     }
 
                                     snippet_offset_to_type_index.insert( offset, type_index );
                                 },
-                                _ => panic!( "internal error: unexpected way of calling JS shims; please report this!" )
+                                _ => return Err( ProcessingError::new( Stage::InlineJs, "internal error: unexpected way of calling JS shims; please report this!" ).in_function( caller_index ) )
                             }
                         }
                     },
@@ -85,17 +87,23 @@ pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
     let mut data_entries = Vec::new();
     mem::swap( &mut data_entries, &mut ctx.data );
 
-    fn add_js_snippet( ctx: &mut Context, value_slice: &[u8], snippet_index_by_hash: &mut HashMap< String, usize >, snippet_index_by_offset: &mut HashMap< i32, usize >, snippets: &mut Vec< Snippet >, offset: i32, type_index: u32 ) {
+    fn add_js_snippet( ctx: &mut Context, value_slice: &[u8], snippet_index_by_hash: &mut HashMap< String, usize >, snippet_index_by_offset: &mut HashMap< i32, usize >, snippets: &mut Vec< Snippet >, offset: i32, type_index: u32 ) -> Result< (), ProcessingError > {
         let code = match String::from_utf8( value_slice.to_owned() ) {
             Ok( code ) => code,
             Err( _ ) => {
-                panic!( "You have invalid UTF-8 in one of your `js!` snippets! (offset = {}, length = {})", offset, value_slice.len() );
+                return Err( ProcessingError::new( Stage::InlineJs, format!( "you have invalid UTF-8 in one of your `js!` snippets! (offset = {}, length = {})", offset, value_slice.len() ) ) );
             }
         };
 
         let code_hash = hash( &code );
 
-        let shim_ty = ctx.fn_ty_by_index( type_index ).unwrap();
+        let shim_ty = ctx.fn_ty_by_index( type_index ).ok_or_else( || {
+            ProcessingError::new( Stage::InlineJs, format!( "JS shim refers to a non-existent type #{}", type_index ) )
+        })?;
+        if shim_ty.params.is_empty() {
+            return Err( ProcessingError::new( Stage::InlineJs, "JS shim doesn't take a pointer to the code" ) );
+        }
+
         let ty = FnTy {
             params: shim_ty.params.iter().cloned().take( shim_ty.params.len() - 1 ).collect(),
             return_type: shim_ty.return_type.clone()
@@ -104,7 +112,7 @@ pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
         if let Some( &snippet_index ) = snippet_index_by_hash.get( &code_hash ) {
             let snippet: &Snippet = &snippets[ snippet_index ];
             if snippet.ty != ty {
-                panic!(
+                return Err( ProcessingError::new( Stage::InlineJs, format!(
                     "internal error: same snippet of JS (by value) is used with two different shims; please report this!\nfn 1: {:?}\nfn 2: {:?}\nhash: {}\noffset 1: {}\noffset 2: {}\nsnippet:\n\"{}\"",
                     ty,
                     snippet.ty,
@@ -112,7 +120,7 @@ pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
                     snippet.offset,
                     offset,
                     snippet.code
-                );
+                )));
             }
 
             snippet_index_by_offset.insert( offset, snippet_index );
@@ -129,6 +137,8 @@ pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
             snippet_index_by_hash.insert( code_hash, snippets.len() );
             snippets.push( snippet );
         }
+
+        Ok(())
     }
 
     for (offset, type_index) in snippet_offset_to_type_index {
@@ -145,14 +155,14 @@ pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
                 }
             })
             .find( |&(data, data_offset)| offset >= data_offset && offset < (data_offset + data.value.len() as i32) )
-            .expect( "js! snippet not found in data section" );
+            .ok_or_else( || ProcessingError::new( Stage::InlineJs, format!( "`js!` snippet at offset {} not found in the data section", offset ) ) )?;
 
         let relative_offset = offset - data_offset;
         let slice = &data.value[ relative_offset as usize.. ];
         let slice = &slice[ 0..slice.iter().position( |&byte| byte == 0 ).unwrap_or( slice.len() ) ];
 
         // TODO: Purge this with the help of the new "linking" WASM section?
-        add_js_snippet( ctx, slice, &mut snippet_index_by_hash, &mut snippet_index_by_offset, &mut snippets, offset, type_index );
+        add_js_snippet( ctx, slice, &mut snippet_index_by_hash, &mut snippet_index_by_offset, &mut snippets, offset, type_index )?;
     }
 
     ctx.data = data_entries;
@@ -178,6 +188,8 @@ pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
         ctx.functions.remove( function_index );
     }
 
+    // Every call to a shim was already checked to be preceded by
+    // an `i32.const` with a known offset, so this can't fail.
     ctx.patch_code( |instructions| {
         let should_process = instructions.iter().any( |instruction| {
             match instruction {
@@ -198,11 +210,11 @@ pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
             match instruction {
                 Instruction::Call( function_index ) if shim_map.contains_key( &function_index ) => {
                     // Pop the last argument which was a pointer to the code.
-                    let offset = match new_instructions.pop().unwrap() {
-                        Instruction::I32Const( offset ) => offset,
-                        _ => panic!()
+                    let offset = match new_instructions.pop() {
+                        Some( Instruction::I32Const( offset ) ) => offset,
+                        _ => unreachable!()
                     };
-                    let &snippet_index = snippet_index_by_offset.get( &offset ).unwrap();
+                    let snippet_index = snippet_index_by_offset[ &offset ];
                     let snippet = &snippets[ snippet_index ];
                     new_instructions.push( Instruction::Call( snippet.function_index ) );
                 }
@@ -223,5 +235,5 @@ pub fn process_and_extract( ctx: &mut Context ) -> Vec< JsSnippet > {
         }
     }));
 
-    output
+    Ok( output )
 }
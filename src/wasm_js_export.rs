@@ -5,6 +5,7 @@ use wasm_context::{
     FunctionKind,
     Context
 };
+use wasm_error::{ProcessingError, Stage};
 
 pub struct JsExport {
     pub raw_name: String,
@@ -40,23 +41,24 @@ const ENCODING_BASE: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmno
 
 const PREFIX: &'static str = "__JS_EXPORT_";
 
-pub fn process( ctx: &mut Context ) -> Vec< JsExport > {
+pub fn process( ctx: &mut Context ) -> Result< Vec< JsExport >, ProcessingError > {
     let mut output = Vec::new();
-    for function in ctx.functions.values_mut() {
+    for (&function_index, function) in ctx.functions.iter_mut() {
         if let &mut FunctionKind::Definition { ref mut export, ref mut name, .. } = function {
             if export.names.len() == 1 && export.names[ 0 ].starts_with( PREFIX ) {
+                let error = |message: String| ProcessingError::new( Stage::JsExport, message ).in_function( function_index );
                 let json_metadata = {
                     let encoded_metadata = &export.names[ 0 ][ PREFIX.len().. ];
                     base_x::decode( ENCODING_BASE, encoded_metadata )
-                        .expect( "cannot decode `js_export!` symbol" )
+                        .map_err( |_| error( "cannot decode `js_export!` symbol".to_owned() ) )?
                 };
 
                 let json_metadata = String::from_utf8( json_metadata )
-                    .expect( "one of the `js_export!` symbols has metadata which is not valid UTF-8" );
+                    .map_err( |_| error( "one of the `js_export!` symbols has metadata which is not valid UTF-8".to_owned() ) )?;
 
                 let metadata: ExportMetadata =
                     serde_json::from_str( &json_metadata )
-                    .expect( "cannot parse `js_export!` symbol metadata" );
+                    .map_err( |err| error( format!( "cannot parse `js_export!` symbol metadata: {}", err ) ) )?;
 
                 export.names[ 0 ] = metadata.name.clone();
                 if let &mut Some( ref mut name ) = name {
@@ -71,5 +73,5 @@ pub fn process( ctx: &mut Context ) -> Vec< JsExport > {
         }
     }
 
-    output
+    Ok( output )
 }
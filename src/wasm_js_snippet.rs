@@ -5,6 +5,7 @@ use sha1::Sha1;
 use serde_json;
 
 use wasm_context::{Context, FunctionKind};
+use wasm_error::{ProcessingError, Stage};
 use wasm_inline_js::JsSnippet;
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
@@ -20,16 +21,20 @@ fn hash( string: &str ) -> String {
     format!( "{}", hasher.digest() )
 }
 
-pub fn process( target_dir: &Path, ctx: &Context ) -> Vec< JsSnippet > {
+pub fn process( target_dir: &Path, ctx: &Context ) -> Result< Vec< JsSnippet >, ProcessingError > {
     let mut snippets = Vec::new();
-    for (_, function) in &ctx.functions {
+    for (&function_index, function) in &ctx.functions {
         if let &FunctionKind::Import { ref import, .. } = function {
             if import.module == "env" {
                 let name_hash = hash( &import.field );
                 let path = target_dir.join( ".cargo-web" ).join( "snippets" ).join( &name_hash[ 0..2 ] ).join( format!( "{}.json", name_hash ) );
                 if path.exists() {
-                    let blob = fs::read( path ).expect( "cannot read JS snippet from the filesystem" );
-                    let snippet: Snippet = serde_json::from_slice( &blob ).expect( "corrupted JS snippet file" );
+                    let blob = fs::read( &path ).map_err( |error| {
+                        ProcessingError::new( Stage::JsSnippet, format!( "cannot read JS snippet from {:?}: {}", path, error ) ).in_function( function_index )
+                    })?;
+                    let snippet: Snippet = serde_json::from_slice( &blob ).map_err( |error| {
+                        ProcessingError::new( Stage::JsSnippet, format!( "corrupted JS snippet file {:?}: {}", path, error ) ).in_function( function_index )
+                    })?;
                     let snippet = JsSnippet {
                         name: snippet.name,
                         code: snippet.code,
@@ -41,5 +46,5 @@ pub fn process( target_dir: &Path, ctx: &Context ) -> Vec< JsSnippet > {
         }
     }
 
-    snippets
+    Ok( snippets )
 }
\ No newline at end of file
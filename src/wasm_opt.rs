@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use cargo_shim::MessageFormat;
+use config::WasmOptConfig;
+use emscripten::binaryen_package;
+use messages;
+use package::{DownloadSettings, download_package};
+use utils::{find_cmd, read_bytes};
+use wasm_bulk_memory;
+use wasm_sections;
+
+fn find_wasm_opt( message_format: MessageFormat ) -> Option< PathBuf > {
+    if let Some( package ) = binaryen_package() {
+        // If we can't get our own copy (e.g. when offline) one from the `PATH` will do.
+        match download_package( message_format, &DownloadSettings::from_env(), &package ) {
+            Ok( binaryen_root ) => {
+                let path = binaryen_root.join( "binaryen" ).join( "bin" ).join( "wasm-opt" );
+                if path.exists() {
+                    return Some( path );
+                }
+            },
+            Err( error ) => {
+                warn!( "Cannot get binaryen: {}; looking for `wasm-opt` in the PATH instead", error );
+            }
+        }
+    }
+
+    let possible_commands =
+        if cfg!( windows ) {
+            &[ "wasm-opt.exe" ]
+        } else {
+            &[ "wasm-opt" ]
+        };
+
+    find_cmd( possible_commands ).map( PathBuf::from )
+}
+
+fn export_names( path: &Path, bytes: &[u8] ) -> Result< Vec< String >, String > {
+    let module = wasm_bulk_memory::deserialize( bytes ).map_err( |error| format!( "cannot parse {:?}: {:?}", path, error ) )?;
+    let names = module.export_section()
+        .map( |section| section.entries().iter().map( |entry| entry.field().to_owned() ).collect() )
+        .unwrap_or_default();
+
+    Ok( names )
+}
+
+/// Returns the custom sections with the function names or the DWARF debug info.
+fn debug_sections( path: &Path, bytes: &[u8] ) -> Result< Vec< String >, String > {
+    let names = wasm_sections::custom_section_names( bytes ).map_err( |error| format!( "cannot parse {:?}: {:?}", path, error ) )?;
+    Ok( names.into_iter().filter( |name| name == "name" || name.starts_with( ".debug_" ) ).collect() )
+}
+
+/// Runs binaryen's `wasm-opt` over the given `.wasm` file in place
+/// if an optimization level was configured in `Web.toml`.
+///
+/// Fails if `wasm-opt` removes any of the exports since the
+/// generated JavaScript depends on them.
+pub fn run( message_format: MessageFormat, config: &WasmOptConfig, path: &Path, uses_shared_memory: bool ) -> Result< (), String > {
+    let level = match config.level {
+        Some( ref level ) => level,
+        None => return Ok(())
+    };
+
+    let wasm_opt = find_wasm_opt( message_format ).ok_or_else( || {
+        "`wasm-opt` not found; please install binaryen or remove the `wasm-opt` section from your Web.toml".to_owned()
+    })?;
+
+    messages::progress( message_format, "optimizing", Some( path ), &format!( "    Optimizing {:?} with `wasm-opt -O{}`...", path.file_name().unwrap(), level ) );
+
+    let bytes = read_bytes( path ).map_err( |error| format!( "cannot read {:?}: {}", path, error ) )?;
+    let exports_before = export_names( path, &bytes )?;
+    let debug_sections_before = debug_sections( path, &bytes )?;
+    let size_before = fs::metadata( path ).map( |metadata| metadata.len() ).unwrap_or( 0 );
+
+    let output_path = path.with_extension( "wasm-opt.wasm" );
+    let mut command = Command::new( &wasm_opt );
+    command.arg( format!( "-O{}", level ) );
+    if uses_shared_memory {
+        // The memory of such modules is initialized through `memory.init`.
+        command.arg( "--enable-threads" );
+        command.arg( "--enable-bulk-memory" );
+    }
+
+    // Without this the names and the DWARF which the source maps and the symbolication need are stripped.
+    if !debug_sections_before.is_empty() {
+        command.arg( "-g" );
+    }
+
+    let status = command
+        .arg( path )
+        .arg( "-o" )
+        .arg( &output_path )
+        .status()
+        .map_err( |error| format!( "cannot launch {:?}: {}", wasm_opt, error ) )?;
+
+    if !status.success() {
+        let _ = fs::remove_file( &output_path );
+        return Err( format!( "`wasm-opt` failed with {}", status ) );
+    }
+
+    let bytes = read_bytes( &output_path ).map_err( |error| format!( "cannot read {:?}: {}", output_path, error ) )?;
+    let exports_after = export_names( &output_path, &bytes )?;
+    let missing: Vec< _ > = exports_before.iter().filter( |name| !exports_after.contains( name ) ).map( |name| format!( "`{}`", name ) ).collect();
+    if !missing.is_empty() {
+        let _ = fs::remove_file( &output_path );
+        return Err( format!( "`wasm-opt` removed exports which the generated JavaScript depends on: {}", missing.join( ", " ) ) );
+    }
+
+    let debug_sections_after = debug_sections( &output_path, &bytes )?;
+    let dropped: Vec< _ > = debug_sections_before.iter().filter( |name| !debug_sections_after.contains( name ) ).map( |name| format!( "`{}`", name ) ).collect();
+    if !dropped.is_empty() {
+        messages::warning( message_format, Some( path ), &format!( "`wasm-opt` dropped the following debug sections: {}", dropped.join( ", " ) ) );
+    }
+
+    let _ = fs::remove_file( path );
+    fs::rename( &output_path, path ).map_err( |error| format!( "cannot rename {:?} to {:?}: {}", output_path, path, error ) )?;
+
+    let size_after = fs::metadata( path ).map( |metadata| metadata.len() ).unwrap_or( 0 );
+    debug!( "Size of {:?} after `wasm-opt`: {} -> {}", path, size_before, size_after );
+    Ok(())
+}
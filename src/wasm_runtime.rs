@@ -1,4 +1,5 @@
 use std::path::Path;
+use std::fs;
 use std::collections::BTreeMap;
 use std::fmt::Write as FmtWrite;
 use std::fmt::Display;
@@ -6,9 +7,13 @@ use std::str::FromStr;
 
 use unicode_categories::UnicodeCategories;
 use handlebars::Handlebars;
+use serde_json;
+
+use config::{EnvVariable, LoaderConfig, ThreadsConfig};
 
 use wasm_inline_js::JsSnippet;
 use wasm_js_export::{JsExport, TypeMetadata};
+use wasm_threads::{self, SharedMemory};
 
 use super::Error;
 
@@ -82,6 +87,13 @@ static ONLY_LOADER_TEMPLATE: &str = include_str!( "wasm_runtime_only_loader.js"
 static STANDALONE_TEMPLATE: &str = include_str!( "wasm_runtime_standalone.js" );
 static LIBRARY_ES6_TEMPLATE: &str = include_str!( "wasm_runtime_library_es6.js" );
 static WEB_EXTENSION_TEMPLATE: &str = include_str!( "wasm_runtime_web_extension.js" );
+static THREADS_TEMPLATE: &str = include_str!( "wasm_runtime_threads.js" );
+
+static INITIALIZE_THREADS: &str = r#"if( thread !== null ) {
+                return __web_initialize_worker_thread();
+            }
+
+            Object.defineProperty( Module, 'threads', { value: new ThreadPool() } );"#;
 
 fn join< T: Display, I: IntoIterator< Item = T > >( separator: &str, iter: I ) -> String {
     let mut output = String::new();
@@ -95,8 +107,9 @@ fn join< T: Display, I: IntoIterator< Item = T > >( separator: &str, iter: I ) -
     output
 }
 
-pub fn generate_js( runtime: RuntimeKind, main_symbol: Option< String >, wasm_path: &Path, prepend_js: &str, snippets: &[JsSnippet], exports: &[JsExport] ) -> String {
+pub fn generate_js( runtime: RuntimeKind, loader: &LoaderConfig, main_symbol: Option< String >, wasm_path: &Path, wasm_hash: &str, wasm_integrity: Option< &str >, worker_integrity: Option< &str >, prepend_js: &str, snippets: &[JsSnippet], exports: &[JsExport], shared_memory: Option< &SharedMemory >, threads: &ThreadsConfig, env: &[EnvVariable] ) -> String {
     let filename = wasm_path.file_name().unwrap().to_str().unwrap();
+    let wasm_size = fs::metadata( wasm_path ).map( |metadata| metadata.len() ).unwrap_or( 0 );
     let module_name = to_js_identifier( wasm_path.file_stem().unwrap().to_str().unwrap() );
 
     let mut snippets_js = String::new();
@@ -153,9 +166,44 @@ pub fn generate_js( runtime: RuntimeKind, main_symbol: Option< String >, wasm_pa
 
     let handlebars = Handlebars::new();
     let mut template_data = BTreeMap::new();
+    if let Some( shared_memory ) = shared_memory {
+        // The stack must be 16-byte aligned.
+        let stack_size = threads.stack_size.unwrap_or( wasm_threads::DEFAULT_STACK_SIZE );
+        let stack_size = (stack_size + 15) & !15;
+        let pool_size = threads.pool_size
+            .map( |pool_size| pool_size.to_string() )
+            .unwrap_or_else( || "( typeof navigator === \"object\" && navigator.hardwareConcurrency ) || 4".to_owned() );
+
+        template_data.insert( "memory_initial", shared_memory.initial.to_string() );
+        template_data.insert( "memory_maximum", shared_memory.maximum.to_string() );
+        template_data.insert( "stack_size", stack_size.to_string() );
+        template_data.insert( "tls_size", shared_memory.tls_size.to_string() );
+        template_data.insert( "tls_align", shared_memory.tls_align.to_string() );
+        template_data.insert( "pool_size", pool_size );
+        template_data.insert( "module_name", module_name.clone() );
+        let threads_js = handlebars.render_template( THREADS_TEMPLATE, &template_data ).unwrap();
+        template_data.clear();
+
+        template_data.insert( "threads", threads_js.trim().to_owned() );
+        template_data.insert( "initialize_threads", INITIALIZE_THREADS.to_owned() );
+        template_data.insert( "memory", "Module.memory".to_owned() );
+        template_data.insert( "memory_import", "\"memory\": Module.memory,".to_owned() );
+    } else {
+        template_data.insert( "threads", "".to_owned() );
+        template_data.insert( "initialize_threads", "".to_owned() );
+        template_data.insert( "memory", "Module.instance.exports.memory".to_owned() );
+        template_data.insert( "memory_import", "".to_owned() );
+    }
+
     template_data.insert( "snippets", snippets_js.trim().to_owned() );
     template_data.insert( "exports", exports_code.trim().to_owned() );
     template_data.insert( "prepend_js", prepend_js.to_owned() );
+
+    let exposed_env: BTreeMap< &str, &str > = env.iter()
+        .filter( |variable| variable.expose )
+        .map( |variable| (variable.name.as_str(), variable.value.as_str()) )
+        .collect();
+    template_data.insert( "env", serde_json::to_string( &exposed_env ).unwrap() );
     if let Some( main_symbol ) = main_symbol {
         template_data.insert( "call_main", format!( "Module.instance.exports.{}();", main_symbol ) );
     } else {
@@ -166,7 +214,15 @@ pub fn generate_js( runtime: RuntimeKind, main_symbol: Option< String >, wasm_pa
     template_data.clear();
     template_data.insert( "factory", factory );
     template_data.insert( "wasm_filename", filename.to_owned() );
+    template_data.insert( "worker_wasm_filename", wasm_threads::worker_path( wasm_path ).file_name().unwrap().to_string_lossy().into_owned() );
     template_data.insert( "module_name", module_name );
+    template_data.insert( "wasm_size", wasm_size.to_string() );
+    template_data.insert( "wasm_hash", wasm_hash.to_owned() );
+    template_data.insert( "cache", loader.cache_in_indexeddb.to_string() );
+    template_data.insert( "integrity", serde_json::to_string( &wasm_integrity ).unwrap() );
+    template_data.insert( "worker_integrity", serde_json::to_string( &worker_integrity ).unwrap() );
+    template_data.insert( "wasm_base_url", serde_json::to_string( &loader.wasm_base_url ).unwrap() );
+    template_data.insert( "credentials", serde_json::to_string( loader.credentials.as_ref().map( |credentials| credentials.as_str() ).unwrap_or( "same-origin" ) ).unwrap() );
 
     match runtime {
         RuntimeKind::Standalone => {
@@ -1,6 +1,8 @@
-function() {
+function( environment ) {
     var Module = {};
 
+    Object.defineProperty( Module, 'env', { value: Object.freeze( {{{env}}} ) } );
+
     {{{prepend_js}}}
 
     var HEAP8 = null;
@@ -14,8 +16,10 @@ function() {
 
     Object.defineProperty( Module, 'exports', { value: {} } );
 
+    {{{threads}}}
+
     function __web_on_grow() {
-        var buffer = Module.instance.exports.memory.buffer;
+        var buffer = {{{memory}}}.buffer;
         HEAP8 = new Int8Array( buffer );
         HEAP16 = new Int16Array( buffer );
         HEAP32 = new Int32Array( buffer );
@@ -38,6 +42,7 @@ function() {
         imports: {
             env: {
                 {{{snippets}}}
+                {{{memory_import}}}
                 "__web_on_grow": __web_on_grow
             }
         },
@@ -46,6 +51,7 @@ function() {
             Object.defineProperty( Module, 'web_malloc', { value: Module.instance.exports.__web_malloc } );
             Object.defineProperty( Module, 'web_free', { value: Module.instance.exports.__web_free } );
             Object.defineProperty( Module, 'web_table', { value: Module.instance.exports.__indirect_function_table } );
+            {{{initialize_threads}}}
 
             {{{exports}}}
             __web_on_grow();
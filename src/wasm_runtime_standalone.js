@@ -5,18 +5,239 @@ if( typeof Rust === "undefined" ) {
 }
 
 (function( root, factory ) {
+    // This has to be grabbed here since `document.currentScript`
+    // is only set while the script is being initially evaluated.
+    var script_url = ( typeof document === "object" && document.currentScript ) ? document.currentScript.src : null;
+
     if( typeof define === "function" && define.amd ) {
-        define( [], factory );
+        define( [], function() { return factory( script_url ); } );
     } else if( typeof module === "object" && module.exports ) {
-        module.exports = factory();
+        module.exports = factory( script_url );
     } else {
-        Rust.{{{module_name}}} = factory();
+        Rust.{{{module_name}}} = factory( script_url );
     }
-}( this, function() {
+}( this, function( script_url ) {
     return (function( module_factory ) {
-        var instance = module_factory();
+        var options = ( typeof Rust.options === "object" && Rust.options !== null && Rust.options["{{{module_name}}}"] ) || {};
+        var instance = module_factory({
+            options: options,
+            script_url: script_url,
+            load_worker_module: load_worker_module
+        });
+
+        function is_wasm_module( wasm ) {
+            return typeof WebAssembly.Module === "function" && wasm instanceof WebAssembly.Module;
+        }
+
+        function resolve_wasm_url() {
+            if( typeof options.url === "string" ) {
+                return options.url;
+            }
+
+            return resolve_url( "{{{wasm_filename}}}" );
+        }
+
+        function resolve_url( filename ) {
+            var base_url = typeof options.base_url === "string" ? options.base_url : {{{wasm_base_url}}};
+
+            if( base_url !== null ) {
+                if( base_url.length > 0 && base_url[ base_url.length - 1 ] !== "/" ) {
+                    base_url += "/";
+                }
+            } else {
+                base_url = script_url;
+            }
+
+            if( !base_url || typeof URL !== "function" ) {
+                return ( base_url && base_url[ base_url.length - 1 ] === "/" ? base_url : "" ) + filename;
+            }
+
+            // There's no `document` inside of a Worker.
+            var document_url = typeof document === "object" ? document.baseURI : self.location.href;
+            return new URL( filename, new URL( base_url, document_url ) ).href;
+        }
+
+        // Only used when the module uses shared memory.
+        function load_worker_module() {
+            var fetch_fn = typeof options.fetch === "function" ? options.fetch : fetch;
+            var credentials = typeof options.credentials === "string" ? options.credentials : {{{credentials}}};
+            var url = typeof options.worker_url === "string" ? options.worker_url : resolve_url( "{{{worker_wasm_filename}}}" );
+            var worker_integrity = ( typeof options.worker_integrity === "string" || options.worker_integrity === false ) ? options.worker_integrity : {{{worker_integrity}}};
+            var init = {credentials: credentials};
+            if( options.integrity !== false && worker_integrity ) {
+                init.integrity = worker_integrity;
+            }
+
+            return Promise.resolve( fetch_fn( url, init ) )
+                .then( function( response ) { return response.arrayBuffer(); } )
+                .then( function( bytes ) { return WebAssembly.compile( bytes ); } );
+        }
+
+        var loaded_bytes = 0;
+        var total_bytes = {{{wasm_size}}};
+
+        function report_progress( phase ) {
+            var progress = {
+                module: "{{{module_name}}}",
+                phase: phase,
+                loaded: loaded_bytes,
+                total: Math.max( loaded_bytes, total_bytes )
+            };
+
+            if( typeof options.on_progress === "function" ) {
+                options.on_progress( progress );
+            }
+
+            if( typeof window === "object" && typeof window.dispatchEvent === "function" && typeof CustomEvent === "function" ) {
+                window.dispatchEvent( new CustomEvent( "cargo-web:progress", { detail: progress } ) );
+            }
+        }
+
+        function track_download( response ) {
+            if( !response.body || typeof response.body.getReader !== "function" || typeof ReadableStream !== "function" ) {
+                report_progress( "compiling" );
+                return response;
+            }
+
+            var reader = response.body.getReader();
+            var stream = new ReadableStream({
+                pull: function( controller ) {
+                    return reader.read().then( function( result ) {
+                        if( result.done ) {
+                            controller.close();
+                            report_progress( "compiling" );
+                            return;
+                        }
+
+                        loaded_bytes += result.value.byteLength;
+                        report_progress( "downloading" );
+                        controller.enqueue( result.value );
+                    });
+                },
+                cancel: function( reason ) {
+                    return reader.cancel( reason );
+                }
+            });
+
+            return new Response( stream, {
+                status: response.status,
+                statusText: response.statusText,
+                headers: response.headers
+            });
+        }
+
+        var wasm_hash = "{{{wasm_hash}}}";
+        var integrity = ( typeof options.integrity === "string" || options.integrity === false ) ? options.integrity : {{{integrity}}};
+        var use_cache = ( typeof options.cache === "boolean" ? options.cache : {{{cache}}} ) && typeof indexedDB === "object" && indexedDB !== null;
+        var downloaded_bytes = null;
+
+        function open_cache() {
+            return new Promise( function( resolve, reject ) {
+                var request = indexedDB.open( "cargo-web", 1 );
+                request.onupgradeneeded = function() {
+                    request.result.createObjectStore( "modules" );
+                };
+                request.onsuccess = function() { resolve( request.result ); };
+                request.onerror = function() { reject( request.error ); };
+            });
+        }
+
+        function load_from_cache() {
+            return open_cache().then( function( db ) {
+                return new Promise( function( resolve, reject ) {
+                    var request = db.transaction( "modules", "readonly" ).objectStore( "modules" ).get( "{{{module_name}}}" );
+                    request.onsuccess = function() {
+                        var entry = request.result;
+                        resolve( entry && entry.hash === wasm_hash ? entry.wasm : null );
+                    };
+                    request.onerror = function() { reject( request.error ); };
+                });
+            }).then( function( cached ) {
+                if( cached === null || !integrity ) {
+                    return cached;
+                }
+
+                // A compiled module can't be verified, so with the integrity enabled only the raw bytes are cached.
+                if( is_wasm_module( cached ) ) {
+                    return null;
+                }
+
+                return verify_integrity( cached ).then( function( is_valid ) {
+                    if( !is_valid ) {
+                        console.log( "The cached Rust wasm module '{{{module_name}}}' doesn't match its integrity hash; downloading it again" );
+                        return null;
+                    }
+
+                    return cached;
+                });
+            });
+        }
+
+        function verify_integrity( bytes ) {
+            var match = /^sha384-(.+)$/.exec( integrity );
+            if( match === null || typeof crypto !== "object" || !crypto.subtle ) {
+                return Promise.resolve( false );
+            }
+
+            return crypto.subtle.digest( "SHA-384", bytes ).then( function( digest ) {
+                var binary = "";
+                var digest_bytes = new Uint8Array( digest );
+                for( var i = 0; i < digest_bytes.length; ++i ) {
+                    binary += String.fromCharCode( digest_bytes[ i ] );
+                }
+
+                return btoa( binary ) === match[ 1 ];
+            });
+        }
+
+        function store_in_cache( mod ) {
+            return open_cache().then( function( db ) {
+                function put( wasm ) {
+                    return new Promise( function( resolve, reject ) {
+                        var transaction = db.transaction( "modules", "readwrite" );
+                        transaction.oncomplete = function() { resolve(); };
+                        transaction.onerror = function() { reject( transaction.error ); };
+                        transaction.onabort = function() { reject( transaction.error ); };
+                        transaction.objectStore( "modules" ).put( { hash: wasm_hash, wasm: wasm }, "{{{module_name}}}" );
+                    });
+                }
+
+                if( integrity ) {
+                    return downloaded_bytes.then( put );
+                }
+
+                // Not every browser supports storing compiled modules,
+                // in which case we fall back to storing the raw bytes.
+                return put( mod ).catch( function( error ) {
+                    if( downloaded_bytes === null ) {
+                        throw error;
+                    }
+
+                    return downloaded_bytes.then( put );
+                });
+            }).catch( function( error ) {
+                console.log( "Cannot cache Rust wasm module '{{{module_name}}}':", error );
+            });
+        }
+
+        function compile( wasm ) {
+            if( is_wasm_module( wasm ) ) {
+                return wasm;
+            }
+
+            report_progress( "compiling" );
+            return WebAssembly.compile( wasm );
+        }
 
         if( typeof process === "object" && typeof process.versions === "object" && typeof process.versions.node === "string" ) {
+            if( options.wasm !== undefined ) {
+                // This can be a promise, so we have to go through the asynchronous path.
+                return Promise.resolve( options.wasm )
+                    .then( compile )
+                    .then( function( mod ) { return WebAssembly.instantiate( mod, instance.imports ); } )
+                    .then( function( wasm_instance ) { return instance.initialize( wasm_instance ); } );
+            }
+
             var fs = require( "fs" );
             var path = require( "path" );
             var wasm_path = path.join( __dirname, "{{{wasm_filename}}}" );
@@ -25,25 +246,69 @@ if( typeof Rust === "undefined" ) {
             var wasm_instance = new WebAssembly.Instance( mod, instance.imports );
             return instance.initialize( wasm_instance );
         } else {
-            var file = fetch( "{{{wasm_filename}}}", {credentials: "same-origin"} );
+            function download_and_compile() {
+                var fetch_fn = typeof options.fetch === "function" ? options.fetch : fetch;
+                var credentials = typeof options.credentials === "string" ? options.credentials : {{{credentials}}};
+                var init = {credentials: credentials};
+                if( integrity ) {
+                    // The browser will refuse to hand us the body if it doesn't match.
+                    init.integrity = integrity;
+                }
 
-            var wasm_instance = ( typeof WebAssembly.instantiateStreaming === "function"
-                ? WebAssembly.instantiateStreaming( file, instance.imports )
-                    .then( function( result ) { return result.instance; } )
+                report_progress( "downloading" );
+                var file = Promise.resolve( fetch_fn( resolve_wasm_url(), init ) ).then( track_download );
+                if( use_cache ) {
+                    file = file.then( function( response ) {
+                        downloaded_bytes = response.clone().arrayBuffer();
+                        return response;
+                    });
+                }
 
-                : file
-                    .then( function( response ) { return response.arrayBuffer(); } )
-                    .then( function( bytes ) { return WebAssembly.compile( bytes ); } )
-                    .then( function( mod ) { return WebAssembly.instantiate( mod, instance.imports ) } ) );
+                return ( typeof WebAssembly.compileStreaming === "function"
+                    ? WebAssembly.compileStreaming( file )
+                    : file
+                        .then( function( response ) { return response.arrayBuffer(); } )
+                        .then( function( bytes ) { return WebAssembly.compile( bytes ); } ) );
+            }
 
-            return wasm_instance
+            var wasm_module;
+            if( options.wasm !== undefined ) {
+                wasm_module = Promise.resolve( options.wasm ).then( compile );
+            } else if( use_cache ) {
+                wasm_module = load_from_cache()
+                    .catch( function( error ) {
+                        console.log( "Cannot load Rust wasm module '{{{module_name}}}' from the cache:", error );
+                        return null;
+                    })
+                    .then( function( cached ) {
+                        if( cached !== null ) {
+                            return compile( cached );
+                        }
+
+                        return download_and_compile().then( function( mod ) {
+                            store_in_cache( mod );
+                            return mod;
+                        });
+                    });
+            } else {
+                wasm_module = download_and_compile();
+            }
+
+            return wasm_module
+                .then( function( mod ) {
+                    report_progress( "instantiating" );
+                    return WebAssembly.instantiate( mod, instance.imports );
+                })
                 .then( function( wasm_instance ) {
+                    report_progress( "running-main" );
                     var exports = instance.initialize( wasm_instance );
                     console.log( "Finished loading Rust wasm module '{{{module_name}}}'" );
+                    report_progress( "finished" );
                     return exports;
                 })
                 .catch( function( error ) {
                     console.log( "Error loading Rust wasm module '{{{module_name}}}':", error );
+                    report_progress( "failed" );
                     throw error;
                 });
         }
@@ -0,0 +1,121 @@
+var thread = ( environment && environment.options && environment.options.thread ) || null;
+
+    Object.defineProperty( Module, 'memory', {
+        value: ( thread !== null && thread.memory ) || new WebAssembly.Memory( { initial: {{{memory_initial}}}, maximum: {{{memory_maximum}}}, shared: true } )
+    });
+
+    // This is stringified and evaluated inside of every worker.
+    function __web_worker_main() {
+        self.onmessage = function( event ) {
+            var data = event.data;
+            self.onmessage = null;
+            self.Rust = { options: {} };
+            self.Rust.options[ data.name ] = {
+                wasm: data.module,
+                cache: false,
+                thread: {
+                    memory: data.memory,
+                    stack_top: data.stack_top,
+                    tls_block: data.tls_block
+                }
+            };
+
+            importScripts( data.script_url );
+        };
+    }
+
+    function __web_initialize_worker_thread() {
+        Module.instance.exports.__web_initialize_thread( thread.stack_top, thread.tls_block );
+        __web_on_grow();
+
+        self.onmessage = function( event ) {
+            Module.web_table.get( event.data.fn_pointer )( event.data.argument );
+            self.postMessage( "done" );
+        };
+
+        self.postMessage( "ready" );
+        return Module.exports;
+    }
+
+    function ThreadPool() {
+        this.size = {{{pool_size}}};
+        this.worker_count = 0;
+        this.starting_count = 0;
+        this.idle_workers = [];
+        this.queue = [];
+        this.worker_module = null;
+    }
+
+    ThreadPool.prototype.start_worker = function() {
+        if( typeof Worker !== "function" || !environment || typeof environment.load_worker_module !== "function" ) {
+            throw new Error( "Threads are not supported in this environment" );
+        }
+
+        if( !environment.script_url ) {
+            throw new Error( "Cannot start a worker thread since the URL of the script of Rust wasm module '{{{module_name}}}' is unknown" );
+        }
+
+        var pool = this;
+        var stack_size = {{{stack_size}}};
+        var stack_top = Module.web_malloc( stack_size ) + stack_size;
+
+        // Every thread gets its own copy of the thread-local variables.
+        var tls_size = {{{tls_size}}};
+        var tls_align = {{{tls_align}}};
+        var tls_block = 0;
+        if( tls_size > 0 ) {
+            tls_block = ( Module.web_malloc( tls_size + tls_align ) + tls_align - 1 ) & ~( tls_align - 1 );
+        }
+
+        this.worker_count += 1;
+        this.starting_count += 1;
+        if( this.worker_module === null ) {
+            this.worker_module = environment.load_worker_module();
+        }
+
+        this.worker_module.then( function( worker_module ) {
+            var source = "(" + __web_worker_main.toString() + ")();";
+            var worker = new Worker( URL.createObjectURL( new Blob( [ source ], { type: "application/javascript" } ) ) );
+            worker.onmessage = function( event ) {
+                if( event.data === "ready" ) {
+                    pool.starting_count -= 1;
+                }
+
+                pool.idle_workers.push( worker );
+                pool.dispatch();
+            };
+
+            worker.onerror = function( error ) {
+                console.log( "Error in a worker thread of Rust wasm module '{{{module_name}}}':", error );
+            };
+
+            worker.postMessage({
+                name: "{{{module_name}}}",
+                script_url: environment.script_url,
+                module: worker_module,
+                memory: Module.memory,
+                stack_top: stack_top,
+                tls_block: tls_block
+            });
+        }).catch( function( error ) {
+            pool.worker_count -= 1;
+            pool.starting_count -= 1;
+            console.log( "Cannot start a worker thread for Rust wasm module '{{{module_name}}}':", error );
+        });
+    };
+
+    ThreadPool.prototype.dispatch = function() {
+        while( this.queue.length > 0 && this.idle_workers.length > 0 ) {
+            this.idle_workers.pop().postMessage( this.queue.shift() );
+        }
+
+        while( this.queue.length > this.starting_count && this.worker_count < this.size ) {
+            this.start_worker();
+        }
+    };
+
+    // Runs `fn_pointer( argument )` on one of the worker threads.
+    ThreadPool.prototype.spawn = function( fn_pointer, argument ) {
+        this.queue.push( { fn_pointer: fn_pointer, argument: argument } );
+        this.dispatch();
+    };
@@ -0,0 +1,130 @@
+use parity_wasm::elements as pw;
+use parity_wasm::elements::Deserialize;
+
+pub const CUSTOM_SECTION_ID: u8 = 0;
+pub const CODE_SECTION_ID: u8 = 10;
+
+/// Iterates over a sequence of `(id, payload)` pairs, each of which is encoded
+/// as a one byte id followed by the payload's length and the payload itself.
+///
+/// This is the format of both the sections of a module
+/// and the subsections of the `name` section.
+pub struct Sections< 'a > {
+    p: &'a [u8]
+}
+
+impl< 'a > Iterator for Sections< 'a > {
+    type Item = Result< (u8, &'a [u8]), pw::Error >;
+
+    fn next( &mut self ) -> Option< Self::Item > {
+        if self.p.is_empty() {
+            return None;
+        }
+
+        let result = read_section( &mut self.p );
+        if result.is_err() {
+            self.p = &[];
+        }
+
+        Some( result )
+    }
+}
+
+fn read_section< 'a >( p: &mut &'a [u8] ) -> Result< (u8, &'a [u8]), pw::Error > {
+    let id = u8::from( pw::VarUint7::deserialize( p )? );
+    let size = u32::from( pw::VarUint32::deserialize( p )? ) as usize;
+    if size > p.len() {
+        return Err( pw::Error::UnexpectedEof );
+    }
+
+    let (payload, rest) = p.split_at( size );
+    *p = rest;
+    Ok( (id, payload) )
+}
+
+/// Returns the sections of the given module's bytes.
+pub fn sections< 'a >( bytes: &'a [u8] ) -> Result< Sections< 'a >, pw::Error > {
+    if bytes.len() < 8 {
+        return Err( pw::Error::UnexpectedEof );
+    }
+
+    Ok( Sections { p: &bytes[ 8.. ] } )
+}
+
+/// Returns the subsections of the given `name` section's payload.
+pub fn subsections< 'a >( payload: &'a [u8] ) -> Sections< 'a > {
+    Sections { p: payload }
+}
+
+/// Returns the payload of the first section with the given id.
+pub fn find_section( bytes: &[u8], section_id: u8 ) -> Result< Option< &[u8] >, pw::Error > {
+    for section in sections( bytes )? {
+        let (id, payload) = section?;
+        if id == section_id {
+            return Ok( Some( payload ) );
+        }
+    }
+
+    Ok( None )
+}
+
+/// Splits the payload of a custom section into its name and the rest of its payload.
+fn split_custom_section< 'a >( mut payload: &'a [u8] ) -> Result< (&'a [u8], &'a [u8]), pw::Error > {
+    let name_length = u32::from( pw::VarUint32::deserialize( &mut payload )? ) as usize;
+    if name_length > payload.len() {
+        return Err( pw::Error::UnexpectedEof );
+    }
+
+    Ok( payload.split_at( name_length ) )
+}
+
+/// Returns the payload of the custom section with the given name, without the name itself.
+pub fn find_custom_section< 'a >( bytes: &'a [u8], name: &str ) -> Result< Option< &'a [u8] >, pw::Error > {
+    for section in sections( bytes )? {
+        let (id, payload) = section?;
+        if id != CUSTOM_SECTION_ID {
+            continue;
+        }
+
+        let (section_name, payload) = split_custom_section( payload )?;
+        if section_name == name.as_bytes() {
+            return Ok( Some( payload ) );
+        }
+    }
+
+    Ok( None )
+}
+
+/// Returns the names of every custom section.
+pub fn custom_section_names( bytes: &[u8] ) -> Result< Vec< String >, pw::Error > {
+    let mut output = Vec::new();
+    for section in sections( bytes )? {
+        let (id, payload) = section?;
+        if id == CUSTOM_SECTION_ID {
+            let (name, _) = split_custom_section( payload )?;
+            output.push( String::from_utf8_lossy( name ).into_owned() );
+        }
+    }
+
+    Ok( output )
+}
+
+#[test]
+fn test_sections() {
+    let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+    bytes.extend_from_slice( &[ 1, 2, 0xaa, 0xbb ] );
+    bytes.extend_from_slice( &[ 0, 4, 3, b'f', b'o', b'o', 10, 1, 0xcc ] );
+
+    let list: Vec< _ > = sections( &bytes ).unwrap().map( |section| section.unwrap() ).collect();
+    assert_eq!( list, vec![ (1, &[ 0xaa, 0xbb ][..]), (0, &[ 3, b'f', b'o', b'o' ][..]), (10, &[ 0xcc ][..]) ] );
+    assert_eq!( find_section( &bytes, CODE_SECTION_ID ).unwrap(), Some( &[ 0xcc ][..] ) );
+    assert_eq!( find_section( &bytes, 2 ).unwrap(), None );
+    assert_eq!( find_custom_section( &bytes, "foo" ).unwrap(), Some( &[][..] ) );
+    assert_eq!( find_custom_section( &bytes, "bar" ).unwrap(), None );
+    assert_eq!( custom_section_names( &bytes ).unwrap(), vec![ "foo".to_owned() ] );
+
+    // The last section is truncated.
+    bytes.extend_from_slice( &[ 11, 5, 0 ] );
+    assert!( find_section( &bytes, 11 ).is_err() );
+    assert!( sections( b"\0asm" ).is_err() );
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use std::usize;
+
+use parity_wasm::elements as pw;
+use rustc_demangle;
+
+use wasm_bulk_memory;
+use wasm_context::{Context, FunctionKind, ImportExport, Instruction};
+use wasm_dwarf;
+
+// The root represents everything which is reachable from the outside,
+// and the table everything which can be called indirectly.
+const ROOT: usize = 0;
+const TABLE: usize = 1;
+
+fn function_node( function_index: u32 ) -> usize {
+    function_index as usize + 2
+}
+
+fn demangle( name: &str ) -> String {
+    format!( "{:#}", rustc_demangle::demangle( name ) )
+}
+
+#[derive(Serialize, Debug)]
+pub struct FunctionSize {
+    pub index: u32,
+    pub name: String,
+    pub size: u64,
+    pub retained_size: u64,
+    /// The chain of immediate dominators, from the closest one up to the root.
+    pub retained_by: Vec< String >
+}
+
+#[derive(Serialize, Debug)]
+pub struct CrateSize {
+    pub name: String,
+    pub size: u64,
+    pub functions: usize
+}
+
+#[derive(Serialize, Debug)]
+pub struct DataSegmentSize {
+    pub offset: Option< i32 >,
+    pub size: u64
+}
+
+#[derive(Serialize, Debug)]
+pub struct SizeReport {
+    pub total_size: u64,
+    pub code_size: u64,
+    pub data_size: u64,
+    pub unreachable_size: u64,
+    pub functions: Vec< FunctionSize >,
+    pub crates: Vec< CrateSize >,
+    pub data_segments: Vec< DataSegmentSize >
+}
+
+fn crate_name( name: &str ) -> &str {
+    let mut name = name;
+    loop {
+        let trimmed = [ "<", "&", "mut ", "*const ", "*mut ", "dyn ", "(" ].iter()
+            .filter( |prefix| name.starts_with( *prefix ) )
+            .map( |prefix| &name[ prefix.len().. ] )
+            .next();
+
+        match trimmed {
+            Some( trimmed ) => name = trimmed,
+            None => break
+        }
+    }
+
+    match name.find( "::" ) {
+        Some( position ) if position > 0 && name[ ..position ].chars().all( |ch| ch.is_alphanumeric() || ch == '_' ) => &name[ ..position ],
+        _ => "(unknown)"
+    }
+}
+
+// Returns the immediate dominator of every node and the nodes
+// reachable from the root in postorder.
+//
+// This is based on "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy.
+fn compute_dominators( edges: &[Vec< usize >] ) -> (Vec< Option< usize > >, Vec< usize >) {
+    let count = edges.len();
+    let mut postorder = Vec::with_capacity( count );
+    let mut visited = vec![ false; count ];
+    let mut stack = vec![ (ROOT, 0) ];
+    visited[ ROOT ] = true;
+    loop {
+        let (node, edge_index) = match stack.last() {
+            Some( &entry ) => entry,
+            None => break
+        };
+
+        if edge_index < edges[ node ].len() {
+            stack.last_mut().unwrap().1 += 1;
+            let target = edges[ node ][ edge_index ];
+            if !visited[ target ] {
+                visited[ target ] = true;
+                stack.push( (target, 0) );
+            }
+        } else {
+            stack.pop();
+            postorder.push( node );
+        }
+    }
+
+    let mut postorder_index = vec![ usize::MAX; count ];
+    for (index, &node) in postorder.iter().enumerate() {
+        postorder_index[ node ] = index;
+    }
+
+    let mut predecessors = vec![ Vec::new(); count ];
+    for &node in &postorder {
+        for &target in &edges[ node ] {
+            predecessors[ target ].push( node );
+        }
+    }
+
+    let intersect = |idom: &[Option< usize >], mut a: usize, mut b: usize| {
+        while a != b {
+            while postorder_index[ a ] < postorder_index[ b ] {
+                a = idom[ a ].unwrap();
+            }
+            while postorder_index[ b ] < postorder_index[ a ] {
+                b = idom[ b ].unwrap();
+            }
+        }
+        a
+    };
+
+    let mut idom = vec![ None; count ];
+    idom[ ROOT ] = Some( ROOT );
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &node in postorder.iter().rev() {
+            if node == ROOT {
+                continue;
+            }
+
+            let mut new_idom = None;
+            for &predecessor in &predecessors[ node ] {
+                if idom[ predecessor ].is_none() {
+                    continue;
+                }
+
+                new_idom = Some( match new_idom {
+                    None => predecessor,
+                    Some( current ) => intersect( &idom, predecessor, current )
+                });
+            }
+
+            if new_idom != idom[ node ] {
+                idom[ node ] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    (idom, postorder)
+}
+
+/// Computes a size breakdown of a processed `.wasm` file.
+pub fn analyze( bytes: &[u8], top: usize ) -> Result< SizeReport, String > {
+    let converted = wasm_bulk_memory::from_standard_encoding( bytes ).map_err( |error| format!( "cannot parse the module: {:?}", error ) )?;
+    let layouts = wasm_dwarf::scan_code_layout( &converted ).map_err( |error| format!( "cannot parse the code section: {:?}", error ) )?;
+    let module = pw::deserialize_buffer( &converted ).map_err( |error| format!( "cannot parse the module: {:?}", error ) )?;
+    let ctx = Context::from_module( module ).map_err( |error| error.to_string() )?;
+
+    let count = ctx.functions.len() + 2;
+    let mut names = vec![ String::new(); count ];
+    let mut sizes = vec![ 0_u64; count ];
+    let mut edges = vec![ Vec::new(); count ];
+    let mut is_definition = vec![ false; count ];
+    names[ ROOT ] = "<root>".to_owned();
+    names[ TABLE ] = "<function table>".to_owned();
+
+    let mut definition_index = 0;
+    for (&function_index, function) in &ctx.functions {
+        let node = function_node( function_index );
+        if node >= count {
+            return Err( format!( "unexpected function index: {}", function_index ) );
+        }
+
+        if function.is_exported() {
+            edges[ ROOT ].push( node );
+        }
+
+        match function {
+            &FunctionKind::Import { ref name, ref import, .. } => {
+                names[ node ] = match *name {
+                    Some( ref name ) => demangle( name ),
+                    None => format!( "{}.{}", import.module, import.field )
+                };
+            },
+            &FunctionKind::Definition { ref name, ref instructions, .. } => {
+                names[ node ] = match *name {
+                    Some( ref name ) => demangle( name ),
+                    None => format!( "<function {}>", function_index )
+                };
+
+                if let Some( layout ) = layouts.get( definition_index ) {
+                    sizes[ node ] = (layout.end - layout.start) as u64;
+                }
+
+                is_definition[ node ] = true;
+                definition_index += 1;
+
+                for instruction in instructions {
+                    match instruction {
+                        &Instruction::Call( target ) if function_node( target ) < count => edges[ node ].push( function_node( target ) ),
+                        &Instruction::CallIndirect( .. ) => edges[ node ].push( TABLE ),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some( start ) = ctx.start {
+        edges[ ROOT ].push( function_node( start ) );
+    }
+
+    edges[ ROOT ].push( TABLE );
+    if let Some( ref table ) = ctx.fn_pointer_tables {
+        for &member in &table.members {
+            if function_node( member ) < count {
+                edges[ TABLE ].push( function_node( member ) );
+            }
+        }
+    }
+
+    for targets in &mut edges {
+        targets.sort();
+        targets.dedup();
+    }
+
+    let (idom, postorder) = compute_dominators( &edges );
+    let mut retained_sizes = sizes.clone();
+    for &node in &postorder {
+        if node == ROOT {
+            continue;
+        }
+
+        let parent = idom[ node ].unwrap();
+        retained_sizes[ parent ] += retained_sizes[ node ];
+    }
+
+    let mut definitions: Vec< usize > = (0..count).filter( |&node| is_definition[ node ] ).collect();
+    let code_size = definitions.iter().map( |&node| sizes[ node ] ).sum();
+    let unreachable_size = definitions.iter().filter( |&&node| idom[ node ].is_none() ).map( |&node| sizes[ node ] ).sum();
+
+    let mut crates: HashMap< &str, CrateSize > = HashMap::new();
+    for &node in &definitions {
+        let name = crate_name( &names[ node ] );
+        let entry = crates.entry( name ).or_insert_with( || CrateSize {
+            name: name.to_owned(),
+            size: 0,
+            functions: 0
+        });
+
+        entry.size += sizes[ node ];
+        entry.functions += 1;
+    }
+
+    let mut crates: Vec< _ > = crates.into_iter().map( |(_, entry)| entry ).collect();
+    crates.sort_by( |a, b| b.size.cmp( &a.size ).then_with( || a.name.cmp( &b.name ) ) );
+
+    definitions.sort_by( |&a, &b| sizes[ b ].cmp( &sizes[ a ] ).then_with( || a.cmp( &b ) ) );
+    let functions = definitions.into_iter().take( top ).map( |node| {
+        let mut retained_by = Vec::new();
+        let mut current = idom[ node ];
+        while let Some( dominator ) = current {
+            retained_by.push( names[ dominator ].clone() );
+            if dominator == ROOT {
+                break;
+            }
+            current = idom[ dominator ];
+        }
+
+        FunctionSize {
+            index: (node - 2) as u32,
+            name: names[ node ].clone(),
+            size: sizes[ node ],
+            retained_size: retained_sizes[ node ],
+            retained_by
+        }
+    }).collect();
+
+    let mut data_segments: Vec< _ > = ctx.data.iter().map( |data| {
+        DataSegmentSize {
+            offset: data.constant_offset(),
+            size: data.value.len() as u64
+        }
+    }).collect();
+    data_segments.sort_by( |a, b| b.size.cmp( &a.size ) );
+    let data_size = data_segments.iter().map( |segment| segment.size ).sum();
+
+    Ok( SizeReport {
+        total_size: bytes.len() as u64,
+        code_size,
+        data_size,
+        unreachable_size,
+        functions,
+        crates,
+        data_segments
+    })
+}
+
+impl SizeReport {
+    fn percent( &self, size: u64 ) -> f64 {
+        if self.total_size == 0 {
+            0.0
+        } else {
+            size as f64 * 100.0 / self.total_size as f64
+        }
+    }
+
+    pub fn print( &self ) {
+        println!( "Total size: {} bytes", self.total_size );
+        println!( "    code: {} bytes ({:.1}%)", self.code_size, self.percent( self.code_size ) );
+        println!( "    data: {} bytes ({:.1}%)", self.data_size, self.percent( self.data_size ) );
+        if self.unreachable_size > 0 {
+            println!( "    unreachable code: {} bytes ({:.1}%)", self.unreachable_size, self.percent( self.unreachable_size ) );
+        }
+
+        println!();
+        println!( "Largest functions:" );
+        println!( "{:>10} {:>7} {:>10}  {}", "Size", "%", "Retained", "Name" );
+        for function in &self.functions {
+            println!( "{:>10} {:>6.1}% {:>10}  {}", function.size, self.percent( function.size ), function.retained_size, function.name );
+
+            let mut chain: Vec< &str > = function.retained_by.iter().take( 4 ).map( |name| name.as_str() ).collect();
+            if function.retained_by.len() > chain.len() {
+                chain.push( "..." );
+            }
+
+            if chain.is_empty() {
+                println!( "{:>30}  retained by: nothing (unreachable)", "" );
+            } else {
+                println!( "{:>30}  retained by: {}", "", chain.join( " <- " ) );
+            }
+        }
+
+        println!();
+        println!( "Crates:" );
+        println!( "{:>10} {:>7} {:>10}  {}", "Size", "%", "Functions", "Name" );
+        for entry in &self.crates {
+            println!( "{:>10} {:>6.1}% {:>10}  {}", entry.size, self.percent( entry.size ), entry.functions, entry.name );
+        }
+
+        if !self.data_segments.is_empty() {
+            println!();
+            println!( "Data segments:" );
+            println!( "{:>10} {:>7} {:>10}", "Size", "%", "Offset" );
+            for segment in &self.data_segments {
+                let offset = segment.offset.map( |offset| format!( "0x{:x}", offset ) ).unwrap_or_else( || "?".to_owned() );
+                println!( "{:>10} {:>6.1}% {:>10}", segment.size, self.percent( segment.size ), offset );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_crate_name() {
+    assert_eq!( crate_name( "core::fmt::write" ), "core" );
+    assert_eq!( crate_name( "<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop" ), "alloc" );
+    assert_eq!( crate_name( "<&mut std::io::Stdout as std::io::Write>::flush" ), "std" );
+    assert_eq!( crate_name( "memcpy" ), "(unknown)" );
+}
+
+#[test]
+fn test_compute_dominators() {
+    // root -> 2, root -> 3, 2 -> 4, 3 -> 4, 4 -> 5; the table (1) is unreachable
+    let edges = vec![
+        vec![ 2, 3 ],
+        vec![],
+        vec![ 4 ],
+        vec![ 4 ],
+        vec![ 5 ],
+        vec![]
+    ];
+
+    let (idom, postorder) = compute_dominators( &edges );
+    assert_eq!( idom, vec![ Some( 0 ), None, Some( 0 ), Some( 0 ), Some( 0 ), Some( 4 ) ] );
+    assert_eq!( postorder.len(), 5 );
+}
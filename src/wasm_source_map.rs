@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use serde_json;
+
+use utils::base64_digit;
+use wasm_dwarf;
+use wasm_sections::find_custom_section;
+
+fn encode_vlq( output: &mut String, value: i64 ) {
+    let mut value = if value < 0 { ((-value) << 1) | 1 } else { value << 1 };
+    loop {
+        let mut digit = value & 31;
+        value >>= 5;
+        if value > 0 {
+            digit |= 32;
+        }
+
+        output.push( base64_digit( digit as u32 ) );
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+/// Generates a source map for the given `.wasm` file from its DWARF line info.
+///
+/// Returns `None` if the module doesn't have any line info.
+pub fn generate( bytes: &[u8] ) -> Result< Option< String >, String > {
+    let debug_line = match find_custom_section( bytes, ".debug_line" ).map_err( |error| format!( "{:?}", error ) )? {
+        Some( debug_line ) => debug_line,
+        None => return Ok( None )
+    };
+
+    let code_offset = match wasm_dwarf::code_section_offset( bytes ).map_err( |error| format!( "{:?}", error ) )? {
+        Some( code_offset ) => code_offset,
+        None => return Ok( None )
+    };
+
+    let mut entries = wasm_dwarf::read_line_entries( debug_line )?;
+    if entries.is_empty() {
+        return Ok( None );
+    }
+
+    entries.sort_by_key( |entry| entry.address );
+
+    let mut sources = Vec::new();
+    let mut source_indexes = HashMap::new();
+    let mut mappings = String::new();
+    let mut last_address = None;
+    let mut last_column = 0;
+    let mut last_source = 0;
+    let mut last_line = 0;
+    let mut last_source_column = 0;
+    for entry in entries {
+        // If there are multiple entries for the same address then the first one wins.
+        if last_address == Some( entry.address ) {
+            continue;
+        }
+        last_address = Some( entry.address );
+
+        let source = match source_indexes.get( &entry.path ).cloned() {
+            Some( source ) => source,
+            None => {
+                let source = sources.len() as i64;
+                source_indexes.insert( entry.path.clone(), source );
+                sources.push( entry.path );
+                source
+            }
+        };
+
+        // In source maps for WebAssembly the generated column is the offset within the whole file.
+        let column = (code_offset + entry.address) as i64;
+        let line = entry.line.saturating_sub( 1 ) as i64;
+        let source_column = entry.column.saturating_sub( 1 ) as i64;
+
+        if !mappings.is_empty() {
+            mappings.push( ',' );
+        }
+
+        encode_vlq( &mut mappings, column - last_column );
+        encode_vlq( &mut mappings, source - last_source );
+        encode_vlq( &mut mappings, line - last_line );
+        encode_vlq( &mut mappings, source_column - last_source_column );
+
+        last_column = column;
+        last_source = source;
+        last_line = line;
+        last_source_column = source_column;
+    }
+
+    let source_map = json!({
+        "version": 3,
+        "sources": sources,
+        "names": [],
+        "mappings": mappings
+    });
+
+    Ok( Some( serde_json::to_string( &source_map ).unwrap() ) )
+}
+
+#[test]
+fn test_encode_vlq() {
+    let mut output = String::new();
+    for &value in &[ 0, 1, -1, 16, 123456 ] {
+        encode_vlq( &mut output, value );
+        output.push( ',' );
+    }
+
+    assert_eq!( output, "A,C,D,gB,gkxH," );
+}
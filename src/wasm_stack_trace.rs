@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use regex::Regex;
+use rustc_demangle;
+
+use utils::read_bytes;
+use wasm_bulk_memory;
+use wasm_context::{Context, FunctionKind};
+
+lazy_static! {
+    // Matches V8's stack frames of WebAssembly functions, e.g.:
+    //    at wasm-function[1234]:0x5ab3
+    //    at _ZN4core9panicking5panic17h0123456789abcdefE (http://localhost:8000/app.wasm:wasm-function[12]:0x1234)
+    static ref WASM_FRAME_REGEX: Regex = Regex::new( r"^(\s*at )(?:(\S+) \()?(\S*wasm-function\[(\d+)\][^\s)]*)\)?\s*$" ).unwrap();
+    static ref WASM_FUNCTION_NAME_REGEX: Regex = Regex::new( r"^\$func(\d+)$" ).unwrap();
+}
+
+fn demangle( name: &str ) -> String {
+    format!( "{:#}", rustc_demangle::demangle( name ) )
+}
+
+/// Resolves function indexes in stack traces using the module's name section.
+pub struct Symbolicator {
+    names: HashMap< u32, String >
+}
+
+impl Symbolicator {
+    pub fn from_file( path: &Path ) -> Option< Self > {
+        let bytes = match read_bytes( path ) {
+            Ok( bytes ) => bytes,
+            Err( error ) => {
+                debug!( "Cannot load {:?} for symbolication: {}", path, error );
+                return None;
+            }
+        };
+
+        let module = match wasm_bulk_memory::deserialize( &bytes ) {
+            Ok( module ) => module,
+            Err( error ) => {
+                debug!( "Cannot load {:?} for symbolication: {:?}", path, error );
+                return None;
+            }
+        };
+
+        let ctx = match Context::from_module( module ) {
+            Ok( ctx ) => ctx,
+            Err( error ) => {
+                debug!( "Cannot load {:?} for symbolication: {}", path, error );
+                return None;
+            }
+        };
+        let names: HashMap< _, _ > = ctx.functions.iter().filter_map( |(&function_index, function)| {
+            let name = match function {
+                &FunctionKind::Import { ref name, .. } |
+                &FunctionKind::Definition { ref name, .. } => name.as_ref()?
+            };
+
+            Some( (function_index, demangle( name )) )
+        }).collect();
+
+        if names.is_empty() {
+            return None;
+        }
+
+        Some( Symbolicator {
+            names
+        })
+    }
+
+    /// Turns a function name as reported by the browser into a readable one.
+    pub fn function_name( &self, name: &str ) -> String {
+        if let Some( captures ) = WASM_FUNCTION_NAME_REGEX.captures( name ) {
+            let index: u32 = captures[ 1 ].parse().unwrap();
+            if let Some( name ) = self.names.get( &index ) {
+                return name.clone();
+            }
+        }
+
+        demangle( name )
+    }
+
+    fn symbolicate_line( &self, line: &str ) -> String {
+        let captures = match WASM_FRAME_REGEX.captures( line ) {
+            Some( captures ) => captures,
+            None => return line.to_owned()
+        };
+
+        let name = match captures[ 4 ].parse::< u32 >().ok().and_then( |index| self.names.get( &index ) ) {
+            Some( name ) => name.clone(),
+            None => match captures.get( 2 ) {
+                Some( name ) => self.function_name( name.as_str() ),
+                None => return line.to_owned()
+            }
+        };
+
+        format!( "{}{} ({})", &captures[ 1 ], name, &captures[ 3 ] )
+    }
+
+    /// Replaces the WebAssembly frames in a stack trace with demangled function names.
+    pub fn symbolicate( &self, text: &str ) -> String {
+        let lines: Vec< _ > = text.lines().map( |line| self.symbolicate_line( line ) ).collect();
+        lines.join( "\n" )
+    }
+}
+
+#[test]
+fn test_symbolicate() {
+    let mut names = HashMap::new();
+    names.insert( 12, "core::panicking::panic".to_owned() );
+    let symbolicator = Symbolicator { names };
+
+    let trace = "RuntimeError: unreachable\n    at wasm-function[12]:0x1234\n    at _ZN3foo3bar17h0123456789abcdefE (http://localhost/app.wasm:wasm-function[7]:0x99)\n    at http://localhost/app.js:10:5";
+    assert_eq!(
+        symbolicator.symbolicate( trace ),
+        "RuntimeError: unreachable\n    at core::panicking::panic (wasm-function[12]:0x1234)\n    at foo::bar (http://localhost/app.wasm:wasm-function[7]:0x99)\n    at http://localhost/app.js:10:5"
+    );
+
+    assert_eq!( symbolicator.function_name( "$func12" ), "core::panicking::panic" );
+}
@@ -0,0 +1,381 @@
+use std::path::{Path, PathBuf};
+
+use wasm_context::{
+    Context,
+    Export,
+    FnTy,
+    FunctionIndex,
+    FunctionKind,
+    GlobalIndex,
+    GlobalKind,
+    Import,
+    ImportExport,
+    Instruction,
+    MemoryKind,
+    ValueType
+};
+use wasm_error::{ProcessingError, Stage};
+
+pub const DEFAULT_STACK_SIZE: u64 = 1024 * 1024;
+
+/// The shared memory which the main thread and every worker have to import.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SharedMemory {
+    pub initial: u32,
+    pub maximum: u32,
+    /// The size and the alignment of the block for the thread-local
+    /// storage which every worker has to allocate; zero if the module has none.
+    pub tls_size: u32,
+    pub tls_align: u32
+}
+
+/// Returns the path of the module which gets instantiated in the workers.
+pub fn worker_path( path: &Path ) -> PathBuf {
+    path.with_extension( "worker.wasm" )
+}
+
+pub fn is_worker_module( path: &Path ) -> bool {
+    path.file_name()
+        .map( |name| name.to_string_lossy().ends_with( ".worker.wasm" ) )
+        .unwrap_or( false )
+}
+
+fn threads_error< S: Into< String > >( message: S ) -> ProcessingError {
+    ProcessingError::new( Stage::Threads, message )
+}
+
+fn is_named< T: ImportExport >( entity: &T, entity_name: Option< &str >, name: &str ) -> bool {
+    entity_name == Some( name ) || entity.as_export().names.iter().any( |export| export == name )
+}
+
+fn find_global( ctx: &Context, name: &str ) -> Option< GlobalIndex > {
+    ctx.globals.iter()
+        .find( |&(_, global)| is_named( global, global.name(), name ) )
+        .map( |(&global_index, _)| global_index )
+}
+
+fn find_function( ctx: &Context, name: &str ) -> Option< FunctionIndex > {
+    ctx.functions.iter()
+        .find( |&(_, function)| is_named( function, function.name(), name ) )
+        .map( |(&function_index, _)| function_index )
+}
+
+fn mutable_i32_global( ctx: &Context, name: &str ) -> Result< Option< GlobalIndex >, ProcessingError > {
+    let global_index = match find_global( ctx, name ) {
+        Some( global_index ) => global_index,
+        None => return Ok( None )
+    };
+
+    let global_type = ctx.globals[ &global_index ].global_type();
+    if !global_type.is_mutable || global_type.ty != ValueType::I32 {
+        return Err( threads_error( format!( "`{}` is not a mutable `i32` global", name ) ) );
+    }
+
+    Ok( Some( global_index ) )
+}
+
+fn constant_i32_global( ctx: &Context, name: &str ) -> Result< Option< u32 >, ProcessingError > {
+    let global_index = match find_global( ctx, name ) {
+        Some( global_index ) => global_index,
+        None => return Ok( None )
+    };
+
+    match ctx.globals[ &global_index ] {
+        GlobalKind::Definition { ref initializer, .. } => {
+            match initializer.as_slice() {
+                &[ Instruction::I32Const( value ), Instruction::End ] if value >= 0 => Ok( Some( value as u32 ) ),
+                _ => Err( threads_error( format!( "`{}` is not initialized with a constant", name ) ) )
+            }
+        },
+        GlobalKind::Import { .. } => Err( threads_error( format!( "`{}` is imported", name ) ) )
+    }
+}
+
+/// Prepares a module which was compiled with `+atomics` to be instantiated on multiple threads.
+///
+/// The shared memory is turned into an `env.memory` import so that the runtime
+/// can create it and hand the same memory to every worker, and an export
+/// is added through which each worker sets up its own stack and thread-local storage.
+///
+/// The `__stack_pointer`, `__tls_base` and `__wasm_init_tls` are looked up
+/// either by their exports or by their names from the name section.
+pub fn process( ctx: &mut Context ) -> Result< Option< SharedMemory >, ProcessingError > {
+    let (initial, maximum) = {
+        let memory = ctx.memories.values_mut().find( |memory| {
+            match **memory {
+                MemoryKind::Import { ref limits, .. } |
+                MemoryKind::Definition { ref limits, .. } => limits.shared
+            }
+        });
+
+        let memory = match memory {
+            Some( memory ) => memory,
+            None => return Ok( None )
+        };
+
+        let (export, limits) = match *memory {
+            MemoryKind::Import { ref export, ref limits, .. } |
+            MemoryKind::Definition { ref export, ref limits } => (export.clone(), limits.clone())
+        };
+
+        let maximum = limits.max.ok_or_else( || threads_error( "the shared memory has no maximum size" ) )?;
+        *memory = MemoryKind::Import {
+            export,
+            limits: limits.clone(),
+            import: Import {
+                module: "env".to_owned(),
+                field: "memory".to_owned()
+            }
+        };
+
+        (limits.min, maximum)
+    };
+
+    let stack_pointer = mutable_i32_global( ctx, "__stack_pointer" )?.ok_or_else( || {
+        threads_error( "cannot find `__stack_pointer`; it has to be either exported or named in the name section" )
+    })?;
+
+    let mut instructions = vec![
+        Instruction::GetLocal( 0 ),
+        Instruction::SetGlobal( stack_pointer )
+    ];
+
+    let (tls_size, tls_align) = if mutable_i32_global( ctx, "__tls_base" )?.is_some() {
+        let init_tls = find_function( ctx, "__wasm_init_tls" ).ok_or_else( || {
+            threads_error( "the module uses thread-local storage, but `__wasm_init_tls` cannot be found; it has to be either exported or named in the name section" )
+        })?;
+
+        let tls_size = constant_i32_global( ctx, "__tls_size" )?.ok_or_else( || {
+            threads_error( "the module uses thread-local storage, but `__tls_size` cannot be found; it has to be either exported or named in the name section" )
+        })?;
+
+        let tls_align = constant_i32_global( ctx, "__tls_align" )?.unwrap_or( 16 );
+        if !tls_align.is_power_of_two() {
+            return Err( threads_error( format!( "`__tls_align` is not a power of two: {}", tls_align ) ) );
+        }
+
+        // This sets the `__tls_base` and copies the initial values of the thread-locals.
+        instructions.push( Instruction::GetLocal( 1 ) );
+        instructions.push( Instruction::Call( init_tls ) );
+        (tls_size, tls_align)
+    } else {
+        (0, 1)
+    };
+
+    instructions.push( Instruction::End );
+
+    let type_index = ctx.get_or_add_fn_type( FnTy { params: vec![ ValueType::I32, ValueType::I32 ], return_type: None } );
+    ctx.add_function( FunctionKind::Definition {
+        type_index,
+        export: Export::some( "__web_initialize_thread".to_owned() ),
+        name: Some( "__web_initialize_thread".to_owned() ),
+        locals: Vec::new(),
+        instructions
+    });
+
+    Ok( Some( SharedMemory {
+        initial,
+        maximum,
+        tls_size,
+        tls_align
+    }))
+}
+
+/// Creates the module which gets instantiated in the workers.
+///
+/// It's the same module except without the start function and the active
+/// data segments since those would overwrite whatever the main thread
+/// already has in the memory. Modules compiled with `+bulk-memory`
+/// initialize the memory through `memory.init` in their start function
+/// instead, and only the main thread is supposed to run it.
+///
+/// The passive data segments are kept since `__wasm_init_tls` copies the
+/// initial values of the thread-locals from them, and the active ones are
+/// emptied instead of removed to keep the indexes of the passive ones intact.
+///
+/// The function table can't be shared between the threads, but since
+/// every instance initializes it from the same element segment
+/// the function pointers are the same in every one of them.
+pub fn worker_context( ctx: &Context ) -> Context {
+    let mut ctx = ctx.clone();
+    if ctx.data.iter().all( |data| data.offset.is_some() ) {
+        ctx.data.clear();
+    } else {
+        for data in &mut ctx.data {
+            if data.offset.is_some() {
+                data.offset = None;
+                data.value.clear();
+            }
+        }
+    }
+
+    ctx.start = None;
+    ctx.source_mapping_url = None;
+    ctx.debug_sections.clear();
+    ctx
+}
+
+#[cfg(test)]
+fn shared_memory_context() -> Context {
+    use wasm_context::{GlobalType, Limits};
+
+    let mut ctx = Context::new();
+    ctx.memories.insert( 0, MemoryKind::Definition {
+        export: Export::some( "memory".to_owned() ),
+        limits: Limits { min: 17, max: Some( 16384 ), shared: true }
+    });
+    // Not the stack pointer even though it's the first mutable `i32` global.
+    ctx.globals.insert( 0, GlobalKind::Definition {
+        export: Export::none(),
+        name: Some( "GUARD".to_owned() ),
+        global_type: GlobalType { ty: ValueType::I32, is_mutable: true },
+        initializer: vec![ Instruction::I32Const( 0 ), Instruction::End ]
+    });
+    ctx.globals.insert( 1, GlobalKind::Definition {
+        export: Export::none(),
+        name: Some( "__stack_pointer".to_owned() ),
+        global_type: GlobalType { ty: ValueType::I32, is_mutable: true },
+        initializer: vec![ Instruction::I32Const( 1048576 ), Instruction::End ]
+    });
+    ctx
+}
+
+#[test]
+fn test_process_shared_memory() {
+    let mut ctx = shared_memory_context();
+    let shared_memory = process( &mut ctx ).unwrap();
+    assert_eq!( shared_memory, Some( SharedMemory { initial: 17, maximum: 16384, tls_size: 0, tls_align: 1 } ) );
+    match ctx.memories[ &0 ] {
+        MemoryKind::Import { ref import, .. } => {
+            assert_eq!( import.module, "env" );
+            assert_eq!( import.field, "memory" );
+        },
+        _ => panic!()
+    }
+
+    let function = ctx.functions.values().find( |function| function.as_export().names == [ "__web_initialize_thread" ] ).unwrap();
+    match *function {
+        FunctionKind::Definition { ref instructions, .. } => {
+            assert_eq!( instructions, &vec![ Instruction::GetLocal( 0 ), Instruction::SetGlobal( 1 ), Instruction::End ] );
+        },
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_process_thread_local_storage() {
+    use wasm_context::GlobalType;
+
+    let mut ctx = shared_memory_context();
+    ctx.globals.insert( 2, GlobalKind::Definition {
+        export: Export::some( "__tls_base".to_owned() ),
+        name: None,
+        global_type: GlobalType { ty: ValueType::I32, is_mutable: true },
+        initializer: vec![ Instruction::I32Const( 0 ), Instruction::End ]
+    });
+    ctx.globals.insert( 3, GlobalKind::Definition {
+        export: Export::none(),
+        name: Some( "__tls_size".to_owned() ),
+        global_type: GlobalType { ty: ValueType::I32, is_mutable: false },
+        initializer: vec![ Instruction::I32Const( 24 ), Instruction::End ]
+    });
+
+    // Without `__wasm_init_tls` the thread-locals can't be initialized.
+    assert!( process( &mut ctx.clone() ).is_err() );
+
+    let type_index = ctx.get_or_add_fn_type( FnTy { params: vec![ ValueType::I32 ], return_type: None } );
+    let init_tls = ctx.add_function( FunctionKind::Definition {
+        type_index,
+        export: Export::none(),
+        name: Some( "__wasm_init_tls".to_owned() ),
+        locals: Vec::new(),
+        instructions: vec![ Instruction::End ]
+    });
+
+    let shared_memory = process( &mut ctx ).unwrap().unwrap();
+    assert_eq!( (shared_memory.tls_size, shared_memory.tls_align), (24, 16) );
+
+    let function = ctx.functions.values().find( |function| function.as_export().names == [ "__web_initialize_thread" ] ).unwrap();
+    match *function {
+        FunctionKind::Definition { ref instructions, .. } => {
+            assert_eq!( instructions, &vec![
+                Instruction::GetLocal( 0 ),
+                Instruction::SetGlobal( 1 ),
+                Instruction::GetLocal( 1 ),
+                Instruction::Call( init_tls ),
+                Instruction::End
+            ]);
+        },
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_process_without_stack_pointer() {
+    let mut ctx = shared_memory_context();
+    ctx.globals.remove( &1 );
+    assert!( process( &mut ctx ).is_err() );
+}
+
+#[test]
+fn test_worker_context() {
+    use wasm_context::Data;
+
+    let mut ctx = shared_memory_context();
+    ctx.data.push( Data {
+        offset: Some( vec![ Instruction::I32Const( 1024 ), Instruction::End ] ),
+        value: b"hello".to_vec()
+    });
+
+    process( &mut ctx ).unwrap();
+    assert!( worker_context( &ctx ).data.is_empty() );
+
+    ctx.data.push( Data {
+        offset: None,
+        value: b"world".to_vec()
+    });
+
+    // The active segment can't be removed since `memory.init` refers to the passive one by its index.
+    assert_eq!( worker_context( &ctx ).data, vec![
+        Data { offset: None, value: Vec::new() },
+        Data { offset: None, value: b"world".to_vec() }
+    ]);
+}
+
+#[test]
+fn test_worker_context_with_passive_segments() {
+    use wasm_bulk_memory::{self, THREADED_MODULE};
+    use wasm_validate::validate;
+
+    let mut ctx = Context::from_bytes( THREADED_MODULE ).unwrap();
+    let shared_memory = process( &mut ctx ).unwrap().unwrap();
+    assert_eq!( (shared_memory.initial, shared_memory.maximum, shared_memory.tls_size), (2, 16384, 4) );
+
+    // Only the main thread initializes the memory, but every worker initializes its own thread-locals.
+    let worker_ctx = worker_context( &ctx );
+    assert!( ctx.start.is_some() );
+    assert_eq!( worker_ctx.start, None );
+    assert_eq!( worker_ctx.data, ctx.data );
+
+    for ctx in vec![ ctx, worker_ctx ] {
+        let module = ctx.into_module();
+        validate( &module ).unwrap();
+
+        let bytes = wasm_bulk_memory::serialize( module ).unwrap();
+        assert!( Context::from_bytes( &bytes ).is_ok() );
+    }
+}
+
+#[test]
+fn test_process_unshared_memory() {
+    use wasm_context::Limits;
+
+    let mut ctx = Context::new();
+    ctx.memories.insert( 0, MemoryKind::Definition {
+        export: Export::some( "memory".to_owned() ),
+        limits: Limits { min: 17, max: None, shared: false }
+    });
+
+    assert_eq!( process( &mut ctx ).unwrap(), None );
+    assert!( ctx.functions.is_empty() );
+}
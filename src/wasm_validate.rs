@@ -0,0 +1,969 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use parity_wasm::elements as pw;
+use parity_wasm::elements::{BlockType, Deserialize, Instruction, MemArg, ValueType};
+
+use wasm_sections;
+
+#[derive(Debug)]
+pub struct ValidationError {
+    pub function: Option< (u32, Option< String >) >,
+    pub message: String
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        match self.function {
+            Some( (index, Some( ref name )) ) => write!( formatter, "function #{} (`{}`): {}", index, name, self.message ),
+            Some( (index, None) ) => write!( formatter, "function #{}: {}", index, self.message ),
+            None => write!( formatter, "{}", self.message )
+        }
+    }
+}
+
+impl From< String > for ValidationError {
+    fn from( message: String ) -> Self {
+        ValidationError {
+            function: None,
+            message
+        }
+    }
+}
+
+struct FnType {
+    params: Vec< ValueType >,
+    return_type: Option< ValueType >
+}
+
+struct ModuleInfo {
+    types: Vec< FnType >,
+    functions: Vec< u32 >,
+    table_count: usize,
+    memory_count: usize,
+    globals: Vec< (ValueType, bool) >,
+    imported_global_count: usize,
+    element_segment_count: usize,
+    data_segment_count: usize
+}
+
+impl ModuleInfo {
+    fn fn_type( &self, type_index: u32 ) -> Result< &FnType, String > {
+        self.types.get( type_index as usize ).ok_or_else( || format!( "type index out of bounds: {}", type_index ) )
+    }
+
+    fn function_type( &self, function_index: u32 ) -> Result< &FnType, String > {
+        let type_index = *self.functions.get( function_index as usize ).ok_or_else( || format!( "function index out of bounds: {}", function_index ) )?;
+        self.fn_type( type_index )
+    }
+
+    fn global( &self, global_index: u32 ) -> Result< (ValueType, bool), String > {
+        self.globals.get( global_index as usize ).cloned().ok_or_else( || format!( "global index out of bounds: {}", global_index ) )
+    }
+
+    fn require_memory( &self ) -> Result< (), String > {
+        if self.memory_count == 0 {
+            return Err( "memory instruction used without a memory".to_owned() );
+        }
+
+        Ok(())
+    }
+
+    fn require_table( &self ) -> Result< (), String > {
+        if self.table_count == 0 {
+            return Err( "table instruction used without a table".to_owned() );
+        }
+
+        Ok(())
+    }
+
+    fn data_segment( &self, segment_index: u32 ) -> Result< (), String > {
+        self.require_memory()?;
+        if segment_index as usize >= self.data_segment_count {
+            return Err( format!( "data segment index out of bounds: {}", segment_index ) );
+        }
+
+        Ok(())
+    }
+
+    fn element_segment( &self, segment_index: u32 ) -> Result< (), String > {
+        self.require_table()?;
+        if segment_index as usize >= self.element_segment_count {
+            return Err( format!( "element segment index out of bounds: {}", segment_index ) );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Operand {
+    Known( ValueType ),
+    Unknown
+}
+
+struct Frame {
+    is_loop: bool,
+    is_if: bool,
+    result: Option< ValueType >,
+    height: usize,
+    unreachable: bool
+}
+
+struct FunctionValidator< 'a > {
+    module: &'a ModuleInfo,
+    locals: Vec< ValueType >,
+    return_type: Option< ValueType >,
+    operands: Vec< Operand >,
+    frames: Vec< Frame >
+}
+
+impl< 'a > FunctionValidator< 'a > {
+    fn push( &mut self, ty: ValueType ) {
+        self.operands.push( Operand::Known( ty ) );
+    }
+
+    fn pop( &mut self ) -> Result< Operand, String > {
+        let (height, unreachable) = {
+            let frame = self.frames.last().unwrap();
+            (frame.height, frame.unreachable)
+        };
+
+        if self.operands.len() == height {
+            if unreachable {
+                return Ok( Operand::Unknown );
+            }
+
+            return Err( "not enough operands on the stack".to_owned() );
+        }
+
+        Ok( self.operands.pop().unwrap() )
+    }
+
+    fn pop_expecting( &mut self, expected: ValueType ) -> Result< (), String > {
+        match self.pop()? {
+            Operand::Known( actual ) if actual != expected => Err( format!( "type mismatch: expected {:?}, found {:?}", expected, actual ) ),
+            _ => Ok(())
+        }
+    }
+
+    fn unary( &mut self, input: ValueType, output: ValueType ) -> Result< (), String > {
+        self.pop_expecting( input )?;
+        self.push( output );
+        Ok(())
+    }
+
+    fn binary( &mut self, input: ValueType, output: ValueType ) -> Result< (), String > {
+        self.pop_expecting( input )?;
+        self.pop_expecting( input )?;
+        self.push( output );
+        Ok(())
+    }
+
+    fn load( &mut self, output: ValueType, align: u32, natural_align: u32 ) -> Result< (), String > {
+        self.module.require_memory()?;
+        if align > natural_align {
+            return Err( format!( "alignment of 2^{} is larger than the natural alignment of 2^{}", align, natural_align ) );
+        }
+
+        self.unary( ValueType::I32, output )
+    }
+
+    fn store( &mut self, input: ValueType, align: u32, natural_align: u32 ) -> Result< (), String > {
+        self.module.require_memory()?;
+        if align > natural_align {
+            return Err( format!( "alignment of 2^{} is larger than the natural alignment of 2^{}", align, natural_align ) );
+        }
+
+        self.pop_expecting( input )?;
+        self.pop_expecting( ValueType::I32 )
+    }
+
+    fn atomic( &mut self, memarg: &MemArg, natural_align: u8, inputs: &[ValueType], output: Option< ValueType > ) -> Result< (), String > {
+        self.module.require_memory()?;
+        if memarg.align != natural_align {
+            return Err( format!( "atomic accesses must be naturally aligned; expected an alignment of 2^{}, found 2^{}", natural_align, memarg.align ) );
+        }
+
+        for &input in inputs.iter().rev() {
+            self.pop_expecting( input )?;
+        }
+
+        if let Some( output ) = output {
+            self.push( output );
+        }
+
+        Ok(())
+    }
+
+    fn call( &mut self, ty: &FnType ) -> Result< (), String > {
+        for &param in ty.params.iter().rev() {
+            self.pop_expecting( param )?;
+        }
+
+        if let Some( return_type ) = ty.return_type {
+            self.push( return_type );
+        }
+
+        Ok(())
+    }
+
+    fn local( &self, local_index: u32 ) -> Result< ValueType, String > {
+        self.locals.get( local_index as usize ).cloned().ok_or_else( || format!( "local index out of bounds: {}", local_index ) )
+    }
+
+    fn label_type( &self, depth: u32 ) -> Result< Option< ValueType >, String > {
+        let index = self.frames.len().checked_sub( depth as usize + 1 ).ok_or_else( || format!( "branch depth out of bounds: {}", depth ) )?;
+        let frame = &self.frames[ index ];
+        Ok( if frame.is_loop { None } else { frame.result } )
+    }
+
+    fn set_unreachable( &mut self ) {
+        let frame = self.frames.last_mut().unwrap();
+        self.operands.truncate( frame.height );
+        frame.unreachable = true;
+    }
+
+    fn push_frame( &mut self, block_type: BlockType, is_loop: bool, is_if: bool ) {
+        let result = match block_type {
+            BlockType::Value( ty ) => Some( ty ),
+            BlockType::NoResult => None
+        };
+
+        self.frames.push( Frame {
+            is_loop,
+            is_if,
+            result,
+            height: self.operands.len(),
+            unreachable: false
+        });
+    }
+
+    fn pop_frame( &mut self ) -> Result< Frame, String > {
+        let (result, height) = {
+            let frame = self.frames.last().unwrap();
+            (frame.result, frame.height)
+        };
+
+        if let Some( ty ) = result {
+            self.pop_expecting( ty )?;
+        }
+
+        if self.operands.len() != height {
+            return Err( "unexpected values left on the stack at the end of a block".to_owned() );
+        }
+
+        Ok( self.frames.pop().unwrap() )
+    }
+
+    #[allow(unreachable_patterns)]
+    fn step( &mut self, instruction: &Instruction ) -> Result< (), String > {
+        use parity_wasm::elements::ValueType::{I32, I64, F32, F64};
+
+        match *instruction {
+            Instruction::Unreachable => self.set_unreachable(),
+            Instruction::Nop => {},
+            Instruction::Block( block_type ) => self.push_frame( block_type, false, false ),
+            Instruction::Loop( block_type ) => self.push_frame( block_type, true, false ),
+            Instruction::If( block_type ) => {
+                self.pop_expecting( I32 )?;
+                self.push_frame( block_type, false, true );
+            },
+            Instruction::Else => {
+                if !self.frames.last().unwrap().is_if {
+                    return Err( "`else` outside of an `if`".to_owned() );
+                }
+
+                let frame = self.pop_frame()?;
+                let block_type = frame.result.map( BlockType::Value ).unwrap_or( BlockType::NoResult );
+                self.push_frame( block_type, false, false );
+            },
+            Instruction::End => {
+                let frame = self.pop_frame()?;
+                if frame.is_if && frame.result.is_some() {
+                    return Err( "an `if` without an `else` can't return a value".to_owned() );
+                }
+
+                if !self.frames.is_empty() {
+                    if let Some( ty ) = frame.result {
+                        self.push( ty );
+                    }
+                }
+            },
+            Instruction::Br( depth ) => {
+                if let Some( ty ) = self.label_type( depth )? {
+                    self.pop_expecting( ty )?;
+                }
+                self.set_unreachable();
+            },
+            Instruction::BrIf( depth ) => {
+                self.pop_expecting( I32 )?;
+                if let Some( ty ) = self.label_type( depth )? {
+                    self.pop_expecting( ty )?;
+                    self.push( ty );
+                }
+            },
+            Instruction::BrTable( ref data ) => {
+                self.pop_expecting( I32 )?;
+                let ty = self.label_type( data.default )?;
+                for &depth in data.table.iter() {
+                    if self.label_type( depth )? != ty {
+                        return Err( "all targets of a `br_table` must have the same type".to_owned() );
+                    }
+                }
+
+                if let Some( ty ) = ty {
+                    self.pop_expecting( ty )?;
+                }
+                self.set_unreachable();
+            },
+            Instruction::Return => {
+                if let Some( ty ) = self.return_type {
+                    self.pop_expecting( ty )?;
+                }
+                self.set_unreachable();
+            },
+            Instruction::Call( function_index ) => {
+                let module = self.module;
+                self.call( module.function_type( function_index )? )?;
+            },
+            Instruction::CallIndirect( type_index, _ ) => {
+                let module = self.module;
+                if module.table_count == 0 {
+                    return Err( "`call_indirect` used without a table".to_owned() );
+                }
+
+                self.pop_expecting( I32 )?;
+                self.call( module.fn_type( type_index )? )?;
+            },
+            Instruction::Drop => {
+                self.pop()?;
+            },
+            Instruction::Select => {
+                self.pop_expecting( I32 )?;
+                let a = self.pop()?;
+                let b = self.pop()?;
+                match (a, b) {
+                    (Operand::Known( a ), Operand::Known( b )) if a != b => {
+                        return Err( format!( "type mismatch: `select` operands are {:?} and {:?}", b, a ) );
+                    },
+                    (Operand::Known( ty ), _) | (_, Operand::Known( ty )) => self.push( ty ),
+                    _ => self.operands.push( Operand::Unknown )
+                }
+            },
+            Instruction::GetLocal( local_index ) => {
+                let ty = self.local( local_index )?;
+                self.push( ty );
+            },
+            Instruction::SetLocal( local_index ) => {
+                let ty = self.local( local_index )?;
+                self.pop_expecting( ty )?;
+            },
+            Instruction::TeeLocal( local_index ) => {
+                let ty = self.local( local_index )?;
+                self.unary( ty, ty )?;
+            },
+            Instruction::GetGlobal( global_index ) => {
+                let (ty, _) = self.module.global( global_index )?;
+                self.push( ty );
+            },
+            Instruction::SetGlobal( global_index ) => {
+                let (ty, is_mutable) = self.module.global( global_index )?;
+                if !is_mutable {
+                    return Err( format!( "global #{} is immutable", global_index ) );
+                }
+                self.pop_expecting( ty )?;
+            },
+
+            Instruction::I32Load( align, _ ) => self.load( I32, align, 2 )?,
+            Instruction::I64Load( align, _ ) => self.load( I64, align, 3 )?,
+            Instruction::F32Load( align, _ ) => self.load( F32, align, 2 )?,
+            Instruction::F64Load( align, _ ) => self.load( F64, align, 3 )?,
+            Instruction::I32Load8S( align, _ ) | Instruction::I32Load8U( align, _ ) => self.load( I32, align, 0 )?,
+            Instruction::I32Load16S( align, _ ) | Instruction::I32Load16U( align, _ ) => self.load( I32, align, 1 )?,
+            Instruction::I64Load8S( align, _ ) | Instruction::I64Load8U( align, _ ) => self.load( I64, align, 0 )?,
+            Instruction::I64Load16S( align, _ ) | Instruction::I64Load16U( align, _ ) => self.load( I64, align, 1 )?,
+            Instruction::I64Load32S( align, _ ) | Instruction::I64Load32U( align, _ ) => self.load( I64, align, 2 )?,
+            Instruction::I32Store( align, _ ) => self.store( I32, align, 2 )?,
+            Instruction::I64Store( align, _ ) => self.store( I64, align, 3 )?,
+            Instruction::F32Store( align, _ ) => self.store( F32, align, 2 )?,
+            Instruction::F64Store( align, _ ) => self.store( F64, align, 3 )?,
+            Instruction::I32Store8( align, _ ) => self.store( I32, align, 0 )?,
+            Instruction::I32Store16( align, _ ) => self.store( I32, align, 1 )?,
+            Instruction::I64Store8( align, _ ) => self.store( I64, align, 0 )?,
+            Instruction::I64Store16( align, _ ) => self.store( I64, align, 1 )?,
+            Instruction::I64Store32( align, _ ) => self.store( I64, align, 2 )?,
+            Instruction::CurrentMemory( _ ) => {
+                self.module.require_memory()?;
+                self.push( I32 );
+            },
+            Instruction::GrowMemory( _ ) => {
+                self.module.require_memory()?;
+                self.unary( I32, I32 )?;
+            },
+
+            Instruction::I32Const( _ ) => self.push( I32 ),
+            Instruction::I64Const( _ ) => self.push( I64 ),
+            Instruction::F32Const( _ ) => self.push( F32 ),
+            Instruction::F64Const( _ ) => self.push( F64 ),
+
+            Instruction::I32Eqz => self.unary( I32, I32 )?,
+            Instruction::I64Eqz => self.unary( I64, I32 )?,
+
+            Instruction::I32Eq | Instruction::I32Ne |
+            Instruction::I32LtS | Instruction::I32LtU | Instruction::I32GtS | Instruction::I32GtU |
+            Instruction::I32LeS | Instruction::I32LeU | Instruction::I32GeS | Instruction::I32GeU => self.binary( I32, I32 )?,
+
+            Instruction::I64Eq | Instruction::I64Ne |
+            Instruction::I64LtS | Instruction::I64LtU | Instruction::I64GtS | Instruction::I64GtU |
+            Instruction::I64LeS | Instruction::I64LeU | Instruction::I64GeS | Instruction::I64GeU => self.binary( I64, I32 )?,
+
+            Instruction::F32Eq | Instruction::F32Ne |
+            Instruction::F32Lt | Instruction::F32Gt | Instruction::F32Le | Instruction::F32Ge => self.binary( F32, I32 )?,
+
+            Instruction::F64Eq | Instruction::F64Ne |
+            Instruction::F64Lt | Instruction::F64Gt | Instruction::F64Le | Instruction::F64Ge => self.binary( F64, I32 )?,
+
+            Instruction::I32Clz | Instruction::I32Ctz | Instruction::I32Popcnt => self.unary( I32, I32 )?,
+            Instruction::I64Clz | Instruction::I64Ctz | Instruction::I64Popcnt => self.unary( I64, I64 )?,
+
+            Instruction::I32Add | Instruction::I32Sub | Instruction::I32Mul |
+            Instruction::I32DivS | Instruction::I32DivU | Instruction::I32RemS | Instruction::I32RemU |
+            Instruction::I32And | Instruction::I32Or | Instruction::I32Xor |
+            Instruction::I32Shl | Instruction::I32ShrS | Instruction::I32ShrU |
+            Instruction::I32Rotl | Instruction::I32Rotr => self.binary( I32, I32 )?,
+
+            Instruction::I64Add | Instruction::I64Sub | Instruction::I64Mul |
+            Instruction::I64DivS | Instruction::I64DivU | Instruction::I64RemS | Instruction::I64RemU |
+            Instruction::I64And | Instruction::I64Or | Instruction::I64Xor |
+            Instruction::I64Shl | Instruction::I64ShrS | Instruction::I64ShrU |
+            Instruction::I64Rotl | Instruction::I64Rotr => self.binary( I64, I64 )?,
+
+            Instruction::F32Abs | Instruction::F32Neg | Instruction::F32Ceil | Instruction::F32Floor |
+            Instruction::F32Trunc | Instruction::F32Nearest | Instruction::F32Sqrt => self.unary( F32, F32 )?,
+
+            Instruction::F64Abs | Instruction::F64Neg | Instruction::F64Ceil | Instruction::F64Floor |
+            Instruction::F64Trunc | Instruction::F64Nearest | Instruction::F64Sqrt => self.unary( F64, F64 )?,
+
+            Instruction::F32Add | Instruction::F32Sub | Instruction::F32Mul | Instruction::F32Div |
+            Instruction::F32Min | Instruction::F32Max | Instruction::F32Copysign => self.binary( F32, F32 )?,
+
+            Instruction::F64Add | Instruction::F64Sub | Instruction::F64Mul | Instruction::F64Div |
+            Instruction::F64Min | Instruction::F64Max | Instruction::F64Copysign => self.binary( F64, F64 )?,
+
+            Instruction::I32WrapI64 => self.unary( I64, I32 )?,
+            Instruction::I32TruncSF32 | Instruction::I32TruncUF32 => self.unary( F32, I32 )?,
+            Instruction::I32TruncSF64 | Instruction::I32TruncUF64 => self.unary( F64, I32 )?,
+            Instruction::I64ExtendSI32 | Instruction::I64ExtendUI32 => self.unary( I32, I64 )?,
+            Instruction::I64TruncSF32 | Instruction::I64TruncUF32 => self.unary( F32, I64 )?,
+            Instruction::I64TruncSF64 | Instruction::I64TruncUF64 => self.unary( F64, I64 )?,
+            Instruction::F32ConvertSI32 | Instruction::F32ConvertUI32 => self.unary( I32, F32 )?,
+            Instruction::F32ConvertSI64 | Instruction::F32ConvertUI64 => self.unary( I64, F32 )?,
+            Instruction::F32DemoteF64 => self.unary( F64, F32 )?,
+            Instruction::F64ConvertSI32 | Instruction::F64ConvertUI32 => self.unary( I32, F64 )?,
+            Instruction::F64ConvertSI64 | Instruction::F64ConvertUI64 => self.unary( I64, F64 )?,
+            Instruction::F64PromoteF32 => self.unary( F32, F64 )?,
+            Instruction::I32ReinterpretF32 => self.unary( F32, I32 )?,
+            Instruction::I64ReinterpretF64 => self.unary( F64, I64 )?,
+            Instruction::F32ReinterpretI32 => self.unary( I32, F32 )?,
+            Instruction::F64ReinterpretI64 => self.unary( I64, F64 )?,
+
+            Instruction::I32Extend8S | Instruction::I32Extend16S => self.unary( I32, I32 )?,
+            Instruction::I64Extend8S | Instruction::I64Extend16S | Instruction::I64Extend32S => self.unary( I64, I64 )?,
+
+            Instruction::AtomicWake( ref memarg ) => self.atomic( memarg, 2, &[ I32, I32 ], Some( I32 ) )?,
+            Instruction::I32AtomicWait( ref memarg ) => self.atomic( memarg, 2, &[ I32, I32, I64 ], Some( I32 ) )?,
+            Instruction::I64AtomicWait( ref memarg ) => self.atomic( memarg, 3, &[ I32, I64, I64 ], Some( I32 ) )?,
+
+            Instruction::I32AtomicLoad( ref memarg ) => self.atomic( memarg, 2, &[ I32 ], Some( I32 ) )?,
+            Instruction::I32AtomicLoad8u( ref memarg ) => self.atomic( memarg, 0, &[ I32 ], Some( I32 ) )?,
+            Instruction::I32AtomicLoad16u( ref memarg ) => self.atomic( memarg, 1, &[ I32 ], Some( I32 ) )?,
+            Instruction::I64AtomicLoad( ref memarg ) => self.atomic( memarg, 3, &[ I32 ], Some( I64 ) )?,
+            Instruction::I64AtomicLoad8u( ref memarg ) => self.atomic( memarg, 0, &[ I32 ], Some( I64 ) )?,
+            Instruction::I64AtomicLoad16u( ref memarg ) => self.atomic( memarg, 1, &[ I32 ], Some( I64 ) )?,
+            Instruction::I64AtomicLoad32u( ref memarg ) => self.atomic( memarg, 2, &[ I32 ], Some( I64 ) )?,
+
+            Instruction::I32AtomicStore( ref memarg ) => self.atomic( memarg, 2, &[ I32, I32 ], None )?,
+            Instruction::I32AtomicStore8u( ref memarg ) => self.atomic( memarg, 0, &[ I32, I32 ], None )?,
+            Instruction::I32AtomicStore16u( ref memarg ) => self.atomic( memarg, 1, &[ I32, I32 ], None )?,
+            Instruction::I64AtomicStore( ref memarg ) => self.atomic( memarg, 3, &[ I32, I64 ], None )?,
+            Instruction::I64AtomicStore8u( ref memarg ) => self.atomic( memarg, 0, &[ I32, I64 ], None )?,
+            Instruction::I64AtomicStore16u( ref memarg ) => self.atomic( memarg, 1, &[ I32, I64 ], None )?,
+            Instruction::I64AtomicStore32u( ref memarg ) => self.atomic( memarg, 2, &[ I32, I64 ], None )?,
+
+            Instruction::I32AtomicRmwAdd( ref memarg ) | Instruction::I32AtomicRmwSub( ref memarg ) |
+            Instruction::I32AtomicRmwAnd( ref memarg ) | Instruction::I32AtomicRmwOr( ref memarg ) |
+            Instruction::I32AtomicRmwXor( ref memarg ) | Instruction::I32AtomicRmwXchg( ref memarg ) => self.atomic( memarg, 2, &[ I32, I32 ], Some( I32 ) )?,
+
+            Instruction::I32AtomicRmwAdd8u( ref memarg ) | Instruction::I32AtomicRmwSub8u( ref memarg ) |
+            Instruction::I32AtomicRmwAnd8u( ref memarg ) | Instruction::I32AtomicRmwOr8u( ref memarg ) |
+            Instruction::I32AtomicRmwXor8u( ref memarg ) | Instruction::I32AtomicRmwXchg8u( ref memarg ) => self.atomic( memarg, 0, &[ I32, I32 ], Some( I32 ) )?,
+
+            Instruction::I32AtomicRmwAdd16u( ref memarg ) | Instruction::I32AtomicRmwSub16u( ref memarg ) |
+            Instruction::I32AtomicRmwAnd16u( ref memarg ) | Instruction::I32AtomicRmwOr16u( ref memarg ) |
+            Instruction::I32AtomicRmwXor16u( ref memarg ) | Instruction::I32AtomicRmwXchg16u( ref memarg ) => self.atomic( memarg, 1, &[ I32, I32 ], Some( I32 ) )?,
+
+            Instruction::I64AtomicRmwAdd( ref memarg ) | Instruction::I64AtomicRmwSub( ref memarg ) |
+            Instruction::I64AtomicRmwAnd( ref memarg ) | Instruction::I64AtomicRmwOr( ref memarg ) |
+            Instruction::I64AtomicRmwXor( ref memarg ) | Instruction::I64AtomicRmwXchg( ref memarg ) => self.atomic( memarg, 3, &[ I32, I64 ], Some( I64 ) )?,
+
+            Instruction::I64AtomicRmwAdd8u( ref memarg ) | Instruction::I64AtomicRmwSub8u( ref memarg ) |
+            Instruction::I64AtomicRmwAnd8u( ref memarg ) | Instruction::I64AtomicRmwOr8u( ref memarg ) |
+            Instruction::I64AtomicRmwXor8u( ref memarg ) | Instruction::I64AtomicRmwXchg8u( ref memarg ) => self.atomic( memarg, 0, &[ I32, I64 ], Some( I64 ) )?,
+
+            Instruction::I64AtomicRmwAdd16u( ref memarg ) | Instruction::I64AtomicRmwSub16u( ref memarg ) |
+            Instruction::I64AtomicRmwAnd16u( ref memarg ) | Instruction::I64AtomicRmwOr16u( ref memarg ) |
+            Instruction::I64AtomicRmwXor16u( ref memarg ) | Instruction::I64AtomicRmwXchg16u( ref memarg ) => self.atomic( memarg, 1, &[ I32, I64 ], Some( I64 ) )?,
+
+            Instruction::I64AtomicRmwAdd32u( ref memarg ) | Instruction::I64AtomicRmwSub32u( ref memarg ) |
+            Instruction::I64AtomicRmwAnd32u( ref memarg ) | Instruction::I64AtomicRmwOr32u( ref memarg ) |
+            Instruction::I64AtomicRmwXor32u( ref memarg ) | Instruction::I64AtomicRmwXchg32u( ref memarg ) => self.atomic( memarg, 2, &[ I32, I64 ], Some( I64 ) )?,
+
+            Instruction::I32AtomicRmwCmpxchg( ref memarg ) => self.atomic( memarg, 2, &[ I32, I32, I32 ], Some( I32 ) )?,
+            Instruction::I32AtomicRmwCmpxchg8u( ref memarg ) => self.atomic( memarg, 0, &[ I32, I32, I32 ], Some( I32 ) )?,
+            Instruction::I32AtomicRmwCmpxchg16u( ref memarg ) => self.atomic( memarg, 1, &[ I32, I32, I32 ], Some( I32 ) )?,
+            Instruction::I64AtomicRmwCmpxchg( ref memarg ) => self.atomic( memarg, 3, &[ I32, I64, I64 ], Some( I64 ) )?,
+            Instruction::I64AtomicRmwCmpxchg8u( ref memarg ) => self.atomic( memarg, 0, &[ I32, I64, I64 ], Some( I64 ) )?,
+            Instruction::I64AtomicRmwCmpxchg16u( ref memarg ) => self.atomic( memarg, 1, &[ I32, I64, I64 ], Some( I64 ) )?,
+            Instruction::I64AtomicRmwCmpxchg32u( ref memarg ) => self.atomic( memarg, 2, &[ I32, I64, I64 ], Some( I64 ) )?,
+
+            Instruction::MemoryInit( segment_index ) => {
+                self.module.data_segment( segment_index )?;
+                self.pop_expecting( I32 )?;
+                self.pop_expecting( I32 )?;
+                self.pop_expecting( I32 )?;
+            },
+            Instruction::MemoryDrop( segment_index ) => self.module.data_segment( segment_index )?,
+            Instruction::MemoryCopy | Instruction::MemoryFill => {
+                self.module.require_memory()?;
+                self.pop_expecting( I32 )?;
+                self.pop_expecting( I32 )?;
+                self.pop_expecting( I32 )?;
+            },
+            Instruction::TableInit( segment_index ) => {
+                self.module.element_segment( segment_index )?;
+                self.pop_expecting( I32 )?;
+                self.pop_expecting( I32 )?;
+                self.pop_expecting( I32 )?;
+            },
+            Instruction::TableDrop( segment_index ) => self.module.element_segment( segment_index )?,
+            Instruction::TableCopy => {
+                self.module.require_table()?;
+                self.pop_expecting( I32 )?;
+                self.pop_expecting( I32 )?;
+                self.pop_expecting( I32 )?;
+            },
+
+            // That's only SIMD, which isn't standardized yet.
+            _ => return Err( "SIMD instructions are not supported; use `--skip-validation` to build this module".to_owned() )
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_function( module: &ModuleInfo, ty: &FnType, body: &pw::FuncBody ) -> Result< (), String > {
+    let mut locals = ty.params.clone();
+    for local in body.locals() {
+        for _ in 0..local.count() {
+            locals.push( local.value_type() );
+        }
+    }
+
+    let mut validator = FunctionValidator {
+        module,
+        locals,
+        return_type: ty.return_type,
+        operands: Vec::new(),
+        frames: vec![ Frame {
+            is_loop: false,
+            is_if: false,
+            result: ty.return_type,
+            height: 0,
+            unreachable: false
+        }]
+    };
+
+    let instructions = body.code().elements();
+    for (position, instruction) in instructions.iter().enumerate() {
+        if validator.frames.is_empty() {
+            return Err( format!( "unexpected instruction after the final `end` at instruction #{} ({:?})", position, instruction ) );
+        }
+
+        if let Err( error ) = validator.step( instruction ) {
+            return Err( format!( "{} at instruction #{} ({:?})", error, position, instruction ) );
+        }
+    }
+
+    if !validator.frames.is_empty() {
+        return Err( "the function body is missing its final `end`".to_owned() );
+    }
+
+    Ok(())
+}
+
+fn validate_constant_expression( module: &ModuleInfo, expression: Option< &pw::InitExpr >, expected: ValueType, what: &str ) -> Result< (), String > {
+    let code = match expression {
+        Some( expression ) => expression.code(),
+        None => return Err( format!( "{} is missing its initializer", what ) )
+    };
+
+    let ty = match code {
+        &[ Instruction::I32Const( _ ), Instruction::End ] => ValueType::I32,
+        &[ Instruction::I64Const( _ ), Instruction::End ] => ValueType::I64,
+        &[ Instruction::F32Const( _ ), Instruction::End ] => ValueType::F32,
+        &[ Instruction::F64Const( _ ), Instruction::End ] => ValueType::F64,
+        &[ Instruction::GetGlobal( global_index ), Instruction::End ] => {
+            if global_index as usize >= module.imported_global_count {
+                return Err( format!( "{} can only refer to imported globals; found a reference to global #{}", what, global_index ) );
+            }
+
+            module.global( global_index )?.0
+        },
+        _ => return Err( format!( "{} is not a valid constant expression: {:?}", what, code ) )
+    };
+
+    if ty != expected {
+        return Err( format!( "{} has a type mismatch: expected {:?}, found {:?}", what, expected, ty ) );
+    }
+
+    Ok(())
+}
+
+fn function_names( module: &pw::Module ) -> HashMap< u32, String > {
+    fn parse( p: &[u8], names: &mut HashMap< u32, String > ) -> Result< (), pw::Error > {
+        for subsection in wasm_sections::subsections( p ) {
+            let (kind, mut payload) = subsection?;
+            if kind != 1 {
+                continue;
+            }
+
+            let count = u32::from( pw::VarUint32::deserialize( &mut payload )? );
+            for _ in 0..count {
+                let index = u32::from( pw::VarUint32::deserialize( &mut payload )? );
+                let name_length = u32::from( pw::VarUint32::deserialize( &mut payload )? ) as usize;
+                if name_length > payload.len() {
+                    return Err( pw::Error::UnexpectedEof );
+                }
+
+                let (name, rest) = payload.split_at( name_length );
+                payload = rest;
+                names.insert( index, String::from_utf8_lossy( name ).into_owned() );
+            }
+        }
+
+        Ok(())
+    }
+
+    let mut names = HashMap::new();
+    for section in module.sections() {
+        if let &pw::Section::Custom( ref section ) = section {
+            if section.name() == "name" {
+                let _ = parse( section.payload(), &mut names );
+            }
+        }
+    }
+
+    names
+}
+
+/// Checks whether the given module is structurally valid and
+/// whether the code of every function type checks.
+pub fn validate( module: &pw::Module ) -> Result< (), ValidationError > {
+    let types: Vec< _ > = module.type_section().map( |section| {
+        section.types().iter().map( |ty| {
+            let &pw::Type::Function( ref ty ) = ty;
+            FnType {
+                params: ty.params().to_vec(),
+                return_type: ty.return_type()
+            }
+        }).collect()
+    }).unwrap_or_default();
+
+    let mut info = ModuleInfo {
+        types,
+        functions: Vec::new(),
+        table_count: 0,
+        memory_count: 0,
+        globals: Vec::new(),
+        imported_global_count: 0,
+        element_segment_count: module.elements_section().map( |section| section.entries().len() ).unwrap_or( 0 ),
+        data_segment_count: module.data_section().map( |section| section.entries().len() ).unwrap_or( 0 )
+    };
+
+    if let Some( section ) = module.import_section() {
+        for entry in section.entries() {
+            match *entry.external() {
+                pw::External::Function( type_index ) => {
+                    info.fn_type( type_index ).map_err( |error| format!( "import `{}.{}`: {}", entry.module(), entry.field(), error ) )?;
+                    info.functions.push( type_index );
+                },
+                pw::External::Table( _ ) => info.table_count += 1,
+                pw::External::Memory( _ ) => info.memory_count += 1,
+                pw::External::Global( ref global_type ) => {
+                    info.globals.push( (global_type.content_type(), global_type.is_mutable()) );
+                    info.imported_global_count += 1;
+                }
+            }
+        }
+    }
+
+    let imported_function_count = info.functions.len();
+    if let Some( section ) = module.function_section() {
+        for entry in section.entries() {
+            info.fn_type( entry.type_ref() )?;
+            info.functions.push( entry.type_ref() );
+        }
+    }
+
+    info.table_count += module.table_section().map( |section| section.entries().len() ).unwrap_or( 0 );
+    info.memory_count += module.memory_section().map( |section| section.entries().len() ).unwrap_or( 0 );
+
+    if info.table_count > 1 {
+        return Err( format!( "multiple tables are not supported; found {}", info.table_count ).into() );
+    }
+
+    if info.memory_count > 1 {
+        return Err( format!( "multiple memories are not supported; found {}", info.memory_count ).into() );
+    }
+
+    if let Some( section ) = module.global_section() {
+        for (index, entry) in section.entries().iter().enumerate() {
+            let ty = entry.global_type().content_type();
+            let what = format!( "global #{}", info.imported_global_count + index );
+            validate_constant_expression( &info, Some( entry.init_expr() ), ty, &what )?;
+            info.globals.push( (ty, entry.global_type().is_mutable()) );
+        }
+    }
+
+    let bodies = module.code_section().map( |section| section.bodies() ).unwrap_or( &[] );
+    if bodies.len() != info.functions.len() - imported_function_count {
+        return Err( format!(
+            "the number of function bodies ({}) doesn't match the number of declared functions ({})",
+            bodies.len(),
+            info.functions.len() - imported_function_count
+        ).into() );
+    }
+
+    if let Some( section ) = module.export_section() {
+        let mut seen = HashSet::new();
+        for entry in section.entries() {
+            if !seen.insert( entry.field() ) {
+                return Err( format!( "duplicate export: `{}`", entry.field() ).into() );
+            }
+
+            let (kind, index, count) = match *entry.internal() {
+                pw::Internal::Function( index ) => ("function", index, info.functions.len()),
+                pw::Internal::Table( index ) => ("table", index, info.table_count),
+                pw::Internal::Memory( index ) => ("memory", index, info.memory_count),
+                pw::Internal::Global( index ) => ("global", index, info.globals.len())
+            };
+
+            if index as usize >= count {
+                return Err( format!( "export `{}` refers to a non-existent {} #{}", entry.field(), kind, index ).into() );
+            }
+        }
+    }
+
+    if let Some( start ) = module.start_section() {
+        let ty = info.function_type( start ).map_err( |error| format!( "start function: {}", error ) )?;
+        if !ty.params.is_empty() || ty.return_type.is_some() {
+            return Err( format!( "the start function #{} must not take any arguments nor return anything", start ).into() );
+        }
+    }
+
+    if let Some( section ) = module.elements_section() {
+        for (index, entry) in section.entries().iter().enumerate() {
+            let what = format!( "element segment #{}", index );
+            if entry.index() as usize >= info.table_count {
+                return Err( format!( "{} refers to a non-existent table #{}", what, entry.index() ).into() );
+            }
+
+            if !entry.passive() {
+                validate_constant_expression( &info, entry.offset().as_ref(), ValueType::I32, &what )?;
+            }
+
+            for &member in entry.members() {
+                if member as usize >= info.functions.len() {
+                    return Err( format!( "{} refers to a non-existent function #{}", what, member ).into() );
+                }
+            }
+        }
+    }
+
+    if let Some( section ) = module.data_section() {
+        for (index, entry) in section.entries().iter().enumerate() {
+            let what = format!( "data segment #{}", index );
+            if entry.index() as usize >= info.memory_count {
+                return Err( format!( "{} refers to a non-existent memory #{}", what, entry.index() ).into() );
+            }
+
+            // Passive segments are only copied into the memory by `memory.init`.
+            if !entry.passive() {
+                validate_constant_expression( &info, entry.offset().as_ref(), ValueType::I32, &what )?;
+            }
+        }
+    }
+
+    for (body_index, body) in bodies.iter().enumerate() {
+        let function_index = (imported_function_count + body_index) as u32;
+        let ty = info.function_type( function_index )?;
+        if let Err( message ) = validate_function( &info, ty, body ) {
+            let name = function_names( module ).remove( &function_index );
+            return Err( ValidationError {
+                function: Some( (function_index, name) ),
+                message
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn module_with_function( return_type: Option< ValueType >, instructions: Vec< Instruction > ) -> pw::Module {
+    use wasm_context::{Context, Export, FnTy, FunctionKind};
+
+    let mut ctx = Context::new();
+    let type_index = ctx.get_or_add_fn_type( FnTy { params: vec![ ValueType::I32 ], return_type } );
+    ctx.add_function( FunctionKind::Definition {
+        export: Export::some( "main".to_owned() ),
+        type_index,
+        name: Some( "main".to_owned() ),
+        locals: vec![],
+        instructions
+    });
+
+    ctx.into_module()
+}
+
+#[test]
+fn test_valid_function() {
+    let module = module_with_function( Some( ValueType::I32 ), vec![
+        Instruction::GetLocal( 0 ),
+        Instruction::If( BlockType::Value( ValueType::I32 ) ),
+        Instruction::I32Const( 1 ),
+        Instruction::Else,
+        Instruction::Unreachable,
+        Instruction::End,
+        Instruction::End
+    ]);
+
+    assert!( validate( &module ).is_ok() );
+}
+
+#[test]
+fn test_type_mismatch() {
+    let module = module_with_function( Some( ValueType::I32 ), vec![
+        Instruction::I64Const( 1 ),
+        Instruction::End
+    ]);
+
+    let error = validate( &module ).unwrap_err();
+    assert_eq!( error.function, Some( (0, Some( "main".to_owned() )) ) );
+    assert_eq!( error.message, "type mismatch: expected I32, found I64 at instruction #1 (End)" );
+}
+
+#[test]
+fn test_local_out_of_bounds() {
+    let module = module_with_function( None, vec![
+        Instruction::GetLocal( 1 ),
+        Instruction::Drop,
+        Instruction::End
+    ]);
+
+    let error = validate( &module ).unwrap_err();
+    assert_eq!( error.to_string(), "function #0 (`main`): local index out of bounds: 1 at instruction #0 (GetLocal(1))" );
+}
+
+#[cfg(test)]
+fn with_memory( mut module: pw::Module, data: Vec< pw::DataSegment > ) -> pw::Module {
+    module.sections_mut().push( pw::Section::Memory( pw::MemorySection::with_entries( vec![ pw::MemoryType::new( 1, Some( 1 ), true ) ] ) ) );
+    module.sections_mut().push( pw::Section::Data( pw::DataSection::with_entries( data ) ) );
+    module
+}
+
+#[test]
+fn test_atomics() {
+    let memarg = |align| MemArg { align, offset: 0 };
+    let instructions = vec![
+        Instruction::GetLocal( 0 ),
+        Instruction::I32Const( 1 ),
+        Instruction::I32AtomicRmwAdd( memarg( 2 ) ),
+        Instruction::Drop,
+        Instruction::GetLocal( 0 ),
+        Instruction::I32Const( 0 ),
+        Instruction::I64Const( -1 ),
+        Instruction::I32AtomicWait( memarg( 2 ) ),
+        Instruction::Drop,
+        Instruction::GetLocal( 0 ),
+        Instruction::I64Const( 1 ),
+        Instruction::I64Const( 2 ),
+        Instruction::I64AtomicRmwCmpxchg( memarg( 3 ) ),
+        Instruction::I32WrapI64,
+        Instruction::I32Extend8S,
+        Instruction::End
+    ];
+
+    let module = with_memory( module_with_function( Some( ValueType::I32 ), instructions.clone() ), Vec::new() );
+    assert!( validate( &module ).is_ok() );
+
+    // Without a memory.
+    let module = module_with_function( Some( ValueType::I32 ), instructions );
+    assert!( validate( &module ).is_err() );
+
+    let module = with_memory( module_with_function( None, vec![
+        Instruction::GetLocal( 0 ),
+        Instruction::I32Const( 1 ),
+        Instruction::I32AtomicStore( memarg( 0 ) ),
+        Instruction::End
+    ]), Vec::new() );
+
+    let error = validate( &module ).unwrap_err();
+    assert!( error.message.starts_with( "atomic accesses must be naturally aligned" ) );
+
+    let module = with_memory( module_with_function( Some( ValueType::I64 ), vec![
+        Instruction::GetLocal( 0 ),
+        Instruction::I32AtomicLoad( memarg( 2 ) ),
+        Instruction::End
+    ]), Vec::new() );
+
+    let error = validate( &module ).unwrap_err();
+    assert_eq!( error.message, "type mismatch: expected I64, found I32 at instruction #2 (End)" );
+}
+
+#[test]
+fn test_passive_data_segments() {
+    let passive = || vec![ pw::DataSegment::new( 0, None, vec![ 1, 2, 3 ], true ) ];
+    let bulk_memory = |segment_index| vec![
+        Instruction::GetLocal( 0 ),
+        Instruction::I32Const( 0 ),
+        Instruction::I32Const( 3 ),
+        Instruction::MemoryInit( segment_index ),
+        Instruction::MemoryDrop( segment_index ),
+        Instruction::End
+    ];
+
+    let module = with_memory( module_with_function( None, bulk_memory( 0 ) ), passive() );
+    assert!( validate( &module ).is_ok() );
+
+    let module = with_memory( module_with_function( None, bulk_memory( 1 ) ), passive() );
+    let error = validate( &module ).unwrap_err();
+    assert_eq!( error.message, "data segment index out of bounds: 1 at instruction #3 (MemoryInit(1))" );
+
+    // An active segment still needs its offset.
+    let module = with_memory( module_with_function( None, vec![ Instruction::End ] ), vec![ pw::DataSegment::new( 0, None, vec![ 1 ], false ) ] );
+    assert_eq!( validate( &module ).unwrap_err().message, "data segment #0 is missing its initializer" );
+}
+
+#[test]
+fn test_simd_is_rejected() {
+    let module = with_memory( module_with_function( None, vec![
+        Instruction::V128Const( Box::new( [ 0; 16 ] ) ),
+        Instruction::Drop,
+        Instruction::End
+    ]), Vec::new() );
+
+    let error = validate( &module ).unwrap_err();
+    assert!( error.message.starts_with( "SIMD instructions are not supported" ) );
+}